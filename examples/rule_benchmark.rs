@@ -0,0 +1,77 @@
+use std::fs;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use core_data::models::logic::*;
+use core_data::models::message::*;
+use core_data::models::payload::*;
+use serde_json::{json, Value};
+
+const MESSAGES: usize = 20_000;
+const THREADS: usize = 4;
+
+fn config() -> Vec<EnrichmentConfig> {
+    serde_json::from_value(json!([
+        {"field": "data.metadata.reference", "rule": {"lower": {"var": "data.document.FIToFICstmrCdtTrf.GrpHdr.MsgId"}}, "description": null},
+        {"field": "data.metadata.amount_band", "rule": {"if": [{"==": [{"amount_compare": [{"var": "input.amount"}, 10000]}, 1]}, "HIGH", "LOW"]}, "description": null},
+        {"field": "data.metadata.fee", "rule": {"decimal_round": [{"decimal_add": [{"var": "input.amount"}, "0.35"]}, 2]}, "description": null},
+        {"field": "data.metadata.channel", "rule": {"cat": ["SWIFT-", {"var": "tenant"}]}, "description": null, "condition": {"!!": {"var": "data.document"}}},
+        {"field": "data.metadata.tags", "rule": {"pad_left": [{"var": "input.branch"}, 6, "0"]}, "description": null, "operation": {"type": "append"}}
+    ])).unwrap()
+}
+
+fn run(message: &Message, count: usize, mut enrich: impl FnMut(&mut Message)) -> Duration {
+    let start = Instant::now();
+    for _ in 0..count {
+        let mut message = message.clone();
+        enrich(&mut message);
+    }
+    start.elapsed()
+}
+
+fn report(label: &str, count: usize, duration: Duration) {
+    println!("{:<32} {:>10.0} messages/s ({:?})", label, count as f64 / duration.as_secs_f64(), duration);
+}
+
+fn main() {
+    let xml_bytes = fs::read("examples/pacs008_001_07_cct_outgoing.xml")
+        .expect("Failed to read XML file");
+    let payload = Payload::new_inline(Some(xml_bytes), PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+    let mut message = Message::new(payload, "banking".to_string(), "pacs.008.001.07".to_string(), "RuleBenchmark".to_string(), "ISOOutgoing".to_string(), None);
+    message.parse(None, "RuleBenchmark".to_string(), "ISOOutgoing".to_string())
+        .expect("Failed to parse XML message");
+    let input: Value = json!({"amount": "12500.10", "branch": "42"});
+
+    // Rules compiled on every call by the shared evaluator
+    let interpreted = run(&message, MESSAGES, |message| {
+        message.enrich_with(RuleEvaluator::shared(), config(), input.clone(), None, "RuleBenchmark".to_string(), "Enrich".to_string())
+            .expect("Failed to enrich message");
+    });
+
+    // Rules compiled once and reused
+    let compiled = Arc::new(CompiledEnrichment::new(RuleEvaluator::shared(), config()).expect("Invalid rules"));
+    let single = run(&message, MESSAGES, |message| {
+        message.enrich_compiled(&compiled, input.clone(), None, "RuleBenchmark".to_string(), "Enrich".to_string())
+            .expect("Failed to enrich message");
+    });
+
+    // The same compiled rules shared by several threads
+    let start = Instant::now();
+    let workers: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let (compiled, message, input) = (Arc::clone(&compiled), message.clone(), input.clone());
+            thread::spawn(move || run(&message, MESSAGES / THREADS, |message| {
+                message.enrich_compiled(&compiled, input.clone(), None, "RuleBenchmark".to_string(), "Enrich".to_string())
+                    .expect("Failed to enrich message");
+            }))
+        })
+        .collect();
+    workers.into_iter().for_each(|worker| { worker.join().unwrap(); });
+    let threaded = start.elapsed();
+
+    println!("\nEnrichment throughput, {} messages:", MESSAGES);
+    report("Compiled per call", MESSAGES, interpreted);
+    report("Compiled once", MESSAGES, single);
+    report(&format!("Compiled once, {} threads", THREADS), MESSAGES, threaded);
+    println!("Speedup: {:.1}x", interpreted.as_secs_f64() / single.as_secs_f64());
+}
@@ -35,11 +35,13 @@ fn main() {
             field: "data.metadata.processing_date".to_string(),
             rule: json!({"var": ["processing_date"]}),
             description: Some("Add processing date".to_string()),
+            ..Default::default()
         },
         EnrichmentConfig {
             field: "data.metadata.transaction_type".to_string(),
             rule: json!({"var": ["transaction_type"]}),
             description: Some("Add transaction type".to_string()),
+            ..Default::default()
         },
     ];
 
@@ -0,0 +1,68 @@
+use serde_json::Value;
+
+use crate::models::errors::{error, FunctionResponseError};
+
+/// Minor unit digits of an ISO 4217 currency, e.g. 0 for JPY and 3 for KWD.
+pub(crate) fn currency_exponent(currency: &str) -> u32 {
+    match currency {
+        "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF" | "UGX" | "UYI" | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+        "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+        "CLF" | "UYW" => 4,
+        _ => 2,
+    }
+}
+
+/// `amount`, a JSON number or decimal string, in minor units of `currency`: 250.75 EUR is 25075.
+/// Amounts are read from their decimal text, so sums are exact.
+pub(crate) fn minor_units(amount: &Value, currency: &str) -> Result<i128, FunctionResponseError> {
+    let text = match amount {
+        Value::Number(number) => number.to_string(),
+        Value::String(text) => text.trim().to_string(),
+        _ => return Err(error("Amount", format!("{} is not an amount", amount))),
+    };
+    let invalid = || error("Amount", format!("{} is not a valid {} amount", text, currency));
+    let (mantissa, exponent) = match text.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i32>().map_err(|_| invalid())?),
+        None => (text.as_str(), 0),
+    };
+    let (negative, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => (true, mantissa),
+        None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = format!("{}{}", whole, fraction);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    // Shift the decimal point to the currency minor unit, which must not drop any digits
+    let shift = currency_exponent(currency) as i32 + exponent - fraction.len() as i32;
+    let mut units: i128 = digits.parse().map_err(|_| invalid())?;
+    if shift >= 0 {
+        units = units.checked_mul(10i128.checked_pow(shift as u32).ok_or_else(invalid)?).ok_or_else(invalid)?;
+    } else {
+        let divisor = 10i128.checked_pow(shift.unsigned_abs()).unwrap_or(i128::MAX);
+        if units % divisor != 0 {
+            return Err(error("Amount", format!("{} has more decimals than {} allows", text, currency)));
+        }
+        units /= divisor;
+    }
+    Ok(if negative { -units } else { units })
+}
+
+/// Decimal text of `units` minor units of `currency`, e.g. "250.75".
+pub(crate) fn format_amount(units: i128, currency: &str) -> String {
+    let exponent = currency_exponent(currency) as usize;
+    let digits = format!("{:0>width$}", units.unsigned_abs(), width = exponent + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - exponent);
+    let sign = if units < 0 { "-" } else { "" };
+    match exponent {
+        0 => format!("{}{}", sign, whole),
+        _ => format!("{}{}.{}", sign, whole, fraction),
+    }
+}
+
+/// `units` minor units of `currency` as the JSON number of an ISO20022 amount.
+pub(crate) fn amount_value(units: i128, currency: &str) -> Value {
+    serde_json::from_str(&format_amount(units, currency)).unwrap_or(Value::Null)
+}
@@ -2,6 +2,8 @@ use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 use sonyflake::Sonyflake;
 
+use crate::models::trace::EvaluationTrace;
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct AuditLog {
     #[serde(rename = "id")]
@@ -28,6 +30,10 @@ pub struct AuditLog {
     version: Box<str>,
 
     changes: Box<[ChangeLog]>,
+
+    /// Rule evaluations behind the entry, recorded in trace mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trace: Option<Box<EvaluationTrace>>,
 }
 
 impl AuditLog {
@@ -67,6 +73,19 @@ impl AuditLog {
         &self.changes
     }
 
+    pub fn trace(&self) -> Option<&EvaluationTrace> {
+        self.trace.as_deref()
+    }
+
+    pub(crate) fn trace_mut(&mut self) -> Option<&mut EvaluationTrace> {
+        self.trace.as_deref_mut()
+    }
+
+    pub fn with_trace(mut self, trace: Option<EvaluationTrace>) -> Self {
+        self.trace = trace.map(Box::new);
+        self
+    }
+
     pub fn start_time(&self) -> &OffsetDateTime {
         &self.start_time
     }
@@ -91,6 +110,7 @@ impl AuditLog {
             instance: String::new().into_boxed_str(),
             version: String::new().into_boxed_str(),
             changes: changes.into_boxed_slice(),
+            trace: None,
         }
     }
 }
@@ -126,8 +146,8 @@ impl ChangeLog {
     pub fn new(field: String, reason: String, old_value: Option<serde_json::Value>, new_value: Option<serde_json::Value>) -> Self {
         ChangeLog {
             field: field.into_boxed_str(),
-            old_value: old_value,
-            new_value: new_value,
+            old_value,
+            new_value,
             reason: reason.into_boxed_str(),
         }
     }
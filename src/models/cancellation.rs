@@ -0,0 +1,283 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+
+use crate::models::auditlog::*;
+use crate::models::errors::{error, FunctionResponseError};
+use crate::models::message::{Message, MessageStatus};
+use crate::models::returns::Pacs004Config;
+use crate::models::document::*;
+use crate::models::path::*;
+
+const STATUS_FIELD: &str = "progress.status";
+
+/// Identifiers of one original transaction targeted by a camt.056 cancellation request.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CancellationReference {
+    pub cancellation_id: Option<String>,
+
+    pub original_message_id: Option<String>,
+
+    pub original_end_to_end_id: Option<String>,
+
+    pub original_uetr: Option<String>,
+
+    pub reason_code: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum CancellationResolution {
+    /// Accept the cancellation and return the funds with a pacs.004
+    Accept(Pacs004Config),
+    /// Refuse the cancellation with a camt.029 resolution of investigation
+    Reject {
+        reason_code: String,
+        additional_info: Option<String>,
+    },
+}
+
+fn text(value: &Value, path: &str) -> Option<String> {
+    value_at(value, path).and_then(|v| v.as_str()).map(|s| s.to_string())
+}
+
+impl CancellationReference {
+    /// Whether `transfer` (a pacs.008 `FIToFICstmrCdtTrf`) holds the referenced transaction.
+    /// The UETR is decisive when both sides carry one, otherwise the original message id
+    /// and end-to-end id must both match.
+    fn matches(&self, transfer: &Value) -> Option<usize> {
+        let message_id = text(transfer, "GrpHdr.MsgId");
+        transfer["CdtTrfTxInf"].as_array()?.iter().position(|transaction| {
+            let uetr = text(transaction, "PmtId.UETR");
+            if let (Some(expected), Some(actual)) = (&self.original_uetr, &uetr) {
+                return expected.eq_ignore_ascii_case(actual);
+            }
+            self.original_message_id.is_some()
+                && self.original_end_to_end_id.is_some()
+                && self.original_message_id == message_id
+                && self.original_end_to_end_id == text(transaction, "PmtId.EndToEndId")
+        })
+    }
+}
+
+impl Message {
+    /// Transactions referenced by a parsed camt.056 FI to FI payment cancellation request.
+    pub fn cancellation_references(&self) -> Result<Vec<CancellationReference>, FunctionResponseError> {
+        let request = value_at(self.data(), "document.FIToFIPmtCxlReq")
+            .ok_or_else(|| error("Cancellation", "Message is not a parsed camt.056".to_string()))?;
+
+        let mut references = Vec::new();
+        for underlying in request["Undrlyg"].as_array().into_iter().flatten() {
+            let group_message_id = text(underlying, "OrgnlGrpInfAndCxl.OrgnlMsgId");
+            for transaction in underlying["TxInf"].as_array().into_iter().flatten() {
+                references.push(CancellationReference {
+                    cancellation_id: text(transaction, "CxlId"),
+                    original_message_id: text(transaction, "OrgnlGrpInf.OrgnlMsgId").or_else(|| group_message_id.clone()),
+                    original_end_to_end_id: text(transaction, "OrgnlEndToEndId"),
+                    original_uetr: text(transaction, "OrgnlUETR"),
+                    reason_code: text(transaction, "CxlRsnInf.0.Rsn.Cd"),
+                });
+            }
+        }
+        Ok(references)
+    }
+
+    /// Finds the original pacs.008 targeted by this camt.056 among `originals`.
+    pub fn correlate_cancellation<'a>(&self, originals: &'a mut [Message]) -> Result<&'a mut Message, FunctionResponseError> {
+        let references = self.cancellation_references()?;
+        let matches: Vec<usize> = originals.iter()
+            .enumerate()
+            .filter(|(_, original)| {
+                value_at(original.data(), "document.FIToFICstmrCdtTrf")
+                    .is_some_and(|transfer| references.iter().any(|r| r.matches(transfer).is_some()))
+            })
+            .map(|(index, _)| index)
+            .collect();
+
+        match matches.as_slice() {
+            [index] => Ok(&mut originals[*index]),
+            [] => Err(error("Cancellation", "No original pacs.008 matches the cancellation request".to_string())),
+            _ => Err(error("Cancellation", format!("{} original messages match the cancellation request", matches.len()))),
+        }
+    }
+
+    /// Moves this original pacs.008 into `CancellationPending` on behalf of a camt.056 request.
+    pub fn request_cancellation(&mut self, request: &Message, description: Option<String>, workflow: String, task: String) -> Result<(), FunctionResponseError> {
+        let start_time = OffsetDateTime::now_utc();
+        if self.progress().status == MessageStatus::CancellationPending {
+            return Err(error("Cancellation", "Cancellation already pending".to_string()));
+        }
+        let reason = request.cancellation_references()?
+            .into_iter()
+            .find_map(|r| r.reason_code)
+            .unwrap_or_else(|| "unspecified".to_string());
+
+        let previous = serde_json::to_value(&self.progress().status).unwrap();
+        self.set_status(MessageStatus::CancellationPending);
+        self.push_audit(AuditLog::new(
+            workflow,
+            task,
+            start_time,
+            description.unwrap_or_else(|| "Cancellation requested".to_string()),
+            vec![ChangeLog::new(
+                STATUS_FIELD.to_string(),
+                format!("camt.056 {} with reason {}", request.id(), reason),
+                Some(previous),
+                Some(json!(MessageStatus::CancellationPending))
+            )]
+        ));
+        Ok(())
+    }
+
+    /// Resolves a pending cancellation of this original pacs.008. Accepting returns the funds
+    /// with a pacs.004 child of the original, and cancels the original when every transaction is
+    /// returned; rejecting answers the camt.056 with a camt.029 child of the request. Otherwise
+    /// the status held before the request is restored.
+    pub fn resolve_cancellation(&mut self, request: &mut Message, resolution: &CancellationResolution, description: Option<String>, workflow: String, task: String) -> Result<Message, FunctionResponseError> {
+        let start_time = OffsetDateTime::now_utc();
+        if self.progress().status != MessageStatus::CancellationPending {
+            return Err(error("Cancellation", "No cancellation pending".to_string()));
+        }
+
+        let (resolution_message, status, reason) = match resolution {
+            CancellationResolution::Accept(config) => {
+                let transfer = value_at(self.data(), "document.FIToFICstmrCdtTrf")
+                    .ok_or_else(|| error("Cancellation", "Message is not a parsed pacs.008".to_string()))?;
+                let count = transfer["CdtTrfTxInf"].as_array().map_or(1, |transactions| transactions.len());
+                let mut indices: Vec<usize> = request.cancellation_references()?
+                    .iter()
+                    .filter_map(|reference| reference.matches(transfer))
+                    .collect();
+                indices.sort_unstable();
+                indices.dedup();
+                if indices.is_empty() {
+                    return Err(error("Cancellation", "No transaction of the original matches the cancellation request".to_string()));
+                }
+                let payment_return = self.return_transactions(config, &indices, None, workflow.clone(), task.clone())?;
+                let returned = value_at(payment_return.data(), "document.PmtRtr.TxInf")
+                    .and_then(Value::as_array)
+                    .map_or(0, |transactions| transactions.len());
+                // A message is cancelled once all of its transactions are, until then it keeps its status
+                match returned < count {
+                    true => {
+                        let reason = format!("{} of {} transactions cancelled by {}", returned, count, payment_return.id());
+                        (payment_return, self.status_before_cancellation(), reason)
+                    }
+                    false => {
+                        let reason = format!("Cancellation resolved by {}", payment_return.id());
+                        (payment_return, MessageStatus::Cancelled, reason)
+                    }
+                }
+            }
+            CancellationResolution::Reject { reason_code, additional_info } => {
+                let resolution = self.camt029(request, reason_code, additional_info.as_ref(), workflow.clone(), task.clone())?;
+                let reason = format!("Cancellation resolved by {}", resolution.id());
+                (resolution, self.status_before_cancellation(), reason)
+            }
+        };
+
+        let change = ChangeLog::new(
+            STATUS_FIELD.to_string(),
+            reason,
+            Some(json!(MessageStatus::CancellationPending)),
+            Some(json!(status))
+        );
+        self.set_status(status);
+        self.push_audit(AuditLog::new(
+            workflow.clone(),
+            task.clone(),
+            start_time,
+            description.clone().unwrap_or_else(|| "Cancellation resolved".to_string()),
+            vec![change]
+        ));
+        request.push_audit(AuditLog::new(
+            workflow,
+            task,
+            start_time,
+            description.unwrap_or_else(|| "Cancellation resolved".to_string()),
+            vec![ChangeLog::new(
+                "children".to_string(),
+                format!("Cancellation of {} resolved", self.id()),
+                None,
+                Some(json!(resolution_message.id().to_string()))
+            )]
+        ));
+        Ok(resolution_message)
+    }
+
+    /// The status in force before the pending cancellation, kept in the audit entry of its request.
+    fn status_before_cancellation(&self) -> MessageStatus {
+        self.audit().iter().rev()
+            .flat_map(|audit| audit.changes())
+            .find(|change| change.field() == STATUS_FIELD)
+            .and_then(|change| change.old_value().cloned())
+            .and_then(|value| serde_json::from_value(value).ok())
+            .unwrap_or(MessageStatus::Processing)
+    }
+
+    fn camt029(&self, request: &Message, reason_code: &str, additional_info: Option<&String>, workflow: String, task: String) -> Result<Message, FunctionResponseError> {
+        let start_time = OffsetDateTime::now_utc();
+        let cancellation = value_at(request.data(), "document.FIToFIPmtCxlReq")
+            .ok_or_else(|| error("Cancellation", "Request is not a parsed camt.056".to_string()))?;
+        let transfer = value_at(self.data(), "document.FIToFICstmrCdtTrf")
+            .ok_or_else(|| error("Cancellation", "Message is not a parsed pacs.008".to_string()))?;
+
+        let mut statuses = Vec::new();
+        for reference in request.cancellation_references()? {
+            let Some(index) = reference.matches(transfer) else {
+                continue;
+            };
+            let transaction = &transfer["CdtTrfTxInf"][index];
+            let mut status = json!({
+                "CxlStsId": message_id(),
+                "OrgnlGrpInf": {
+                    "OrgnlMsgId": transfer["GrpHdr"]["MsgId"],
+                    "OrgnlMsgNmId": document_message_name(self, "FIToFICstmrCdtTrf"),
+                },
+                "OrgnlEndToEndId": transaction["PmtId"]["EndToEndId"],
+                "TxCxlSts": "RJCR",
+                "CxlStsRsnInf": [{"Rsn": {"Cd": reason_code}}],
+            });
+            if let Some(info) = additional_info {
+                status["CxlStsRsnInf"][0]["AddtlInf"] = json!([info]);
+            }
+            for (from, to) in [("PmtId.UETR", "OrgnlUETR"), ("IntrBkSttlmAmt", "OrgnlIntrBkSttlmAmt"), ("IntrBkSttlmDt", "OrgnlIntrBkSttlmDt")] {
+                if let Some(value) = value_at(transaction, from) {
+                    status[to] = value.clone();
+                }
+            }
+            statuses.push(status);
+        }
+        if statuses.is_empty() {
+            return Err(error("Cancellation", "No transaction of the original matches the cancellation request".to_string()));
+        }
+
+        let mut resolution = json!({
+            "Assgnmt": {
+                "Id": message_id(),
+                "Assgnr": cancellation["Assgnmt"]["Assgne"],
+                "Assgne": cancellation["Assgnmt"]["Assgnr"],
+                "CreDtTm": timestamp(),
+            },
+            "Sts": {"Conf": "RJCR"},
+            "CxlDtls": [{"TxInfAndSts": statuses}],
+        });
+        if let Some(case) = value_at(cancellation, "Case") {
+            resolution["RslvdCase"] = case.clone();
+        }
+
+        let changes = vec![
+            ChangeLog::new("data.document.RsltnOfInvstgtn.Sts.Conf".to_string(), "Cancellation rejected".to_string(), None, Some(json!("RJCR"))),
+            ChangeLog::new("data.document.RsltnOfInvstgtn.CxlDtls".to_string(), format!("Rejection reason {}", reason_code), None, Some(resolution["CxlDtls"].clone())),
+        ];
+        let mut message = request.child(json!({"document": {"RsltnOfInvstgtn": resolution}}), workflow.clone(), task.clone(), Some("camt.029".to_string()));
+        message.push_audit(AuditLog::new(
+            workflow,
+            task,
+            start_time,
+            format!("Resolution of camt.056 {}", request.id()),
+            changes
+        ));
+        validate(&message, "Cancellation")?;
+        Ok(message)
+    }
+}
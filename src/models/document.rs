@@ -0,0 +1,67 @@
+use serde_json::{json, Map, Value};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use sonyflake::Sonyflake;
+
+use crate::models::errors::{error, FunctionResponseError};
+use crate::models::message::Message;
+
+/// Current UTC time as an ISO20022 date time, e.g. CreDtTm.
+pub(crate) fn timestamp() -> String {
+    OffsetDateTime::now_utc().format(&Rfc3339).unwrap()
+}
+
+/// Unique identifier for a generated message, e.g. GrpHdr.MsgId.
+pub(crate) fn message_id() -> String {
+    Sonyflake::new().unwrap().next_id().unwrap().to_string()
+}
+
+pub(crate) fn agent(bic: &str) -> Value {
+    json!({"FinInstnId": {"BICFI": bic}})
+}
+
+pub(crate) fn settlement(method: &str, clearing_system: Option<&String>) -> Value {
+    let mut settlement = Map::new();
+    settlement.insert("SttlmMtd".to_string(), json!(method));
+    if let Some(clearing_system) = clearing_system {
+        settlement.insert("ClrSys".to_string(), json!({"Cd": clearing_system}));
+    }
+    Value::Object(settlement)
+}
+
+/// ISO20022 message name for a document root element, as produced by this library.
+pub(crate) fn message_name(root: &str) -> Option<&'static str> {
+    match root {
+        "CstmrCdtTrfInitn" => Some("pain.001.001.12"),
+        "FIToFIPmtStsRpt" => Some("pacs.002.001.12"),
+        "PmtRtr" => Some("pacs.004.001.13"),
+        "FIToFICstmrCdtTrf" => Some("pacs.008.001.12"),
+        "FICdtTrf" => Some("pacs.009.001.11"),
+        "RsltnOfInvstgtn" => Some("camt.029.001.13"),
+        "BkToCstmrStmt" => Some("camt.053.001.12"),
+        "BkToCstmrDbtCdtNtfctn" => Some("camt.054.001.12"),
+        "FIToFIPmtCxlReq" => Some("camt.056.001.11"),
+        _ => None,
+    }
+}
+
+/// ISO20022 message name of the document of `message`: its origin when that is a version of
+/// the same message, e.g. pacs.008.001.07, or else the version this library produces.
+pub(crate) fn document_message_name(message: &Message, root: &str) -> String {
+    match message_name(root) {
+        Some(name) if message.origin().len() > 9 && message.origin().starts_with(&name[..9]) => message.origin().clone(),
+        Some(name) => name.to_string(),
+        None => root.to_string(),
+    }
+}
+
+/// Root element and body of `data.document`.
+pub(crate) fn document_root(data: &Value) -> Option<(&str, &Value)> {
+    data["document"].as_object()?.iter().next().map(|(root, body)| (root.as_str(), body))
+}
+
+pub(crate) fn validate(message: &Message, function: &str) -> Result<(), FunctionResponseError> {
+    message.document()?.validate().map_err(|e| {
+        error(function, format!("Schema validation error: {:?}", e))
+    })
+}
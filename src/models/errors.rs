@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug)]
 pub struct FunctionResponseError {
     pub function: String,
@@ -5,8 +7,39 @@ pub struct FunctionResponseError {
     pub message: String,
 }
 
+/// Class of a `FunctionResponseError`, derived from its HTTP style code.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// 4xx apart from the codes below: the input or definition is wrong
+    Invalid,
+    /// 409 and 423, e.g. a locked resource
+    Conflict,
+    /// 408 and 504
+    Timeout,
+    /// 503, e.g. an unreachable sink or unreadable file
+    Unavailable,
+    /// Any other 5xx
+    Internal,
+}
+
 impl FunctionResponseError {
     pub fn new(function: String, code: u32, message: String) -> Self {
         FunctionResponseError { function, code, message }
     }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self.code {
+            409 | 423 => ErrorKind::Conflict,
+            408 | 504 => ErrorKind::Timeout,
+            503 => ErrorKind::Unavailable,
+            500..=599 => ErrorKind::Internal,
+            _ => ErrorKind::Invalid,
+        }
+    }
+}
+
+/// An `Invalid` error of `function`.
+pub(crate) fn error(function: &str, message: String) -> FunctionResponseError {
+    FunctionResponseError::new(function.to_string(), 400, message)
 }
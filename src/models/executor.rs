@@ -0,0 +1,676 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+
+use crate::models::auditlog::*;
+use crate::models::document::validate;
+use crate::models::errors::{error, FunctionResponseError};
+use crate::models::message::{Message, MessageStatus, StatusCode};
+use crate::models::profile::ProfileRegistry;
+use crate::models::registry::WorkflowRegistry;
+use crate::models::simulation::PlannedWait;
+use crate::models::task::FunctionType;
+use crate::models::trace::{cap_traces, RuleTracer, TraceConfig};
+use crate::models::path::value_at;
+use crate::models::workflow::{CompiledHandler, CompiledTask, CompiledWorkflow, ElementFailurePolicy, ForeachInput, SubWorkflowInput, ValidateInput};
+
+/// Destination of `Publish` tasks, e.g. a queue or topic. `input` is the task input.
+pub trait Publisher: Send + Sync {
+    fn publish(&self, message: &Message, input: &Value) -> Result<(), FunctionResponseError>;
+}
+
+/// A field of `Message::data` changed by more than one parallel task.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MergeConflict {
+    pub path: String,
+
+    pub tasks: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct ExecutionReport {
+    /// Task ids in completion order
+    pub executed: Vec<String>,
+
+    /// Task ids whose condition was not met
+    pub skipped: Vec<String>,
+
+    /// Task ids that failed and were recovered by their repair tasks
+    #[serde(default)]
+    pub repaired: Vec<String>,
+
+    /// Task ids that failed and were not repaired
+    #[serde(default)]
+    pub failed: Vec<String>,
+
+    /// Task ids completed before `WorkflowExecutor::resume`, not run again
+    #[serde(default)]
+    pub resumed: Vec<String>,
+}
+
+/// The workflow being run, nested in the workflows that invoked it as a sub-workflow.
+struct Scope<'a> {
+    workflow: &'a CompiledWorkflow,
+    /// Workflow name in audit entries: the names from the top-level workflow, joined by '/'
+    label: String,
+    /// Name and version of this workflow and the ones invoking it
+    calls: Vec<(String, u16)>,
+}
+
+impl<'a> Scope<'a> {
+    fn new(workflow: &'a CompiledWorkflow) -> Self {
+        let (name, version) = (workflow.workflow().name.clone(), workflow.workflow().version);
+        Scope { workflow, label: name.clone(), calls: vec![(name, version)] }
+    }
+
+    /// Sub-workflow tasks run as part of their calling task, and are not recorded in `Progress`.
+    fn nested(&self) -> bool {
+        self.calls.len() > 1
+    }
+}
+
+/// Runs compiled workflows against messages, level by level of their dependency graph.
+#[derive(Clone)]
+pub struct WorkflowExecutor {
+    publisher: Option<Arc<dyn Publisher>>,
+    registry: Option<Arc<WorkflowRegistry>>,
+    profiles: Option<Arc<ProfileRegistry>>,
+    trace: Option<TraceConfig>,
+    parallel: bool,
+    /// Set by `simulate`: retry delays are recorded here instead of slept through
+    pub(crate) waits: Option<Arc<Mutex<Vec<PlannedWait>>>>,
+}
+
+impl Default for WorkflowExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WorkflowExecutor {
+    pub fn new() -> Self {
+        Self { publisher: None, registry: None, profiles: None, trace: None, parallel: true, waits: None }
+    }
+
+    pub fn with_publisher(mut self, publisher: Arc<dyn Publisher>) -> Self {
+        self.publisher = Some(publisher);
+        self
+    }
+
+    /// Registry resolving the workflows of `SubWorkflow` tasks.
+    pub fn with_registry(mut self, registry: Arc<WorkflowRegistry>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Registry resolving the profiles of `Validate` tasks, the builtin profiles otherwise.
+    pub fn with_profiles(mut self, profiles: Arc<ProfileRegistry>) -> Self {
+        self.profiles = Some(profiles);
+        self
+    }
+
+    /// Records the rule evaluations of task conditions and enrichments in the audit trail, for
+    /// the tenants `config` covers. A task condition gets its own audit entry in this mode.
+    pub fn with_trace(mut self, config: TraceConfig) -> Self {
+        self.trace = Some(config);
+        self
+    }
+
+    /// Whether independent tasks of a level run on separate threads. On by default.
+    pub fn with_parallelism(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Runs every task whose condition holds, `input` being the external enrichment data.
+    /// Tasks of the same level run concurrently on copies of the message, and their changes
+    /// are merged back in declaration order; the same field changed by two of them fails the
+    /// workflow. A failed task goes to its `on_failure` handler, or the workflow one: repair
+    /// tasks that succeed let the workflow carry on, otherwise completed tasks are compensated
+    /// in reverse order before the rejection or exception queue publication. The message ends
+    /// `Completed`, or `Failed` with the task error.
+    pub fn run(&self, workflow: &CompiledWorkflow, message: &mut Message, input: &Value) -> Result<ExecutionReport, FunctionResponseError> {
+        let mut report = ExecutionReport::default();
+        self.run_into(workflow, message, input, &mut report).map(|_| report)
+    }
+
+    /// `run`, filling `report` as tasks finish so it is complete when the run fails too.
+    pub(crate) fn run_into(&self, workflow: &CompiledWorkflow, message: &mut Message, input: &Value, report: &mut ExecutionReport) -> Result<(), FunctionResponseError> {
+        message.set_workflow(&workflow.workflow().name, workflow.workflow().version);
+        message.clear_completed_tasks();
+        self.start(workflow, message, input, report)
+    }
+
+    /// Continues `workflow` on a message that stopped part way, e.g. one saved before a crash or
+    /// after a manual repair. Tasks in `Progress::completed_tasks` are not run again, a task that
+    /// failed runs again, and a completed message is left as is. Messages without completed tasks
+    /// recorded resume after `prev_task`. Resuming fails when the message is on another workflow,
+    /// or when this version changed or removed a task that already completed.
+    ///
+    /// Task effects are recorded in the message together with its progress, so they are applied
+    /// once provided the message is saved after each run; a publication made just before the
+    /// process stopped is made again.
+    pub fn resume(&self, workflow: &CompiledWorkflow, message: &mut Message, input: &Value) -> Result<ExecutionReport, FunctionResponseError> {
+        let definition = workflow.workflow();
+        let progress = message.progress().clone();
+        let fail = |reason: String| error("Workflow", format!("Cannot resume message {}: {}", message.id(), reason));
+
+        // Sub-workflows record their caller first, e.g. incoming/common_checks
+        let current = progress.workflow_id.split('/').next().unwrap_or_default();
+        if current != definition.name {
+            return Err(fail(format!("it is on workflow {}, not {}", current, definition.name)));
+        }
+        if progress.status == MessageStatus::Completed {
+            let resumed = progress.completed_tasks.iter().map(|c| c.task_id.clone()).collect();
+            return Ok(ExecutionReport { resumed, ..Default::default() });
+        }
+
+        let version_changed = progress.workflow_version.is_some_and(|version| version != definition.version);
+        if progress.completed_tasks.is_empty() {
+            if version_changed {
+                return Err(fail(format!("workflow {} changed from version {} to {} and no completed tasks are recorded", definition.name, progress.workflow_version.unwrap_or_default(), definition.version)));
+            }
+            let order: Vec<&CompiledTask> = workflow.levels().iter().flatten().map(|index| &workflow.tasks()[*index]).collect();
+            if let Some(position) = order.iter().position(|task| task.task().task_id == progress.prev_task) {
+                let failed = progress.status == MessageStatus::Failed || progress.prev_status_code == Some(StatusCode::Failure);
+                let done = if failed { position } else { position + 1 };
+                for task in &order[..done] {
+                    message.complete_task(&task.task().task_id, task.fingerprint());
+                }
+            }
+        } else {
+            for completed in &progress.completed_tasks {
+                match workflow.task(&completed.task_id) {
+                    Some(task) if task.fingerprint() == completed.fingerprint => {}
+                    Some(_) => return Err(fail(format!("completed task {} changed in workflow {} version {}", completed.task_id, definition.name, definition.version))),
+                    None => return Err(fail(format!("completed task {} was removed from workflow {} version {}", completed.task_id, definition.name, definition.version))),
+                }
+            }
+        }
+
+        let resumed = message.progress().completed_tasks.iter().map(|c| c.task_id.clone()).collect();
+        message.set_workflow(&definition.name, definition.version);
+        let mut report = ExecutionReport { resumed, ..Default::default() };
+        self.start(workflow, message, input, &mut report)?;
+        Ok(report)
+    }
+
+    fn start(&self, workflow: &CompiledWorkflow, message: &mut Message, input: &Value, report: &mut ExecutionReport) -> Result<(), FunctionResponseError> {
+        message.set_status(MessageStatus::Processing);
+        let result = self.run_levels(&Scope::new(workflow), message, input, report);
+        message.set_status(match result {
+            Ok(_) => MessageStatus::Completed,
+            Err(_) => MessageStatus::Failed,
+        });
+        result
+    }
+
+    fn run_levels(&self, scope: &Scope, message: &mut Message, input: &Value, report: &mut ExecutionReport) -> Result<(), FunctionResponseError> {
+        for level in scope.workflow.levels() {
+            // Conditions of a level are evaluated before any of its tasks runs
+            let context = message.rule_context(Value::Null);
+            let mut ready = Vec::new();
+            for task in level.iter().map(|index| &scope.workflow.tasks()[*index]) {
+                if !scope.nested() && message.progress().completed_tasks.iter().any(|c| c.task_id == task.task().task_id) {
+                    continue;
+                }
+                match self.applies(scope, task, message, &context)? {
+                    true => ready.push(task),
+                    false => report.skipped.push(task.task().task_id.clone()),
+                }
+            }
+
+            if ready.len() < 2 || !self.parallel {
+                for task in ready {
+                    match self.execute(scope, task, message, input) {
+                        Ok(()) => report.executed.push(task.task().task_id.clone()),
+                        Err(failure) => self.recover(scope, task, message, input, report, failure)?,
+                    }
+                    self.completed(scope, task, message);
+                }
+                continue;
+            }
+
+            let base = message.clone();
+            let results: Vec<(Message, Result<(), FunctionResponseError>)> = thread::scope(|threads| {
+                let handles: Vec<_> = ready.iter()
+                    .map(|task| {
+                        let mut branch = base.clone();
+                        threads.spawn(move || {
+                            let result = self.execute(scope, task, &mut branch, input);
+                            (branch, result)
+                        })
+                    })
+                    .collect();
+                handles.into_iter()
+                    .map(|handle| handle.join().unwrap_or_else(|_| (base.clone(), Err(error("Workflow", "Task panicked".to_string())))))
+                    .collect()
+            });
+            let mut branches = Vec::new();
+            let mut failures = Vec::new();
+            for (task, (branch, result)) in ready.iter().zip(results) {
+                let task_id = task.task().task_id.clone();
+                match result {
+                    Ok(()) => branches.push((task_id, branch, true)),
+                    Err(failure) => {
+                        // Failed attempts stay audited
+                        branches.push((task_id, branch, false));
+                        failures.push((*task, failure));
+                    }
+                }
+            }
+
+            let executed: Vec<String> = branches.iter().filter(|(_, _, succeeded)| *succeeded).map(|(task_id, _, _)| task_id.clone()).collect();
+            message.merge_branches(&base, branches).map_err(|conflicts| {
+                let details: Vec<String> = conflicts.iter()
+                    .map(|c| format!("{} ({})", c.path, c.tasks.join(", ")))
+                    .collect();
+                error("Workflow", format!("Conflicting changes from parallel tasks: {}", details.join("; ")))
+            })?;
+            cap_traces(self.trace.as_ref(), message, base.audit().len());
+            for task in ready.iter().filter(|task| executed.contains(&task.task().task_id)) {
+                self.completed(scope, task, message);
+            }
+            report.executed.extend(executed);
+            for (task, failure) in failures {
+                self.recover(scope, task, message, input, report, failure)?;
+                self.completed(scope, task, message);
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates the task condition against `context`, the rule context of `message`, auditing
+    /// the evaluation when the message is traced.
+    fn applies(&self, scope: &Scope, task: &CompiledTask, message: &mut Message, context: &Value) -> Result<bool, FunctionResponseError> {
+        let mut tracer = RuleTracer::new(self.trace.as_ref(), message);
+        if !tracer.enabled() {
+            return task.applies_in(context, &mut tracer);
+        }
+        let start_time = OffsetDateTime::now_utc();
+        let result = task.applies_in(context, &mut tracer);
+        let outcome = match &result {
+            Ok(true) => "met".to_string(),
+            Ok(false) => "not met".to_string(),
+            Err(e) => format!("failed: {}", e.message),
+        };
+        message.push_audit(AuditLog::new(
+            scope.label.clone(),
+            task.task().task_id.clone(),
+            start_time,
+            format!("Condition of task {} {}", task.task().task_id, outcome),
+            Vec::new()
+        ).with_trace(tracer.finish()));
+        result
+    }
+
+    fn completed(&self, scope: &Scope, task: &CompiledTask, message: &mut Message) {
+        if !scope.nested() {
+            message.complete_task(&task.task().task_id, task.fingerprint());
+        }
+    }
+
+    /// Handles the failure of `failed`: `Ok` once repaired, else the failure after compensation.
+    fn recover(&self, scope: &Scope, failed: &CompiledTask, message: &mut Message, input: &Value, report: &mut ExecutionReport, mut failure: FunctionResponseError) -> Result<(), FunctionResponseError> {
+        let task_id = failed.task().task_id.clone();
+        let handler = failed.on_failure().or(scope.workflow.on_failure());
+
+        if let Some(CompiledHandler::Repair(tasks)) = handler {
+            self.record(scope, message, &task_id, MessageStatus::Repairing, format!("Task {} failed, repairing", task_id), &failure);
+            let repaired = tasks.iter().try_for_each(|task| match self.applies(scope, task, message, &message.rule_context(Value::Null))? {
+                true => self.execute(scope, task, message, input),
+                false => Ok(()),
+            });
+            match repaired {
+                Ok(()) => {
+                    message.set_status(MessageStatus::Processing);
+                    report.repaired.push(task_id);
+                    return Ok(());
+                }
+                Err(e) => failure = e,
+            }
+        }
+
+        report.failed.push(task_id.clone());
+        self.compensate(scope, message, input, report, &task_id, &failure);
+        message.set_status(MessageStatus::Failed);
+
+        let start_time = OffsetDateTime::now_utc();
+        let handled = match handler {
+            Some(CompiledHandler::Reject { queue, status_report }) => message.to_pacs002(status_report, Some(&failure), None, scope.label.clone(), task_id.clone())
+                .and_then(|rejection| self.publish_failure(scope, &rejection, message, &task_id, queue, &failure)),
+            Some(CompiledHandler::ExceptionQueue { queue }) => {
+                let failed_message = message.clone();
+                self.publish_failure(scope, &failed_message, message, &task_id, queue, &failure)
+            }
+            _ => Ok(()),
+        };
+        // A failing handler is audited; the caller still sees the failure of the task
+        if let Err(e) = handled {
+            message.push_audit(AuditLog::new(
+                scope.label.clone(),
+                task_id,
+                start_time,
+                "Failure handler failed".to_string(),
+                vec![ChangeLog::new("progress.status".to_string(), format!("{}: {}", e.function, e.message), None, None)]
+            ));
+        }
+        Err(failure)
+    }
+
+    /// Runs the compensation of every executed task, most recent first. A failing compensation is
+    /// audited and does not stop the others; a task compensated successfully is no longer completed.
+    fn compensate(&self, scope: &Scope, message: &mut Message, input: &Value, report: &ExecutionReport, failed: &str, failure: &FunctionResponseError) {
+        // Tasks completed before a resume are compensated too
+        let executed: Vec<String> = match scope.nested() {
+            true => report.executed.clone(),
+            false => message.progress().completed_tasks.iter().map(|c| c.task_id.clone()).collect(),
+        };
+        let compensations: Vec<(&String, &CompiledTask)> = executed.iter().rev()
+            .filter(|task_id| !report.repaired.contains(task_id))
+            .filter_map(|task_id| Some((task_id, scope.workflow.task(task_id)?.compensation()?)))
+            .collect();
+        if compensations.is_empty() {
+            return;
+        }
+        self.record(scope, message, failed, MessageStatus::Compensating, format!("Task {} failed, compensating", failed), failure);
+        for (task_id, task) in compensations {
+            let start_time = OffsetDateTime::now_utc();
+            match self.execute(scope, task, message, input) {
+                Ok(()) if !scope.nested() => message.uncomplete_task(task_id),
+                Ok(()) => {}
+                Err(e) => message.push_audit(AuditLog::new(
+                    scope.label.clone(),
+                    task.task().task_id.clone(),
+                    start_time,
+                    "Compensation failed".to_string(),
+                    vec![ChangeLog::new("progress.status".to_string(), format!("{}: {}", e.function, e.message), None, None)]
+                )),
+            }
+        }
+    }
+
+    /// Publishes `outgoing` to a failure queue, auditing it on `message`.
+    fn publish_failure(&self, scope: &Scope, outgoing: &Message, message: &mut Message, task_id: &str, queue: &str, failure: &FunctionResponseError) -> Result<(), FunctionResponseError> {
+        let start_time = OffsetDateTime::now_utc();
+        let publisher = self.publisher.as_ref()
+            .ok_or_else(|| error("Publish", "No publisher configured".to_string()))?;
+        let target = json!({"queue": queue, "error": failure.message});
+        publisher.publish(outgoing, &target)?;
+        message.push_audit(AuditLog::new(
+            scope.label.clone(),
+            task_id.to_string(),
+            start_time,
+            format!("Message {} published to {}", outgoing.id(), queue),
+            vec![ChangeLog::new("payload".to_string(), "Published after failure".to_string(), None, Some(target))]
+        ));
+        Ok(())
+    }
+
+    fn record(&self, scope: &Scope, message: &mut Message, task_id: &str, status: MessageStatus, description: String, failure: &FunctionResponseError) {
+        let previous = message.progress().status.clone();
+        message.set_status(status.clone());
+        message.push_audit(AuditLog::new(
+            scope.label.clone(),
+            task_id.to_string(),
+            OffsetDateTime::now_utc(),
+            description,
+            vec![ChangeLog::new(
+                "progress.status".to_string(),
+                format!("{}: {}", failure.function, failure.message),
+                Some(json!(previous)),
+                Some(json!(status))
+            )]
+        ));
+    }
+
+    /// Runs a task, retrying it as its `RetryPolicy` allows. Each failed attempt is audited.
+    fn execute(&self, scope: &Scope, compiled: &CompiledTask, message: &mut Message, input: &Value) -> Result<(), FunctionResponseError> {
+        let Some(policy) = &compiled.task().retry else {
+            return self.attempt(scope, compiled, message, input);
+        };
+        let mut attempt = 1;
+        loop {
+            let start_time = OffsetDateTime::now_utc();
+            let failure = match self.attempt(scope, compiled, message, input) {
+                Err(e) if policy.retries(attempt, &e) => e,
+                result => return result,
+            };
+            let delay = policy.delay(attempt);
+            message.set_status(MessageStatus::Retrying);
+            message.push_audit(AuditLog::new(
+                scope.label.clone(),
+                compiled.task().task_id.clone(),
+                start_time,
+                format!("Attempt {} of {} failed", attempt, policy.max_attempts),
+                vec![ChangeLog::new(
+                    "progress.status".to_string(),
+                    format!("{}: {}, retrying in {} ms", failure.function, failure.message, delay.as_millis()),
+                    Some(json!(MessageStatus::Processing)),
+                    Some(json!(MessageStatus::Retrying))
+                )]
+            ));
+            match &self.waits {
+                Some(waits) => waits.lock().unwrap().push(PlannedWait {
+                    task_id: compiled.task().task_id.clone(),
+                    attempt,
+                    delay_ms: delay.as_millis() as u64,
+                }),
+                None => thread::sleep(delay),
+            }
+            message.set_status(MessageStatus::Processing);
+            attempt += 1;
+        }
+    }
+
+    fn attempt(&self, scope: &Scope, compiled: &CompiledTask, message: &mut Message, input: &Value) -> Result<(), FunctionResponseError> {
+        let task = compiled.task();
+        let (workflow, task_id) = (scope.label.clone(), task.task_id.clone());
+        let description = Some(task.description.clone()).filter(|d| !d.is_empty());
+        let start_time = OffsetDateTime::now_utc();
+
+        match task.function {
+            FunctionType::Enrich => {
+                let enrichment = compiled.enrichment()
+                    .ok_or_else(|| error("Workflow", format!("Task {} has no enrichment", task_id)))?;
+                message.enrich_traced(enrichment, input.clone(), description, workflow, task_id, self.trace.as_ref())
+            }
+            FunctionType::Validate => {
+                let validation: ValidateInput = serde_json::from_value(task.input.clone())
+                    .map_err(|e| error("Validate", format!("Invalid validation input: {}", e)))?;
+                validate(message, "Validate")?;
+                if let Some(name) = &validation.profile {
+                    let profile = self.profiles.as_deref().unwrap_or(ProfileRegistry::builtins()).get(name)
+                        .ok_or_else(|| error("Validate", format!("Unknown profile {}", name)))?;
+                    message.validate_profile(profile, description.clone(), workflow.clone(), task_id.clone())?;
+                }
+                if validation.identifiers {
+                    message.validate_identifiers(description.clone(), workflow.clone(), task_id.clone())?;
+                }
+                if validation.profile.is_none() && !validation.identifiers {
+                    message.push_audit(AuditLog::new(
+                        workflow,
+                        task_id,
+                        start_time,
+                        description.unwrap_or_else(|| "Schema validated".to_string()),
+                        vec![ChangeLog::new("data".to_string(), "Validated against the message schema".to_string(), None, None)]
+                    ));
+                }
+                Ok(())
+            }
+            FunctionType::Publish => {
+                let publisher = self.publisher.as_ref()
+                    .ok_or_else(|| error("Publish", "No publisher configured".to_string()))?;
+                publisher.publish(message, &task.input)?;
+                message.push_audit(AuditLog::new(
+                    workflow,
+                    task_id,
+                    start_time,
+                    description.unwrap_or_else(|| "Message published".to_string()),
+                    vec![ChangeLog::new("payload".to_string(), "Published".to_string(), None, Some(json!(task.input)))]
+                ));
+                Ok(())
+            }
+            FunctionType::SubWorkflow => {
+                let call = compiled.call()
+                    .ok_or_else(|| error("Workflow", format!("Task {} has no sub-workflow input", task_id)))?;
+                self.call(scope, compiled, call, message, input)
+            }
+            FunctionType::Foreach => {
+                let foreach = compiled.foreach()
+                    .ok_or_else(|| error("Workflow", format!("Task {} has no foreach input", task_id)))?;
+                self.iterate(scope, compiled, foreach, message, input)
+            }
+        }
+    }
+
+    /// Runs the element tasks or enrichment of a `Foreach` task on each element of its array.
+    /// Elements that fail are audited together with the task; under `Abort` the first one fails
+    /// the task and the changes made to every element are rolled back.
+    fn iterate(&self, scope: &Scope, compiled: &CompiledTask, foreach: &ForeachInput, message: &mut Message, input: &Value) -> Result<(), FunctionResponseError> {
+        let task_id = compiled.task().task_id.clone();
+        let start_time = OffsetDateTime::now_utc();
+        let count = match foreach.path.strip_prefix("data.").and_then(|path| value_at(message.data(), path)) {
+            None => 0,
+            Some(Value::Array(items)) => items.len(),
+            Some(_) => return Err(error("Foreach", format!("{} is not an array", foreach.path))),
+        };
+
+        // Skip rolls back one element at a time, so only Abort needs the whole data
+        let snapshot = match foreach.on_element_failure {
+            ElementFailurePolicy::Abort => Some(message.data().clone()),
+            ElementFailurePolicy::Skip => None,
+        };
+        let mut failures = Vec::new();
+        let mut processed = 0;
+        for index in 0..count {
+            // Element tasks may have shortened the array
+            let element = match foreach.path.strip_prefix("data.").and_then(|path| value_at(message.data(), path)) {
+                Some(Value::Array(items)) if index < items.len() => match foreach.on_element_failure {
+                    ElementFailurePolicy::Skip => Some(items[index].clone()),
+                    ElementFailurePolicy::Abort => None,
+                },
+                _ => break,
+            };
+            processed += 1;
+            message.bind_element(Some((&foreach.path, index)));
+            let result = match compiled.enrichment() {
+                Some(enrichment) => {
+                    let description = Some(format!("Enriched element {} of {}", index, foreach.path));
+                    message.enrich_traced(enrichment, input.clone(), description, scope.label.clone(), task_id.clone(), self.trace.as_ref())
+                }
+                None => compiled.element_tasks().iter().try_for_each(|task| match self.applies(scope, task, message, &message.rule_context(Value::Null))? {
+                    true => self.execute(scope, task, message, input),
+                    false => Ok(()),
+                }),
+            };
+            message.bind_element(None);
+            message.set_task(&scope.label, &task_id);
+
+            let Err(e) = result else {
+                continue;
+            };
+            let reason = format!("{}: {}", e.function, e.message);
+            match foreach.on_element_failure {
+                ElementFailurePolicy::Abort => {
+                    if let Some(snapshot) = snapshot {
+                        message.restore_data(snapshot);
+                    }
+                    message.push_audit(AuditLog::new(
+                        scope.label.clone(),
+                        task_id,
+                        start_time,
+                        format!("Element {} of {} failed", index, foreach.path),
+                        vec![ChangeLog::new(format!("{}.{}", foreach.path, index), format!("{}, changes rolled back", reason), None, None)]
+                    ));
+                    return Err(FunctionResponseError::new("Foreach".to_string(), e.code, format!("Element {} of {} failed: {}", index, foreach.path, reason)));
+                }
+                ElementFailurePolicy::Skip => {
+                    if let Some(element) = element {
+                        message.restore_field(&format!("{}.{}", foreach.path, index), element);
+                    }
+                    failures.push(ChangeLog::new(format!("{}.{}", foreach.path, index), format!("{}, skipped", reason), None, None));
+                }
+            }
+        }
+
+        let description = match failures.len() {
+            0 => format!("Processed {} elements of {}", processed, foreach.path),
+            failed => format!("Processed {} elements of {}, {} failed", processed, foreach.path, failed),
+        };
+        message.push_audit(AuditLog::new(scope.label.clone(), task_id, start_time, description, failures));
+        Ok(())
+    }
+
+    /// Runs the workflow of a `SubWorkflow` task on `message`, as part of the calling task: its
+    /// audit entries are nested under the calling workflow name, and its data changes are rolled
+    /// back together when it fails.
+    fn call(&self, scope: &Scope, compiled: &CompiledTask, call: &SubWorkflowInput, message: &mut Message, input: &Value) -> Result<(), FunctionResponseError> {
+        let task_id = compiled.task().task_id.clone();
+        let start_time = OffsetDateTime::now_utc();
+        let registry = self.registry.as_ref()
+            .ok_or_else(|| error("SubWorkflow", "No workflow registry configured".to_string()))?;
+        let workflow = match call.version {
+            Some(version) => registry.get(&call.workflow, version),
+            None => registry.latest(&call.workflow),
+        };
+        let workflow = workflow.ok_or_else(|| match call.version {
+            Some(version) => error("SubWorkflow", format!("Workflow {} version {} is not registered", call.workflow, version)),
+            None => error("SubWorkflow", format!("Workflow {} has no active version", call.workflow)),
+        })?;
+        let (name, version) = (workflow.workflow().name.clone(), workflow.workflow().version);
+        if scope.calls.iter().any(|(n, v)| *n == name && *v == version) {
+            return Err(error("SubWorkflow", format!("Workflow {} version {} is already running", name, version)));
+        }
+
+        let child_input = compiled.sub_workflow_input(message, input)?;
+        message.push_audit(AuditLog::new(
+            scope.label.clone(),
+            task_id.clone(),
+            start_time,
+            format!("Started sub-workflow {} version {}", name, version),
+            vec![ChangeLog::new("input".to_string(), "Sub-workflow input".to_string(), None, Some(child_input.clone()))]
+        ));
+
+        let mut calls = scope.calls.clone();
+        calls.push((name.clone(), version));
+        let nested = Scope { workflow, label: format!("{}/{}", scope.label, name), calls };
+        let snapshot = message.data().clone();
+        let start_time = OffsetDateTime::now_utc();
+        let mut report = ExecutionReport::default();
+        let result = self.run_levels(&nested, message, &child_input, &mut report);
+        message.set_task(&scope.label, &task_id);
+
+        match result {
+            Ok(()) => {
+                message.push_audit(AuditLog::new(
+                    scope.label.clone(),
+                    task_id,
+                    start_time,
+                    format!("Completed sub-workflow {} version {}", name, version),
+                    vec![ChangeLog::new("progress".to_string(), "Sub-workflow tasks".to_string(), None, Some(json!(report)))]
+                ));
+                Ok(())
+            }
+            Err(e) => {
+                let changed = message.data() != &snapshot;
+                message.restore_data(snapshot);
+                message.push_audit(AuditLog::new(
+                    scope.label.clone(),
+                    task_id,
+                    start_time,
+                    format!("Sub-workflow {} version {} failed", name, version),
+                    vec![ChangeLog::new(
+                        "data".to_string(),
+                        format!("{}: {}{}", e.function, e.message, if changed { ", changes rolled back" } else { "" }),
+                        None,
+                        None
+                    )]
+                ));
+                Err(e)
+            }
+        }
+    }
+}
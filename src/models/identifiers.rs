@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+
+use crate::models::auditlog::*;
+use crate::models::errors::{error, FunctionResponseError};
+use crate::models::message::Message;
+
+// IBAN length per country, from the ISO 13616 registry
+const IBAN_LENGTHS: [(&str, usize); 89] = [
+    ("AD", 24), ("AE", 23), ("AL", 28), ("AT", 20), ("AZ", 28), ("BA", 20), ("BE", 16), ("BG", 22),
+    ("BH", 22), ("BI", 27), ("BR", 29), ("BY", 28), ("CH", 21), ("CR", 22), ("CY", 28), ("CZ", 24),
+    ("DE", 22), ("DJ", 27), ("DK", 18), ("DO", 28), ("EE", 20), ("EG", 29), ("ES", 24), ("FI", 18),
+    ("FK", 18), ("FO", 18), ("FR", 27), ("GB", 22), ("GE", 22), ("GI", 23), ("GL", 18), ("GR", 27),
+    ("GT", 28), ("HN", 28), ("HR", 21), ("HU", 28), ("IE", 22), ("IL", 23), ("IQ", 23), ("IS", 26),
+    ("IT", 27), ("JO", 30), ("KW", 30), ("KZ", 20), ("LB", 28), ("LC", 32), ("LI", 21), ("LT", 20),
+    ("LU", 20), ("LV", 21), ("LY", 25), ("MC", 27), ("MD", 24), ("ME", 22), ("MK", 19), ("MN", 20),
+    ("MR", 27), ("MT", 31), ("MU", 30), ("NI", 28), ("NL", 18), ("NO", 15), ("OM", 23), ("PK", 24),
+    ("PL", 28), ("PS", 29), ("PT", 25), ("QA", 29), ("RO", 24), ("RS", 22), ("RU", 33), ("SA", 24),
+    ("SC", 31), ("SD", 18), ("SE", 24), ("SI", 19), ("SK", 24), ("SM", 27), ("SO", 23), ("ST", 25),
+    ("SV", 28), ("TL", 23), ("TN", 24), ("TR", 26), ("UA", 29), ("VA", 22), ("VG", 24), ("XK", 20),
+    ("YE", 30),
+];
+
+// ISO 3166-1 alpha-2 country codes, plus XK used by SWIFT for Kosovo
+const COUNTRY_CODES: &str = "\
+AD AE AF AG AI AL AM AO AQ AR AS AT AU AW AX AZ BA BB BD BE BF BG BH BI BJ BL BM BN BO BQ BR BS \
+BT BV BW BY BZ CA CC CD CF CG CH CI CK CL CM CN CO CR CU CV CW CX CY CZ DE DJ DK DM DO DZ EC EE \
+EG EH ER ES ET FI FJ FK FM FO FR GA GB GD GE GF GG GH GI GL GM GN GP GQ GR GS GT GU GW GY HK HM \
+HN HR HT HU ID IE IL IM IN IO IQ IR IS IT JE JM JO JP KE KG KH KI KM KN KP KR KW KY KZ LA LB LC \
+LI LK LR LS LT LU LV LY MA MC MD ME MF MG MH MK ML MM MN MO MP MQ MR MS MT MU MV MW MX MY MZ NA \
+NC NE NF NG NI NL NO NP NR NU NZ OM PA PE PF PG PH PK PL PM PN PR PS PT PW PY QA RE RO RS RU RW \
+SA SB SC SD SE SG SH SI SJ SK SL SM SN SO SR SS ST SV SX SY SZ TC TD TF TG TH TJ TK TL TM TN TO \
+TR TT TV TW TZ UA UG UM US UY UZ VA VC VE VG VI VN VU WF WS XK YE YT ZA ZM ZW";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum IdentifierKind {
+    Iban,
+    Bic,
+    Lei,
+    CreditorReference,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct InvalidIdentifier {
+    pub kind: IdentifierKind,
+
+    /// Location of the identifier, e.g. data.document.FIToFICstmrCdtTrf.CdtTrfTxInf.0.DbtrAcct.Id.IBAN
+    pub path: String,
+
+    pub value: String,
+}
+
+/// ISO 7064 MOD 97-10 remainder of an alphanumeric string, letters counting as 10 to 35.
+fn mod97(value: &str) -> Option<u32> {
+    let mut remainder = 0u32;
+    for c in value.chars() {
+        let digit = c.to_digit(36)?;
+        remainder = if digit < 10 {
+            (remainder * 10 + digit) % 97
+        } else {
+            (remainder * 100 + digit) % 97
+        };
+    }
+    Some(remainder)
+}
+
+fn is_upper_alphanumeric(value: &str) -> bool {
+    value.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+pub fn is_valid_country(code: &str) -> bool {
+    code.len() == 2 && COUNTRY_CODES.split(' ').any(|c| c == code)
+}
+
+/// IBAN in electronic format: registered country length and mod-97 check digits.
+pub fn is_valid_iban(iban: &str) -> bool {
+    let Some(length) = iban.get(..2)
+        .and_then(|country| IBAN_LENGTHS.iter().find(|(c, _)| *c == country))
+        .map(|(_, length)| *length) else {
+        return false;
+    };
+    iban.len() == length
+        && is_upper_alphanumeric(iban)
+        && iban[2..4].chars().all(|c| c.is_ascii_digit())
+        && mod97(&format!("{}{}", &iban[4..], &iban[..4])) == Some(1)
+}
+
+/// BIC (ISO 9362): party prefix, country code, location and optional branch.
+pub fn is_valid_bic(bic: &str) -> bool {
+    (bic.len() == 8 || bic.len() == 11)
+        && is_upper_alphanumeric(bic)
+        && bic[4..6].chars().all(|c| c.is_ascii_uppercase())
+        && is_valid_country(&bic[4..6])
+}
+
+/// LEI (ISO 17442): 18 alphanumeric characters followed by two mod-97 check digits.
+pub fn is_valid_lei(lei: &str) -> bool {
+    lei.len() == 20
+        && is_upper_alphanumeric(lei)
+        && lei[18..].chars().all(|c| c.is_ascii_digit())
+        && mod97(lei) == Some(1)
+}
+
+/// RF creditor reference (ISO 11649): RF, two check digits and up to 21 alphanumeric characters.
+pub fn is_valid_creditor_reference(reference: &str) -> bool {
+    (5..=25).contains(&reference.len())
+        && reference.starts_with("RF")
+        && is_upper_alphanumeric(reference)
+        && reference[2..4].chars().all(|c| c.is_ascii_digit())
+        && mod97(&format!("{}{}", &reference[4..], &reference[..4])) == Some(1)
+}
+
+fn check(kind: IdentifierKind, value: &str) -> bool {
+    match kind {
+        IdentifierKind::Iban => is_valid_iban(value),
+        IdentifierKind::Bic => is_valid_bic(value),
+        IdentifierKind::Lei => is_valid_lei(value),
+        IdentifierKind::CreditorReference => is_valid_creditor_reference(value),
+    }
+}
+
+fn kind_of(key: &str, parent: Option<&str>) -> Option<IdentifierKind> {
+    match (key, parent) {
+        ("IBAN", _) => Some(IdentifierKind::Iban),
+        ("BICFI" | "AnyBIC" | "BIC", _) => Some(IdentifierKind::Bic),
+        ("LEI", _) => Some(IdentifierKind::Lei),
+        ("Ref", Some("CdtrRefInf")) => Some(IdentifierKind::CreditorReference),
+        _ => None,
+    }
+}
+
+fn collect(value: &Value, path: &str, checked: &mut usize, invalid: &mut Vec<InvalidIdentifier>) {
+    match value {
+        Value::Object(map) => {
+            let key = path.rsplit('.').next();
+            for (field, child) in map {
+                let child_path = format!("{}.{}", path, field);
+                match (kind_of(field, key), child.as_str()) {
+                    // Only RF references are ISO 11649, other creditor references are national formats
+                    (Some(IdentifierKind::CreditorReference), Some(reference)) if !reference.starts_with("RF") => {}
+                    (Some(kind), Some(identifier)) => {
+                        *checked += 1;
+                        if !check(kind, identifier) {
+                            invalid.push(InvalidIdentifier { kind, path: child_path, value: identifier.to_string() });
+                        }
+                    }
+                    _ => collect(child, &child_path, checked, invalid),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                collect(item, &format!("{}.{}", path, index), checked, invalid);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Every IBAN, BIC, LEI and RF creditor reference in `data` that fails its check, with the
+/// number of identifiers checked.
+pub fn invalid_identifiers(data: &Value) -> (usize, Vec<InvalidIdentifier>) {
+    let mut checked = 0;
+    let mut invalid = Vec::new();
+    collect(data, "data", &mut checked, &mut invalid);
+    (checked, invalid)
+}
+
+impl Message {
+    /// Checks every IBAN, BIC, LEI and RF creditor reference found in `Message::data`.
+    pub fn validate_identifiers(&mut self, description: Option<String>, workflow: String, task: String) -> Result<(), FunctionResponseError> {
+        let start_time = OffsetDateTime::now_utc();
+        let (checked, invalid) = invalid_identifiers(self.data());
+        if !invalid.is_empty() {
+            let details: Vec<String> = invalid.iter()
+                .map(|i| format!("{:?} {} at {}", i.kind, i.value, i.path))
+                .collect();
+            return Err(error("Validate", format!("Invalid identifiers: {}", details.join("; "))));
+        }
+
+        self.push_audit(AuditLog::new(
+            workflow,
+            task,
+            start_time,
+            description.unwrap_or_else(|| "Identifiers validated".to_string()),
+            vec![ChangeLog::new(
+                "data".to_string(),
+                format!("{} identifiers validated", checked),
+                None,
+                Some(json!(checked))
+            )]
+        ));
+        Ok(())
+    }
+}
@@ -11,7 +11,7 @@ pub struct ISO20022Message {
 
 impl ISO20022Message {
 	pub fn validate(&self) -> Result<(), ValidationError> {
-        self.document.validate()?;
+        self.document.validate().map_err(|e| ValidationError::new(e.code, e.message))?;
 		Ok(())
 	}
 }
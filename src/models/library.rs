@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use serde_json::Value;
+
+use crate::models::errors::{error, FunctionResponseError};
+use crate::models::task::Task;
+
+/// Reusable task definitions. A workflow definition refers to one with `{"use": "<id>"}`, the
+/// other fields of the reference (e.g. `task_id`, `depends_on`) overriding the library ones.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TaskLibrary {
+    tasks: HashMap<String, Task>,
+}
+
+impl TaskLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads a .json, .yaml or .yml file mapping ids to task definitions. A definition without
+    /// `task_id` takes its id.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<TaskLibrary, FunctionResponseError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .map_err(|e| error("TaskLibrary", format!("Cannot read task library {}: {}", path.display(), e)))?;
+        let fail = |message: String| error("TaskLibrary", format!("Invalid task library {}: {}", path.display(), message));
+        let definitions: HashMap<String, Value> = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&content).map_err(|e| fail(e.to_string()))?,
+            Some("yaml" | "yml") => serde_yaml::from_str(&content).map_err(|e| fail(e.to_string()))?,
+            _ => return Err(fail("unsupported file".to_string())),
+        };
+
+        let mut library = TaskLibrary::new();
+        for (id, mut definition) in definitions {
+            if let Some(fields) = definition.as_object_mut() {
+                fields.entry("task_id").or_insert_with(|| Value::String(id.clone()));
+            }
+            let task = serde_json::from_value(definition).map_err(|e| fail(format!("{}: {}", id, e)))?;
+            library.insert(&id, task)?;
+        }
+        Ok(library)
+    }
+
+    pub fn insert(&mut self, id: &str, task: Task) -> Result<(), FunctionResponseError> {
+        if self.tasks.contains_key(id) {
+            return Err(error("TaskLibrary", format!("Library task {} is already defined", id)));
+        }
+        self.tasks.insert(id.to_string(), task);
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Task> {
+        self.tasks.get(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Replaces the library references of a workflow definition, in its tasks, repair tasks,
+    /// compensation tasks and the tasks of `Foreach` tasks, by the task definitions they use.
+    pub fn resolve(&self, mut definition: Value) -> Result<Value, FunctionResponseError> {
+        if let Some(tasks) = definition.get_mut("tasks").and_then(Value::as_array_mut) {
+            for (index, task) in tasks.iter_mut().enumerate() {
+                self.resolve_task(task, &format!("tasks[{}]", index))?;
+            }
+        }
+        self.resolve_handler(&mut definition, "")?;
+        Ok(definition)
+    }
+
+    fn resolve_task(&self, task: &mut Value, location: &str) -> Result<(), FunctionResponseError> {
+        if let Some(reference) = task.as_object_mut().and_then(|fields| fields.remove("use")) {
+            let id = reference.as_str()
+                .ok_or_else(|| error("TaskLibrary", format!("{}.use: expected a library task id", location)))?;
+            let library_task = self.get(id)
+                .ok_or_else(|| error("TaskLibrary", format!("{}.use: unknown library task {}", location, id)))?;
+            let mut resolved = serde_json::to_value(library_task).map_err(|e| error("TaskLibrary", e.to_string()))?;
+            if let (Some(resolved), Some(overrides)) = (resolved.as_object_mut(), task.as_object()) {
+                resolved.extend(overrides.clone());
+            }
+            *task = resolved;
+        }
+        self.resolve_handler(task, &format!("{}.", location))?;
+        if task["function"] == "Foreach" {
+            let element_tasks = task.get_mut("input")
+                .and_then(|input| input.get_mut("tasks"))
+                .and_then(Value::as_array_mut);
+            for (index, element_task) in element_tasks.into_iter().flatten().enumerate() {
+                self.resolve_task(element_task, &format!("{}.input.tasks[{}]", location, index))?;
+            }
+        }
+        if let Some(compensation) = task.get_mut("compensation").filter(|c| c.is_object()) {
+            self.resolve_task(compensation, &format!("{}.compensation", location))?;
+        }
+        Ok(())
+    }
+
+    fn resolve_handler(&self, owner: &mut Value, prefix: &str) -> Result<(), FunctionResponseError> {
+        let repair_tasks = owner.get_mut("on_failure")
+            .and_then(|handler| handler.get_mut("tasks"))
+            .and_then(Value::as_array_mut);
+        for (index, task) in repair_tasks.into_iter().flatten().enumerate() {
+            self.resolve_task(task, &format!("{}on_failure.tasks[{}]", prefix, index))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,305 @@
+use std::sync::{Arc, OnceLock};
+use datalogic_rs::JsonLogic;
+use serde_json::{json, Value};
+
+use crate::models::errors::FunctionResponseError;
+use crate::models::operators::{Operator, OperatorRegistry};
+use crate::models::reference::ReferenceData;
+use crate::models::path::value_at;
+
+// Operators that evaluate their own arguments and cannot be handed pre-evaluated values
+const LAZY_OPERATORS: [&str; 10] = ["if", "?:", "and", "or", "map", "filter", "reduce", "all", "some", "none"];
+
+/// JsonLogic truthiness: null, false, 0, "" and empty arrays or objects are falsy.
+pub fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().unwrap_or(0.0) != 0.0,
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+/// Minimum and maximum argument count of a standard JsonLogic operator.
+fn standard_arity(op: &str) -> Option<(usize, Option<usize>)> {
+    Some(match op {
+        "var" => (0, Some(2)),
+        "missing" | "merge" | "cat" | "+" | "if" | "preserve" => (0, None),
+        "missing_some" | "==" | "===" | "!=" | "!==" | ">" | ">=" | "in" | "/" | "%" => (2, Some(2)),
+        "<" | "<=" => (2, Some(3)),
+        "map" | "filter" | "all" | "some" | "none" => (2, Some(2)),
+        "reduce" | "?:" => (3, Some(3)),
+        "substr" => (2, Some(3)),
+        "!" | "!!" | "log" => (1, Some(1)),
+        "-" => (1, Some(2)),
+        "and" | "or" | "*" | "max" | "min" => (1, None),
+        _ => return None,
+    })
+}
+
+/// A rule checked and resolved by `RuleEvaluator::compile`, reusable across messages and threads.
+#[derive(Clone)]
+pub struct CompiledRule {
+    source: Value,
+    node: Node,
+}
+
+#[derive(Clone)]
+enum Node {
+    /// Subtree using only standard operators, evaluated by `JsonLogic` as is
+    Standard(Value),
+    Preserve(Value),
+    Array(Vec<Node>),
+    Lazy(String, Vec<Node>),
+    Custom(String, Operator, Vec<Node>),
+    /// `log`, which `JsonLogic` does not provide: its argument, unchanged
+    Log(Box<Node>),
+    Path(String, Vec<Node>),
+    /// A standard operator over evaluated arguments, with its rule reading them by index
+    Operation(Value, Vec<Node>),
+}
+
+impl CompiledRule {
+    pub fn source(&self) -> &Value {
+        &self.source
+    }
+
+    /// The `var` paths the rule reads, with their values in `data` (null when absent). Paths
+    /// computed at evaluation, and the ones inside iterating operators that read array items,
+    /// are left out.
+    pub fn inputs(&self, data: &Value) -> Vec<(String, Value)> {
+        let mut paths = Vec::new();
+        var_paths(&self.source, &mut paths);
+        paths.into_iter()
+            .map(|path| {
+                let value = value_at(data, &path).cloned().unwrap_or(Value::Null);
+                (path, value)
+            })
+            .collect()
+    }
+}
+
+fn var_paths(rule: &Value, paths: &mut Vec<String>) {
+    let (op, args) = match rule {
+        Value::Array(items) => return items.iter().for_each(|item| var_paths(item, paths)),
+        Value::Object(map) if map.len() == 1 => map.iter().next().unwrap(),
+        _ => return,
+    };
+    let path = match args {
+        Value::Array(items) => items.first(),
+        other => Some(other),
+    };
+    match op.as_str() {
+        "preserve" => {}
+        "var" => {
+            let path = match path {
+                Some(Value::String(path)) => path.clone(),
+                Some(Value::Number(index)) => index.to_string(),
+                _ => return,
+            };
+            if !path.is_empty() && !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        // Only the array argument reads the rule data; the others read its items
+        "map" | "filter" | "reduce" | "all" | "some" | "none" => if let Some(array) = path {
+            var_paths(array, paths);
+        },
+        _ => var_paths(args, paths),
+    }
+}
+
+impl std::fmt::Debug for CompiledRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("CompiledRule").field(&self.source).finish()
+    }
+}
+
+/// JsonLogic evaluation with the operators of an `OperatorRegistry` on top of the standard ones.
+/// Rules using only standard operators are evaluated by `JsonLogic` unchanged.
+#[derive(Clone)]
+pub struct RuleEvaluator {
+    logic: JsonLogic,
+    operators: Arc<OperatorRegistry>,
+    reference_data: Option<Arc<ReferenceData>>,
+}
+
+impl Default for RuleEvaluator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RuleEvaluator {
+    /// Evaluator with the standard operator library.
+    pub fn new() -> Self {
+        Self::with_operators(OperatorRegistry::standard())
+    }
+
+    /// Evaluator with the standard operator library, built once and shared.
+    pub fn shared() -> &'static RuleEvaluator {
+        static SHARED: OnceLock<RuleEvaluator> = OnceLock::new();
+        SHARED.get_or_init(RuleEvaluator::new)
+    }
+
+    pub fn with_operators(operators: OperatorRegistry) -> Self {
+        Self { logic: JsonLogic::new(), operators: Arc::new(operators), reference_data: None }
+    }
+
+    /// Evaluator whose rules can query `reference_data` through the `lookup` operator.
+    pub fn with_reference_data(mut operators: OperatorRegistry, reference_data: Arc<ReferenceData>) -> Result<Self, FunctionResponseError> {
+        reference_data.register(&mut operators)?;
+        Ok(Self { logic: JsonLogic::new(), operators: Arc::new(operators), reference_data: Some(reference_data) })
+    }
+
+    pub fn operators(&self) -> &OperatorRegistry {
+        &self.operators
+    }
+
+    pub fn reference_data(&self) -> Option<&Arc<ReferenceData>> {
+        self.reference_data.as_ref()
+    }
+
+    fn uses_operators(&self, rule: &Value) -> bool {
+        match rule {
+            Value::Object(map) => map.iter().any(|(op, args)| op != "preserve" && (op == "log" || self.operators.contains(op) || self.uses_operators(args))),
+            Value::Array(items) => items.iter().any(|item| self.uses_operators(item)),
+            _ => false,
+        }
+    }
+
+    /// Checks every operator of `rule` is known and called with a valid number of arguments, and
+    /// resolves it for repeated evaluation.
+    pub fn compile(&self, rule: &Value) -> Result<CompiledRule, String> {
+        Ok(CompiledRule { source: rule.clone(), node: self.compile_node(rule)? })
+    }
+
+    fn compile_node(&self, rule: &Value) -> Result<Node, String> {
+        let (op, args) = match rule {
+            Value::Array(items) => {
+                let nodes = items.iter().map(|item| self.compile_node(item)).collect::<Result<Vec<_>, _>>()?;
+                return Ok(match self.uses_operators(rule) {
+                    true => Node::Array(nodes),
+                    false => Node::Standard(rule.clone()),
+                });
+            }
+            Value::Object(map) if map.len() == 1 => map.iter().next().unwrap(),
+            Value::Object(_) => return Err(format!("Invalid rule {}: expected one operator", rule)),
+            _ => return Ok(Node::Standard(rule.clone())),
+        };
+        if op == "preserve" {
+            return Ok(Node::Preserve(args.clone()));
+        }
+        let args: Vec<&Value> = match args {
+            Value::Array(items) => items.iter().collect(),
+            other => vec![other],
+        };
+        let nodes = args.iter().map(|arg| self.compile_node(arg)).collect::<Result<Vec<_>, _>>()?;
+
+        let operator = self.operators.get(op);
+        if operator.is_none() {
+            let (min, max) = standard_arity(op).ok_or_else(|| format!("Unknown operator {}", op))?;
+            if nodes.len() < min || max.is_some_and(|max| nodes.len() > max) {
+                return Err(format!("Operator {} called with {} arguments", op, nodes.len()));
+            }
+        }
+        if !self.uses_operators(rule) {
+            return Ok(Node::Standard(rule.clone()));
+        }
+        Ok(match (operator, op.as_str()) {
+            (Some(operator), _) => Node::Custom(op.clone(), Arc::clone(operator), nodes),
+            (None, "log") => Node::Log(Box::new(nodes.into_iter().next().unwrap())),
+            (None, "var" | "missing" | "missing_some") => Node::Path(op.clone(), nodes),
+            (None, _) if LAZY_OPERATORS.contains(&op.as_str()) => Node::Lazy(op.clone(), nodes),
+            (None, _) => {
+                let references: Vec<Value> = (0..nodes.len()).map(|i| json!({"var": i})).collect();
+                Node::Operation(json!({op: references}), nodes)
+            }
+        })
+    }
+
+    pub fn apply(&self, rule: &Value, data: &Value) -> Result<Value, String> {
+        self.evaluate(&self.compile(rule)?, data)
+    }
+
+    pub fn evaluate(&self, rule: &CompiledRule, data: &Value) -> Result<Value, String> {
+        self.evaluate_node(&rule.node, data)
+    }
+
+    fn evaluate_all(&self, nodes: &[Node], data: &Value) -> Result<Vec<Value>, String> {
+        nodes.iter().map(|node| self.evaluate_node(node, data)).collect()
+    }
+
+    fn evaluate_node(&self, node: &Node, data: &Value) -> Result<Value, String> {
+        match node {
+            Node::Standard(rule) => self.logic.apply(rule, data).map_err(|e| format!("{:?}", e)),
+            Node::Preserve(value) => Ok(value.clone()),
+            Node::Array(items) => self.evaluate_all(items, data).map(Value::Array),
+            Node::Lazy(op, args) => self.evaluate_lazy(op, args, data),
+            Node::Custom(op, operator, args) => operator(&self.evaluate_all(args, data)?).map_err(|e| format!("{}: {}", op, e)),
+            Node::Log(arg) => self.evaluate_node(arg, data),
+            // Path operators read the original data with the evaluated paths
+            Node::Path(op, args) => self.logic.apply(&json!({op: self.evaluate_all(args, data)?}), data)
+                .map_err(|e| format!("{:?}", e)),
+            // Evaluated values are passed by reference so they are not read as rules again
+            Node::Operation(rule, args) => self.logic.apply(rule, &Value::Array(self.evaluate_all(args, data)?))
+                .map_err(|e| format!("{:?}", e)),
+        }
+    }
+
+    fn evaluate_lazy(&self, op: &str, args: &[Node], data: &Value) -> Result<Value, String> {
+        let null = Node::Standard(Value::Null);
+        let arg = |index: usize| args.get(index).unwrap_or(&null);
+        match op {
+            "if" | "?:" => {
+                let mut index = 0;
+                while index + 1 < args.len() {
+                    if is_truthy(&self.evaluate_node(&args[index], data)?) {
+                        return self.evaluate_node(&args[index + 1], data);
+                    }
+                    index += 2;
+                }
+                match args.get(index) {
+                    Some(otherwise) => self.evaluate_node(otherwise, data),
+                    None => Ok(Value::Null),
+                }
+            }
+            "and" | "or" => {
+                let mut result = Value::Bool(op == "and");
+                for arg in args {
+                    result = self.evaluate_node(arg, data)?;
+                    if is_truthy(&result) == (op == "or") {
+                        break;
+                    }
+                }
+                Ok(result)
+            }
+            "reduce" => {
+                let items = self.evaluate_node(arg(0), data)?;
+                let mut accumulator = self.evaluate_node(arg(2), data)?;
+                for item in items.as_array().into_iter().flatten() {
+                    accumulator = self.evaluate_node(arg(1), &json!({"current": item, "accumulator": accumulator}))?;
+                }
+                Ok(accumulator)
+            }
+            _ => {
+                let items = self.evaluate_node(arg(0), data)?;
+                let items = items.as_array().cloned().unwrap_or_default();
+                let mut results = Vec::with_capacity(items.len());
+                for item in items {
+                    let result = self.evaluate_node(arg(1), &item)?;
+                    results.push((item, result));
+                }
+                Ok(match op {
+                    "map" => Value::Array(results.into_iter().map(|(_, result)| result).collect()),
+                    "filter" => Value::Array(results.into_iter().filter(|(_, r)| is_truthy(r)).map(|(item, _)| item).collect()),
+                    "all" => Value::Bool(!results.is_empty() && results.iter().all(|(_, r)| is_truthy(r))),
+                    "some" => Value::Bool(results.iter().any(|(_, r)| is_truthy(r))),
+                    _ => Value::Bool(!results.iter().any(|(_, r)| is_truthy(r))),
+                })
+            }
+        }
+    }
+}
@@ -1,17 +1,24 @@
 use std::fs::File;
 use std::io::{BufReader, BufRead};
+use std::sync::OnceLock;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use time::OffsetDateTime;
 use sonyflake::Sonyflake;
 use quick_xml::de::from_reader;
 
-use datalogic_rs::JsonLogic;
+use open_payments_iso20022::document::Document;
 
 use crate::models::payload::*;
+use crate::models::reference::record_lookups;
 use crate::models::auditlog::*;
 use crate::models::errors::FunctionResponseError;
 use crate::models::iso20022::ISO20022Message;
+use crate::models::stream::TransactionStream;
+use crate::models::logic::{is_truthy, CompiledRule, RuleEvaluator};
+use crate::models::executor::MergeConflict;
+use crate::models::trace::{RuleTracer, TraceConfig};
+use crate::models::path::{child_mut, remove_at, set_at, value_at, value_mut_at};
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Progress {
@@ -19,29 +26,143 @@ pub struct Progress {
 
     pub workflow_id: String,
 
+    /// Version of the workflow selected by a `WorkflowRegistry`
+    #[serde(default)]
+    pub workflow_version: Option<u16>,
+
     pub prev_task: String,
     
     pub prev_status_code: Option<StatusCode>,
     
     #[serde(with = "time::serde::iso8601")]
     pub timestamp: OffsetDateTime,
+
+    /// Tasks of the workflow that completed, in order, for `WorkflowExecutor::resume`
+    #[serde(default)]
+    pub completed_tasks: Vec<CompletedTask>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CompletedTask {
+    pub task_id: String,
+
+    /// `CompiledTask::fingerprint` of the task definition that ran
+    pub fingerprint: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum MessageStatus {
     Recieved,
     Processing,
+    /// A task failed and is waiting to run again
+    Retrying,
+    /// Repair tasks are running after a task failed
+    Repairing,
+    /// Completed tasks are being undone after the workflow failed
+    Compensating,
     Completed,
     Failed,
+    CancellationPending,
+    Cancelled,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
 pub struct EnrichmentConfig {
     pub field: String,
 
+    /// Value for the operation; unused by `Remove` and `Rename`
+    #[serde(default)]
     pub rule: Value,
 
     pub description: Option<String>,
+
+    /// Entry is applied only when this rule is truthy
+    #[serde(default)]
+    pub condition: Option<Value>,
+
+    #[serde(default)]
+    pub operation: EnrichmentOperation,
+
+    #[serde(default)]
+    pub on_failure: FailurePolicy,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EnrichmentOperation {
+    #[default]
+    Set,
+    /// Sets the field only when it has no value yet
+    SetIfAbsent,
+    Remove,
+    /// Appends the rule result to the array at the field, creating it when missing
+    Append,
+    /// Shallow merges the rule result object into the object at the field
+    Merge,
+    /// Moves the value at `from` to the field
+    Rename { from: String },
+}
+
+/// What happens when an entry's condition, rule or operation fails.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicy {
+    /// Rolls back the whole enrichment
+    #[default]
+    Abort,
+    Skip,
+    /// Sets the field to this value
+    Default(Value),
+}
+
+type FieldWrites = Vec<(String, Option<Value>)>;
+
+/// Enrichment configs with their rules compiled once, to apply to many messages from any thread.
+#[derive(Clone)]
+pub struct CompiledEnrichment {
+    logic: RuleEvaluator,
+    entries: Vec<CompiledEnrichmentEntry>,
+}
+
+#[derive(Clone)]
+struct CompiledEnrichmentEntry {
+    config: EnrichmentConfig,
+    rule: Result<CompiledRule, String>,
+    condition: Option<Result<CompiledRule, String>>,
+}
+
+impl CompiledEnrichment {
+    /// Compiles every rule and condition of `config`, failing with all invalid ones.
+    pub fn new(logic: &RuleEvaluator, config: Vec<EnrichmentConfig>) -> Result<Self, FunctionResponseError> {
+        let enrichment = Self::unchecked(logic, config);
+        let errors: Vec<String> = enrichment.entries.iter().enumerate()
+            .flat_map(|(index, entry)| [
+                entry.rule.as_ref().err().map(|e| format!("[{}].rule: {}", index, e)),
+                entry.condition.as_ref().and_then(|c| c.as_ref().err()).map(|e| format!("[{}].condition: {}", index, e)),
+            ])
+            .flatten()
+            .collect();
+        if !errors.is_empty() {
+            return Err(FunctionResponseError::new("Enrichment".to_string(), 400, format!("Invalid enrichment rules: {}", errors.join("; "))));
+        }
+        Ok(enrichment)
+    }
+
+    /// Compilation errors are kept and reported when the entry is applied, under its failure policy.
+    fn unchecked(logic: &RuleEvaluator, config: Vec<EnrichmentConfig>) -> Self {
+        let entries = config.into_iter()
+            .map(|config| CompiledEnrichmentEntry {
+                rule: logic.compile(&config.rule),
+                condition: config.condition.as_ref().map(|condition| logic.compile(condition)),
+                config,
+            })
+            .collect();
+        Self { logic: logic.clone(), entries }
+    }
+
+    pub fn configs(&self) -> impl Iterator<Item = &EnrichmentConfig> {
+        self.entries.iter().map(|entry| &entry.config)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -50,7 +171,7 @@ pub enum StatusCode {
     Failure,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Message {
     #[serde(rename = "id")]
     id: u64,
@@ -72,7 +193,74 @@ pub struct Message {
     audit: Vec<AuditLog>,
 
     #[serde(skip)]
-    transaction_changes: Option<Vec<(String, Value)>>,
+    transaction_changes: Option<Vec<(String, Option<Value>)>>,
+
+    #[serde(skip)]
+    document: OnceLock<Document>,
+
+    /// `payments` as the rule context shows them, built on first use
+    #[serde(skip)]
+    payments_view: OnceLock<Value>,
+
+    /// Array element a `Foreach` task is processing: its path and index
+    #[serde(skip)]
+    element: Option<(String, usize)>,
+}
+
+fn typed_document(document: &Value) -> Result<Document, FunctionResponseError> {
+    // The derived deserializers nest deeply, e.g. for pacs.004, and need more stack than a
+    // default thread has in unoptimised builds: the stack grows on demand instead
+    let mut deserializer = serde_stacker::Deserializer::new(document);
+    deserializer.red_zone = 256 * 1024;
+    Document::deserialize(deserializer).map_err(|e| FunctionResponseError::new(
+        "Document".to_string(),
+        400,
+        format!("ISO20022 document conversion error: {:?}", e)
+    ))
+}
+
+/// Messages are equal on their serialized fields; the typed document cache and the state of a
+/// running task are left out.
+impl PartialEq for Message {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.parent_id == other.parent_id
+            && self.payload == other.payload
+            && self.tenant == other.tenant
+            && self.origin == other.origin
+            && self.data == other.data
+            && self.metadata == other.metadata
+            && self.progress == other.progress
+            && self.audit == other.audit
+    }
+}
+
+/// Leaf paths of `new` that differ from `old`, `None` for removed ones. Arrays are leaves.
+fn data_changes(old: &Value, new: &Value, path: &str, changes: &mut FieldWrites) {
+    let child = |key: &str| match path {
+        "" => key.to_string(),
+        _ => format!("{}.{}", path, key),
+    };
+    let empty = serde_json::Map::new();
+    match (old, new) {
+        (Value::Object(_) | Value::Null, Value::Object(new)) => {
+            let old = old.as_object().unwrap_or(&empty);
+            for (key, value) in new {
+                data_changes(old.get(key).unwrap_or(&Value::Null), value, &child(key), changes);
+            }
+            for key in old.keys().filter(|key| !new.contains_key(*key)) {
+                changes.push((child(key), None));
+            }
+        }
+        // Elements are compared one by one while the array keeps its length
+        (Value::Array(old), Value::Array(new)) if old.len() == new.len() && old != new => {
+            for (index, (old, new)) in old.iter().zip(new).enumerate() {
+                data_changes(old, new, &child(&index.to_string()), changes);
+            }
+        }
+        _ if old == new => {}
+        _ => changes.push((path.to_string(), Some(new.clone()))),
+    }
 }
 
 impl Message {
@@ -84,6 +272,15 @@ impl Message {
         &self.data
     }
 
+    /// Typed view of `data.document`, rebuilt from the JSON after it has been changed.
+    pub fn document(&self) -> Result<&Document, FunctionResponseError> {
+        if let Some(document) = self.document.get() {
+            return Ok(document);
+        }
+        let document = typed_document(&self.data["document"])?;
+        Ok(self.document.get_or_init(|| document))
+    }
+
     pub fn id(&self) -> u64 {
         self.id
     }
@@ -112,6 +309,11 @@ impl Message {
         &self.tenant
     }
 
+    pub fn set_status(&mut self, status: MessageStatus) {
+        self.progress.status = status;
+        self.progress.timestamp = OffsetDateTime::now_utc();
+    }
+
     fn transaction_begin(&mut self, workflow: String, task: String) {
         self.progress.workflow_id = workflow;
         self.progress.prev_task = task;
@@ -120,21 +322,13 @@ impl Message {
 
     fn transaction_rollback(&mut self) {
         if let Some(changes) = self.transaction_changes.take() {
-            for (field_path, old_value) in changes.iter().rev() {
-                let parts: Vec<&str> = field_path.split('.').collect();
-                let mut current = &mut self.data;
-                
-                // Navigate to the parent object
-                for part in parts.iter().take(parts.len() - 1) {
-                    if !current[part].is_object() {
-                        break;
-                    }
-                    current = current.get_mut(part).unwrap();
-                }
-                
-                // Restore the old value
-                if let Some(last_part) = parts.last() {
-                    current[*last_part] = old_value.clone();
+            self.data_changed();
+            for (field_path, old_value) in changes.into_iter().rev() {
+                // Restore the old value, or remove a field that did not exist
+                let path = field_path.strip_prefix("data.").unwrap_or(&field_path);
+                match old_value {
+                    Some(old_value) => set_at(&mut self.data, path, old_value),
+                    None => remove_at(&mut self.data, path),
                 }
             }
         }
@@ -149,6 +343,7 @@ impl Message {
     }
 
     fn update(&mut self, field_path: &str, new_value: Value) -> Result<(), FunctionResponseError> {
+        let field_path = self.element_field(field_path);
         let parts: Vec<&str> = field_path.split('.').collect();
         
         if parts[0] != "data" {
@@ -159,21 +354,37 @@ impl Message {
             ));
         }
 
-        let mut current = &mut self.data;
-        for (i, part) in parts.iter().enumerate().skip(1) {
-            if i == parts.len() - 1 {
-                // Store old value for potential rollback
-                if let Some(changes) = &mut self.transaction_changes {
-                    changes.push((field_path.to_string(), current[part].clone()));
-                }
-                current[part] = new_value.clone();
-            } else {
-                if !current[part].is_object() {
-                    current[part] = json!({});
-                }
-                current = current.get_mut(part).unwrap();
+        self.data_changed();
+        if parts.len() > 1 {
+            // Store old value for potential rollback, None when the field is absent
+            if let Some(changes) = &mut self.transaction_changes {
+                let old_value = value_mut_at(&mut self.data, &field_path["data.".len()..]).map(|value| value.clone());
+                changes.push((field_path.to_string(), old_value));
             }
+            let field = parts[1..].iter().try_fold(&mut self.data, |current, part| child_mut(current, part))
+                .map_err(|reason| FunctionResponseError::new("Update".to_string(), 400, format!("Cannot set {}: {}", field_path, reason)))?;
+            *field = new_value;
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, field_path: &str) -> Result<(), FunctionResponseError> {
+        let field_path = &self.element_field(field_path);
+        if !field_path.starts_with("data.") {
+            return Err(FunctionResponseError::new(
+                "Update".to_string(),
+                400,
+                "Invalid field path".to_string()
+            ));
         }
+        let Some(old_value) = value_mut_at(&mut self.data, &field_path["data.".len()..]).map(|value| value.clone()) else {
+            return Ok(());
+        };
+        self.data_changed();
+        if let Some(changes) = &mut self.transaction_changes {
+            changes.push((field_path.to_string(), Some(old_value)));
+        }
+        remove_at(&mut self.data, &field_path["data.".len()..]);
         Ok(())
     }
 
@@ -210,49 +421,113 @@ impl Message {
             progress: Progress {
                 status: MessageStatus::Recieved,
                 workflow_id: workflow.to_string(),
+                workflow_version: None,
                 prev_task: task.to_string(),
                 prev_status_code: Some(StatusCode::Success),
                 timestamp: OffsetDateTime::now_utc(),
+                completed_tasks: Vec::new(),
             },
             audit: vec![audit],
             transaction_changes: Some(Vec::new()),
+            document: OnceLock::new(),
+            payments_view: OnceLock::new(),
+            element: None,
         }
     }
     
     pub fn enrich(&mut self, config: Vec<EnrichmentConfig>, data: serde_json::Value, description: Option<String>, workflow: String, task: String) -> Result<(), FunctionResponseError> {
+        self.enrich_with(RuleEvaluator::shared(), config, data, description, workflow, task)
+    }
+
+    /// Enrichment evaluating the rules with `logic`, e.g. one carrying application operators.
+    pub fn enrich_with(&mut self, logic: &RuleEvaluator, config: Vec<EnrichmentConfig>, data: serde_json::Value, description: Option<String>, workflow: String, task: String) -> Result<(), FunctionResponseError> {
+        self.enrich_compiled(&CompiledEnrichment::unchecked(logic, config), data, description, workflow, task)
+    }
+
+    /// Enrichment with configs compiled ahead of time, the fast path for high volumes.
+    pub fn enrich_compiled(&mut self, enrichment: &CompiledEnrichment, data: serde_json::Value, description: Option<String>, workflow: String, task: String) -> Result<(), FunctionResponseError> {
+        self.enrich_traced(enrichment, data, description, workflow, task, None)
+    }
+
+    /// `enrich_compiled`, attaching the trace of every rule and condition evaluated to the audit
+    /// entry when `trace` covers the message tenant.
+    pub fn enrich_traced(&mut self, enrichment: &CompiledEnrichment, data: serde_json::Value, description: Option<String>, workflow: String, task: String, trace: Option<&TraceConfig>) -> Result<(), FunctionResponseError> {
+        let logic = &enrichment.logic;
+        let mut tracer = RuleTracer::new(trace, self);
         let start_time = OffsetDateTime::now_utc();
-        let logic = JsonLogic::new();
         let mut changes = Vec::new();
         
         // Begin transaction
         self.transaction_begin(workflow.clone(), task.clone());
-
-        for cfg in config {
-            let value = match logic.apply(&cfg.rule, &data) {
-                Ok(v) => v,
-                Err(e) => {
-                    // Rollback on error
-                    self.transaction_rollback();
-                    return Err(FunctionResponseError::new(
-                        "Enrichment".to_string(),
-                        400,
-                        format!("Rule application failed: {:?}", e)
-                    ));
+        let mut context = self.rule_context(data);
+
+        for (index, entry) in enrichment.entries.iter().enumerate() {
+            let cfg = &entry.config;
+            let field_path = self.element_field(&cfg.field);
+            let old_value = self.field(&cfg.field).cloned();
+            let mut reason = cfg.description.clone().unwrap_or_else(|| match &cfg.operation {
+                EnrichmentOperation::Set => format!("Enriched field {}", cfg.field),
+                EnrichmentOperation::SetIfAbsent => format!("Enriched absent field {}", cfg.field),
+                EnrichmentOperation::Remove => format!("Removed field {}", cfg.field),
+                EnrichmentOperation::Append => format!("Appended to field {}", cfg.field),
+                EnrichmentOperation::Merge => format!("Merged into field {}", cfg.field),
+                EnrichmentOperation::Rename { from } => format!("Moved field {} to {}", from, cfg.field),
+            });
+
+            let (writes, datasets) = record_lookups(|| self.enrichment_writes(logic, entry, &context, index, &mut tracer));
+            let writes = match writes {
+                Ok(Some(writes)) => writes,
+                Ok(None) => {
+                    changes.push(ChangeLog::new(field_path.clone(), format!("{}: skipped, condition not met", reason), old_value.clone(), old_value));
+                    continue;
                 }
+                Err(e) => match &cfg.on_failure {
+                    FailurePolicy::Abort => {
+                        // Rollback on error
+                        self.transaction_rollback();
+                        return Err(FunctionResponseError::new("Enrichment".to_string(), 400, e));
+                    }
+                    FailurePolicy::Skip => {
+                        changes.push(ChangeLog::new(field_path.clone(), format!("{}: skipped after failure: {}", reason, e), old_value.clone(), old_value));
+                        continue;
+                    }
+                    FailurePolicy::Default(value) => {
+                        reason = format!("{}: default value after failure: {}", reason, e);
+                        vec![(cfg.field.clone(), Some(value.clone()))]
+                    }
+                },
             };
 
-            // Update with transaction support
-            if let Err(e) = self.update(&cfg.field, value.clone()) {
-                self.transaction_rollback();
-                return Err(e);
+            // Update with transaction support; later rules of the same call see the result
+            for (field, value) in writes {
+                let result = match &value {
+                    Some(value) => self.update(&field, value.clone()),
+                    None => self.remove(&field),
+                };
+                if let Err(e) = result {
+                    self.transaction_rollback();
+                    return Err(e);
+                }
+                let target = self.element_field(&field);
+                match value {
+                    Some(value) => set_at(&mut context, &target, value),
+                    None => remove_at(&mut context, &target),
+                }
+            }
+            if self.element.is_some() {
+                context["item"] = self.field("item").cloned().unwrap_or(Value::Null);
             }
 
-            // Record change for audit
+            // Record change for audit, with the reference data versions the rule relied on
+            if !datasets.is_empty() {
+                reason = format!("{} using {}", reason, datasets.join(", "));
+            }
+            let new_value = self.field(&cfg.field).cloned();
             changes.push(ChangeLog::new(
-                cfg.field.to_string(),
-                cfg.description.unwrap_or_else(|| format!("Enriched field {}", cfg.field)),
-                None,
-                Some(value)
+                field_path,
+                reason,
+                old_value,
+                new_value
             ));
         }
 
@@ -266,20 +541,246 @@ impl Message {
             start_time,
             description.unwrap_or_else(|| "Enrichment applied".to_string()),
             changes
-        );
+        ).with_trace(tracer.finish());
         self.audit.push(audit_log);
         Ok(())
     }
 
-    pub fn parse(&mut self, description: Option<String>, workflow: String, task: String) -> Result<(), FunctionResponseError> {
+    fn field(&self, field_path: &str) -> Option<&Value> {
+        value_at(&self.data, self.element_field(field_path).strip_prefix("data.")?)
+    }
+
+    /// `field_path` with a leading `item` standing for the bound `Foreach` element.
+    fn element_field(&self, field_path: &str) -> String {
+        match (&self.element, field_path.strip_prefix("item")) {
+            (Some((path, index)), Some(rest)) if rest.is_empty() || rest.starts_with('.') => format!("{}.{}{}", path, index, rest),
+            _ => field_path.to_string(),
+        }
+    }
+
+    /// The fields an enrichment entry writes, `None` values being removals, or `None` when its
+    /// condition is not met.
+    fn enrichment_writes(&self, logic: &RuleEvaluator, entry: &CompiledEnrichmentEntry, context: &Value, index: usize, tracer: &mut RuleTracer) -> Result<Option<FieldWrites>, String> {
+        let cfg = &entry.config;
+        if let Some(condition) = &entry.condition {
+            let condition = condition.as_ref().map_err(|e| format!("Condition evaluation failed: {}", e))?;
+            let result = logic.evaluate(condition, context);
+            tracer.record(&format!("input[{}].condition", index), condition, context, &result);
+            if !is_truthy(&result.map_err(|e| format!("Condition evaluation failed: {}", e))?) {
+                return Ok(None);
+            }
+        }
+        let mut rule = || {
+            let rule = entry.rule.as_ref().map_err(|e| format!("Rule application failed: {}", e))?;
+            let result = logic.evaluate(rule, context);
+            tracer.record(&format!("input[{}].rule", index), rule, context, &result);
+            result.map_err(|e| format!("Rule application failed: {}", e))
+        };
+        let current = self.field(&cfg.field);
+
+        let value = match &cfg.operation {
+            EnrichmentOperation::Set => rule()?,
+            EnrichmentOperation::SetIfAbsent => match current {
+                Some(_) => return Ok(Some(Vec::new())),
+                None => rule()?,
+            },
+            EnrichmentOperation::Remove => return Ok(Some(vec![(cfg.field.clone(), None)])),
+            EnrichmentOperation::Append => match current {
+                None => json!([rule()?]),
+                Some(Value::Array(items)) => {
+                    let mut items = items.clone();
+                    items.push(rule()?);
+                    Value::Array(items)
+                }
+                Some(_) => return Err(format!("Cannot append to {}: not an array", cfg.field)),
+            },
+            EnrichmentOperation::Merge => {
+                let Value::Object(additions) = rule()? else {
+                    return Err(format!("Cannot merge into {}: rule result is not an object", cfg.field));
+                };
+                let mut merged = match current {
+                    None => serde_json::Map::new(),
+                    Some(Value::Object(map)) => map.clone(),
+                    Some(_) => return Err(format!("Cannot merge into {}: not an object", cfg.field)),
+                };
+                merged.extend(additions);
+                Value::Object(merged)
+            }
+            EnrichmentOperation::Rename { from } => {
+                let value = self.field(from).cloned().ok_or_else(|| format!("Cannot move {}: no value", from))?;
+                return Ok(Some(vec![(from.clone(), None), (cfg.field.clone(), Some(value))]));
+            }
+        };
+        Ok(Some(vec![(cfg.field.clone(), Some(value))]))
+    }
+
+    /// Context the enrichment rules are evaluated against: the message under `data`, `metadata`,
+    /// `tenant`, `origin` and `progress`, the normalized `payments` of a payment message (empty
+    /// otherwise) and the caller supplied values under `input`. While a `Foreach` task runs, the
+    /// current element is under `item` and its position under `index`. The input keys are also
+    /// kept at the root, as rules written before the message context read them there; the
+    /// message keys win over input keys of the same name.
+    pub fn rule_context(&self, input: Value) -> Value {
+        let payments = self.payments_view.get_or_init(|| self.payments().ok()
+            .and_then(|payments| serde_json::to_value(payments).ok())
+            .unwrap_or_else(|| json!([])));
+        let mut context = json!({
+            "data": self.data,
+            "metadata": self.metadata,
+            "tenant": self.tenant,
+            "origin": self.origin,
+            "progress": self.progress,
+            "payments": payments,
+            "input": input,
+        });
+        if let Some((_, index)) = &self.element {
+            context["item"] = self.field("item").cloned().unwrap_or(Value::Null);
+            context["index"] = json!(index);
+        }
+        if let (Value::Object(context), Some(input)) = (&mut context, input.as_object()) {
+            for (key, value) in input {
+                context.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+        context
+    }
+
+    /// Binds the element at `index` of the array at `path`, or unbinds it with `None`.
+    pub(crate) fn bind_element(&mut self, element: Option<(&str, usize)>) {
+        self.element = element.map(|(path, index)| (path.to_string(), index));
+    }
+
+    /// Applies the changes each succeeded branch made to `data` since `base` and appends the
+    /// audit entries of every branch, failed ones included, in branch order. A field changed
+    /// differently by two branches is a conflict; arrays are merged element by element unless
+    /// a branch changed their length.
+    pub(crate) fn merge_branches(&mut self, base: &Message, branches: Vec<(String, Message, bool)>) -> Result<(), Vec<MergeConflict>> {
+        let mut applied: Vec<(String, String, Option<Value>)> = Vec::new();
+        let mut conflicts: Vec<MergeConflict> = Vec::new();
+        for (task_id, branch, _) in branches.iter().filter(|(_, _, succeeded)| *succeeded) {
+            let mut changes = Vec::new();
+            data_changes(&base.data, &branch.data, "", &mut changes);
+            for (path, value) in changes {
+                let overlapping = applied.iter().find(|(other, _, other_value)| {
+                    let nested = path.starts_with(&format!("{}.", other)) || other.starts_with(&format!("{}.", path));
+                    nested || (*other == path && *other_value != value)
+                });
+                match overlapping {
+                    Some((_, other_task, _)) => conflicts.push(MergeConflict { path: path.clone(), tasks: vec![other_task.clone(), task_id.clone()] }),
+                    None => applied.push((path, task_id.clone(), value)),
+                }
+            }
+        }
+        if !conflicts.is_empty() {
+            return Err(conflicts);
+        }
+
+        self.data_changed();
+        for (path, _, value) in applied {
+            match value {
+                Some(value) => set_at(&mut self.data, &path, value),
+                None => remove_at(&mut self.data, &path),
+            }
+        }
+        // Progress points at the last task of the level, whichever branch finished last
+        self.progress.prev_status_code = Some(match branches.iter().all(|(_, _, succeeded)| *succeeded) {
+            true => StatusCode::Success,
+            false => StatusCode::Failure,
+        });
+        if let Some((task_id, _, _)) = branches.last() {
+            self.progress.prev_task = task_id.clone();
+        }
+        self.progress.timestamp = OffsetDateTime::now_utc();
+        for (_, branch, _) in branches {
+            self.audit.extend(branch.audit.into_iter().skip(base.audit.len()));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_workflow(&mut self, name: &str, version: u16) {
+        self.progress.workflow_id = name.to_string();
+        self.progress.workflow_version = Some(version);
+        self.progress.timestamp = OffsetDateTime::now_utc();
+    }
+
+    /// Fields of `data` changed since `base`, with their old and new values.
+    pub(crate) fn changes_since(&self, base: &Message) -> Vec<(String, Option<Value>, Option<Value>)> {
+        let mut changes = Vec::new();
+        data_changes(&base.data, &self.data, "", &mut changes);
+        changes.into_iter()
+            .map(|(path, new_value)| (path.clone(), value_at(&base.data, &path).cloned(), new_value))
+            .collect()
+    }
+
+    pub(crate) fn complete_task(&mut self, task_id: &str, fingerprint: &str) {
+        self.progress.completed_tasks.push(CompletedTask { task_id: task_id.to_string(), fingerprint: fingerprint.to_string() });
+    }
+
+    pub(crate) fn uncomplete_task(&mut self, task_id: &str) {
+        self.progress.completed_tasks.retain(|c| c.task_id != task_id);
+    }
+
+    pub(crate) fn clear_completed_tasks(&mut self) {
+        self.progress.completed_tasks.clear();
+    }
+
+    /// Points `Progress` back at `task` of `workflow`, e.g. once a sub-workflow returns.
+    pub(crate) fn set_task(&mut self, workflow: &str, task: &str) {
+        self.progress.workflow_id = workflow.to_string();
+        self.progress.prev_task = task.to_string();
+        self.progress.timestamp = OffsetDateTime::now_utc();
+    }
+
+    /// Drops the views derived from `data`, which has changed.
+    fn data_changed(&mut self) {
+        self.document = OnceLock::new();
+        self.payments_view = OnceLock::new();
+    }
+
+    /// Puts back `data` saved before a unit of work that failed.
+    pub(crate) fn restore_data(&mut self, data: Value) {
+        self.data = data;
+        self.data_changed();
+    }
+
+    /// Puts back the field at `path` of `data`, e.g. a `Foreach` element whose tasks failed.
+    pub(crate) fn restore_field(&mut self, path: &str, value: Value) {
+        set_at(&mut self.data, path.strip_prefix("data.").unwrap_or(path), value);
+        self.data_changed();
+    }
+
+    pub(crate) fn audit_mut(&mut self) -> &mut [AuditLog] {
+        &mut self.audit
+    }
+
+    pub(crate) fn push_audit(&mut self, audit: AuditLog) {
+        self.audit.push(audit);
+    }
+
+    /// Creates a message derived from this one, carrying `data` as an inline JSON payload.
+    pub(crate) fn child(&self, data: Value, workflow: String, task: String, message_alias: Option<String>) -> Message {
+        let mut child = Message::generated(data, self.tenant.clone(), self.origin.clone(), workflow, task, message_alias);
+        child.parent_id = Some(self.id.to_string());
+        child
+    }
+
+    /// A message built from already structured `data`, carried as an inline JSON payload.
+    pub(crate) fn generated(data: Value, tenant: String, origin: String, workflow: String, task: String, message_alias: Option<String>) -> Message {
+        let content = serde_json::to_vec(&data).unwrap();
+        let payload = Payload::new_inline(Some(content), PayloadFormat::Json, PayloadSchema::ISO20022, Encoding::Utf8);
+        let mut message = Message::new(payload, tenant, origin, workflow, task, message_alias);
+        message.data = data;
+        message
+    }
+
+    fn reader(&self) -> Result<Box<dyn BufRead + '_>, FunctionResponseError> {
         const BUFFER_SIZE: usize = 32 * 1024; // 32KB buffer
-        let start_time = OffsetDateTime::now_utc();
-        let buf_reader: Box<dyn BufRead> = if let Some(content) = self.payload.content() {
-            Box::new(BufReader::with_capacity(
+        if let Some(content) = self.payload.content() {
+            Ok(Box::new(BufReader::with_capacity(
                 BUFFER_SIZE,
                 content
-            ))
-        } else if let Some(ref url) = self.payload.url() {
+            )))
+        } else if let Some(url) = self.payload.url() {
             let file = File::open(url).map_err(|e| {
                 FunctionResponseError::new(
                     "Parse".to_string(),
@@ -287,20 +788,27 @@ impl Message {
                     format!("File open error: {:?}", e)
                 )
             })?;
-            Box::new(BufReader::with_capacity(BUFFER_SIZE, file))
+            Ok(Box::new(BufReader::with_capacity(BUFFER_SIZE, file)))
         } else {
-            return Err(FunctionResponseError::new(
+            Err(FunctionResponseError::new(
                 "Parse".to_string(),
                 400,
                 "No content or URL provided".to_string()
-            ));
-        };
+            ))
+        }
+    }
+
+    pub fn parse(&mut self, description: Option<String>, workflow: String, task: String) -> Result<(), FunctionResponseError> {
+        let start_time = OffsetDateTime::now_utc();
+        let buf_reader = self.reader()?;
 
         match from_reader::<_, ISO20022Message>(buf_reader) {
             Ok(message) => {
                 match message.validate() {
                     Ok(()) => {
-                        self.data = serde_json::to_value(message).unwrap();
+                        self.data = serde_json::to_value(&message).unwrap();
+                        self.payments_view = OnceLock::new();
+                        self.document = OnceLock::from(message.document);
                         let change_log = ChangeLog::new(
                             "data".to_string(),
                             "ISO20022 message parsed".to_string(),
@@ -333,4 +841,42 @@ impl Message {
             )),
         }
     }
+
+    /// Streams a bulk ISO20022 payload one transaction at a time instead of building the whole
+    /// document in memory. `handler` receives every single-transaction document (or its
+    /// validation error) in order and may stop the stream by returning an error.
+    /// `Message::data` is left untouched; the number of streamed transactions is returned.
+    pub fn parse_stream<F>(&mut self, description: Option<String>, workflow: String, task: String, mut handler: F) -> Result<usize, FunctionResponseError>
+    where
+        F: FnMut(Result<Value, FunctionResponseError>) -> Result<(), FunctionResponseError>,
+    {
+        let start_time = OffsetDateTime::now_utc();
+        let mut failed = 0;
+        let count = {
+            let mut stream = TransactionStream::new(self.reader()?);
+            for transaction in stream.by_ref() {
+                if transaction.is_err() {
+                    failed += 1;
+                }
+                handler(transaction)?;
+            }
+            stream.emitted()
+        };
+
+        let change_log = ChangeLog::new(
+            "data".to_string(),
+            format!("ISO20022 message streamed: {} transactions, {} failed validation", count, failed),
+            None,
+            None
+        );
+        let audit_log = AuditLog::new(
+            workflow,
+            task,
+            start_time,
+            description.unwrap_or_else(|| "ISO20022 message streamed".to_string()),
+            vec![change_log]
+        );
+        self.audit.push(audit_log);
+        Ok(count)
+    }
 }
@@ -5,4 +5,24 @@ pub mod message;
 pub mod task;
 pub mod workflow;
 pub mod errors;
-pub mod iso20022;
\ No newline at end of file
+pub mod iso20022;
+pub mod stream;
+pub mod path;
+pub mod amount;
+pub mod document;
+pub mod transform;
+pub mod status;
+pub mod returns;
+pub mod cancellation;
+pub mod statement;
+pub mod payment;
+pub mod profile;
+pub mod identifiers;
+pub mod operators;
+pub mod reference;
+pub mod logic;
+pub mod registry;
+pub mod executor;
+pub mod library;
+pub mod simulation;
+pub mod trace;
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use regex::Regex;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use time::{Date, Duration, OffsetDateTime, Weekday};
+use time::format_description::well_known::{Iso8601, Rfc3339};
+
+use crate::models::errors::{error, FunctionResponseError};
+use crate::models::identifiers::*;
+
+/// A custom JsonLogic operator, called with its evaluated arguments.
+pub type Operator = Arc<dyn Fn(&[Value]) -> Result<Value, String> + Send + Sync>;
+
+type StandardOperator = fn(&[Value]) -> Result<Value, String>;
+
+// Operators provided by JsonLogic itself, which cannot be replaced
+const JSONLOGIC_OPERATORS: [&str; 36] = [
+    "var", "==", "===", ">", "<", "and", "or", "!", "map", "filter", "reduce", "!=", "!==", ">=", "<=",
+    "?:", "!!", "if", "merge", "missing", "missing_some", "all", "none", "some", "preserve", "in", "cat",
+    "substr", "+", "*", "-", "/", "%", "max", "min", "log",
+];
+
+// Fixed point precision of the decimal operators
+const DECIMAL_PLACES: u32 = 10;
+
+// Business days `business_days_add` moves at most, about forty years
+const MAX_BUSINESS_DAYS: u64 = 10_000;
+
+// Patterns `regex_match` keeps compiled before the cache is cleared
+const REGEX_CACHE_SIZE: usize = 256;
+
+/// Custom operators available to JsonLogic rules, by name.
+#[derive(Clone, Default)]
+pub struct OperatorRegistry {
+    operators: HashMap<String, Operator>,
+}
+
+impl OperatorRegistry {
+    /// An empty registry, without the standard library.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The payment oriented standard library: identifier checks, business day and decimal
+    /// arithmetic, byte substrings, padding, regex matching, hashing, uuid and now.
+    pub fn standard() -> Self {
+        let mut registry = Self::new();
+        let operators: [(&str, StandardOperator); 18] = [
+            ("valid_iban", |args| Ok(json!(text(args, 0).is_ok_and(is_valid_iban)))),
+            ("valid_bic", |args| Ok(json!(text(args, 0).is_ok_and(is_valid_bic)))),
+            ("valid_lei", |args| Ok(json!(text(args, 0).is_ok_and(is_valid_lei)))),
+            ("valid_creditor_reference", |args| Ok(json!(text(args, 0).is_ok_and(is_valid_creditor_reference)))),
+            ("business_days_add", business_days_add),
+            ("amount_compare", amount_compare),
+            ("decimal_add", |args| decimal_fold(args, |a, b| a + b)),
+            ("decimal_sub", |args| decimal_fold(args, |a, b| a - b)),
+            ("decimal_round", decimal_round),
+            ("substr_bytes", substr_bytes),
+            ("pad_left", |args| pad(args, true)),
+            ("pad_right", |args| pad(args, false)),
+            ("regex_match", regex_match),
+            ("sha256", |args| Ok(json!(sha256(text(args, 0)?.as_bytes())))),
+            ("uuid", |_| Ok(json!(uuid()))),
+            ("now", now),
+            ("upper", |args| Ok(json!(text(args, 0)?.to_uppercase()))),
+            ("lower", |args| Ok(json!(text(args, 0)?.to_lowercase()))),
+        ];
+        for (name, operator) in operators {
+            registry.operators.insert(name.to_string(), Arc::new(operator));
+        }
+        registry
+    }
+
+    /// Adds or replaces a custom operator. The JsonLogic operators themselves cannot be replaced.
+    pub fn register<F>(&mut self, name: &str, operator: F) -> Result<(), FunctionResponseError>
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        if JSONLOGIC_OPERATORS.contains(&name) {
+            return Err(error("Rule", format!("Operator {} is a JsonLogic operator", name)));
+        }
+        self.operators.insert(name.to_string(), Arc::new(operator));
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Operator> {
+        self.operators.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.operators.contains_key(name)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.operators.keys().map(|name| name.as_str()).collect();
+        names.sort();
+        names
+    }
+}
+
+fn arg(args: &[Value], index: usize) -> Result<&Value, String> {
+    args.get(index).ok_or_else(|| format!("missing argument {}", index + 1))
+}
+
+fn text(args: &[Value], index: usize) -> Result<&str, String> {
+    arg(args, index)?.as_str().ok_or_else(|| format!("argument {} must be a string", index + 1))
+}
+
+fn integer(args: &[Value], index: usize) -> Result<i64, String> {
+    arg(args, index)?.as_i64().ok_or_else(|| format!("argument {} must be an integer", index + 1))
+}
+
+fn date(value: &str) -> Result<Date, String> {
+    Date::parse(value, &Iso8601::DEFAULT).map_err(|e| format!("invalid date {}: {}", value, e))
+}
+
+/// [date, days, holidays?]: moves `days` business days from `date`, skipping weekends and holidays.
+fn business_days_add(args: &[Value]) -> Result<Value, String> {
+    let mut current = date(text(args, 0)?)?;
+    let days = integer(args, 1)?;
+    if days.unsigned_abs() > MAX_BUSINESS_DAYS {
+        return Err(format!("argument 2 must be at most {} business days", MAX_BUSINESS_DAYS));
+    }
+    let holidays = args.get(2)
+        .and_then(|h| h.as_array())
+        .map(|h| h.iter().filter_map(|d| d.as_str()).map(date).collect::<Result<Vec<_>, _>>())
+        .transpose()?
+        .unwrap_or_default();
+
+    let step = if days < 0 { Duration::days(-1) } else { Duration::days(1) };
+    let mut remaining = days.abs();
+    while remaining > 0 {
+        current = current.checked_add(step).ok_or("date out of range")?;
+        if !matches!(current.weekday(), Weekday::Saturday | Weekday::Sunday) && !holidays.contains(&current) {
+            remaining -= 1;
+        }
+    }
+    Ok(json!(current.to_string()))
+}
+
+/// Exact fixed point value of a decimal number or string, in plain or exponent form.
+fn decimal(value: &Value) -> Result<i128, String> {
+    let text = match value {
+        Value::String(s) => s.trim().to_string(),
+        Value::Number(n) => n.to_string(),
+        _ => return Err(format!("{} is not a decimal", value)),
+    };
+    let (mantissa, exponent) = match text.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i64>().map_err(|_| format!("{} is not a decimal", text))?),
+        None => (text.as_str(), 0),
+    };
+    let (negative, digits) = match mantissa.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, mantissa),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if whole.is_empty() || !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+        return Err(format!("{} is not a decimal", text));
+    }
+    // Trailing zeros beyond the precision, as in 1.50000000000, lose nothing
+    let mut digits = format!("{}{}", whole, fraction);
+    let mut places = fraction.len() as i64 - exponent;
+    while places > DECIMAL_PLACES as i64 && digits.len() > 1 && digits.ends_with('0') {
+        digits.pop();
+        places -= 1;
+    }
+    if places > DECIMAL_PLACES as i64 {
+        return Err(format!("{} has more than {} decimal places", text, DECIMAL_PLACES));
+    }
+    let scaled = digits.parse::<i128>().ok()
+        .zip(u32::try_from(DECIMAL_PLACES as i64 - places).ok())
+        .and_then(|(digits, shift)| 10i128.checked_pow(shift).and_then(|unit| digits.checked_mul(unit)))
+        .ok_or_else(|| format!("{} is out of range", text))?;
+    Ok(if negative { -scaled } else { scaled })
+}
+
+/// Decimal string of a fixed point value with `places` decimals, or as few as needed.
+fn decimal_value(scaled: i128, places: Option<u32>) -> Value {
+    let scale = 10i128.pow(DECIMAL_PLACES);
+    let fraction = format!("{:0>width$}", (scaled % scale).abs(), width = DECIMAL_PLACES as usize);
+    let fraction = match places {
+        Some(places) => &fraction[..places as usize],
+        None => fraction.trim_end_matches('0'),
+    };
+    let sign = if scaled < 0 { "-" } else { "" };
+    match fraction.is_empty() {
+        true => json!(format!("{}{}", sign, (scaled / scale).abs())),
+        false => json!(format!("{}{}.{}", sign, (scaled / scale).abs(), fraction)),
+    }
+}
+
+/// [a, b]: -1, 0 or 1 as the amount `a` is below, equal to or above `b`, compared exactly.
+fn amount_compare(args: &[Value]) -> Result<Value, String> {
+    let ordering = decimal(arg(args, 0)?)?.cmp(&decimal(arg(args, 1)?)?);
+    Ok(json!(ordering as i8))
+}
+
+fn decimal_fold(args: &[Value], operation: fn(i128, i128) -> i128) -> Result<Value, String> {
+    let mut values = args.iter().map(decimal);
+    let first = values.next().ok_or("missing argument 1")??;
+    values.try_fold(first, |total, value| Ok(operation(total, value?))).map(|total| decimal_value(total, None))
+}
+
+/// [value, places]: rounds half away from zero, to a string with `places` decimals.
+fn decimal_round(args: &[Value]) -> Result<Value, String> {
+    let value = decimal(arg(args, 0)?)?;
+    let places = integer(args, 1)?.clamp(0, DECIMAL_PLACES as i64) as u32;
+    let unit = 10i128.pow(DECIMAL_PLACES - places);
+    let rounded = (value.abs() + unit / 2) / unit * unit;
+    Ok(decimal_value(if value < 0 { -rounded } else { rounded }, Some(places)))
+}
+
+/// [text, start, length?]: substring by UTF-8 bytes, shrunk so no character is split.
+fn substr_bytes(args: &[Value]) -> Result<Value, String> {
+    let value = text(args, 0)?;
+    let mut start = (integer(args, 1)?.max(0) as usize).min(value.len());
+    let mut end = match args.get(2) {
+        Some(_) => start.saturating_add(integer(args, 2)?.max(0) as usize).min(value.len()),
+        None => value.len(),
+    };
+    while !value.is_char_boundary(start) {
+        start += 1;
+    }
+    while end > start && !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    Ok(json!(value[start..end.max(start)]))
+}
+
+/// [text, width, pad?]: pads to `width` characters with `pad`, a space by default.
+fn pad(args: &[Value], left: bool) -> Result<Value, String> {
+    let value = match arg(args, 0)? {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let width = integer(args, 1)?.max(0) as usize;
+    let pad = args.get(2).and_then(|p| p.as_str()).and_then(|p| p.chars().next()).unwrap_or(' ');
+    let padding: String = std::iter::repeat_n(pad, width.saturating_sub(value.chars().count())).collect();
+    Ok(json!(if left { padding + &value } else { value + &padding }))
+}
+
+/// [text, pattern]. Compiled patterns are cached, as rules use a handful of literal ones.
+fn regex_match(args: &[Value]) -> Result<Value, String> {
+    static CACHE: OnceLock<Mutex<HashMap<String, Regex>>> = OnceLock::new();
+    let source = text(args, 1)?;
+    let mut cache = CACHE.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    let pattern = match cache.get(source) {
+        Some(pattern) => pattern.clone(),
+        None => {
+            let pattern = Regex::new(source).map_err(|e| e.to_string())?;
+            if cache.len() >= REGEX_CACHE_SIZE {
+                cache.clear();
+            }
+            cache.insert(source.to_string(), pattern.clone());
+            pattern
+        }
+    };
+    drop(cache);
+    Ok(json!(args[0].as_str().is_some_and(|value| pattern.is_match(value))))
+}
+
+/// [format?]: the current UTC time as RFC 3339, or the current date with "date".
+fn now(args: &[Value]) -> Result<Value, String> {
+    let now = OffsetDateTime::now_utc();
+    match args.first().and_then(|f| f.as_str()) {
+        Some("date") => Ok(json!(now.date().to_string())),
+        _ => now.format(&Rfc3339).map(|t| json!(t)).map_err(|e| e.to_string()),
+    }
+}
+
+/// Random (version 4) UUID, as used for UETRs.
+pub fn uuid() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// SHA-256 digest of `input` as lowercase hex.
+pub fn sha256(input: &[u8]) -> String {
+    Sha256::digest(input).iter().map(|b| format!("{:02x}", b)).collect()
+}
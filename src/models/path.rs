@@ -0,0 +1,56 @@
+use serde_json::{json, Value};
+
+/// The value at the dotted `path`, e.g. "GrpHdr.MsgId" or "CdtTrfTxInf.0.PmtId", or None
+/// when it is absent or null.
+pub(crate) fn value_at<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut current = value;
+    for part in path.split('.') {
+        current = match current {
+            Value::Object(map) => map.get(part)?,
+            Value::Array(items) => items.get(part.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    (!current.is_null()).then_some(current)
+}
+
+/// The element `part` of an array, or the member `part` of an object, created as needed. An
+/// array is never replaced: `part` must be the index of one of its elements.
+pub(crate) fn child_mut<'a>(value: &'a mut Value, part: &str) -> Result<&'a mut Value, String> {
+    if let Value::Array(items) = value {
+        let len = items.len();
+        return part.parse::<usize>().ok()
+            .and_then(|index| items.get_mut(index))
+            .ok_or_else(|| format!("{} is not an index of an array of {} elements", part, len));
+    }
+    if !value.is_object() {
+        *value = json!({});
+    }
+    Ok(value.as_object_mut().unwrap().entry(part).or_insert(Value::Null))
+}
+
+/// Writes `new_value` at `path`, leaving `value` as is when the path runs through an array it
+/// does not index.
+pub(crate) fn set_at(value: &mut Value, path: &str, new_value: Value) {
+    if let Ok(field) = path.split('.').try_fold(value, child_mut) {
+        *field = new_value;
+    }
+}
+
+pub(crate) fn remove_at(value: &mut Value, path: &str) {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((parent, key)) => (value_mut_at(value, parent), key),
+        None => (Some(value), path),
+    };
+    if let Some(Value::Object(map)) = parent {
+        map.remove(key);
+    }
+}
+
+/// The value at `path`, explicit nulls included.
+pub(crate) fn value_mut_at<'a>(value: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    path.split('.').try_fold(value, |current, part| match current {
+        Value::Array(items) => items.get_mut(part.parse::<usize>().ok()?),
+        _ => current.get_mut(part),
+    })
+}
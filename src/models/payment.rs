@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::models::errors::{error, FunctionResponseError};
+use crate::models::message::Message;
+use crate::models::document::*;
+use crate::models::path::*;
+
+type FieldPaths = [(&'static str, &'static [&'static str])];
+
+// pacs.008 and pacs.009 CdtTrfTxInf, falling back to the group header
+const CREDIT_TRANSFER_FIELDS: &FieldPaths = &[
+    ("amount", &["IntrBkSttlmAmt.$value"]),
+    ("currency", &["IntrBkSttlmAmt.@Ccy"]),
+    ("debtor_name", &["Dbtr.Nm", "Dbtr.FinInstnId.Nm"]),
+    ("debtor_account", &["DbtrAcct.Id.IBAN", "DbtrAcct.Id.Othr.Id"]),
+    ("debtor_agent", &["DbtrAgt.FinInstnId.BICFI"]),
+    ("creditor_name", &["Cdtr.Nm", "Cdtr.FinInstnId.Nm"]),
+    ("creditor_account", &["CdtrAcct.Id.IBAN", "CdtrAcct.Id.Othr.Id"]),
+    ("creditor_agent", &["CdtrAgt.FinInstnId.BICFI"]),
+    ("instruction_id", &["PmtId.InstrId"]),
+    ("end_to_end_id", &["PmtId.EndToEndId"]),
+    ("transaction_id", &["PmtId.TxId"]),
+    ("uetr", &["PmtId.UETR"]),
+    ("creation_date_time", &["GrpHdr.CreDtTm"]),
+    ("settlement_date", &["IntrBkSttlmDt", "GrpHdr.IntrBkSttlmDt"]),
+    ("remittance_information", &["RmtInf.Ustrd"]),
+];
+
+// pain.001 CdtTrfTxInf, falling back to its PmtInf and then the group header
+const INITIATION_FIELDS: &FieldPaths = &[
+    ("amount", &["Amt.InstdAmt.$value", "Amt.EqvtAmt.Amt.$value"]),
+    ("currency", &["Amt.InstdAmt.@Ccy", "Amt.EqvtAmt.Amt.@Ccy"]),
+    ("debtor_name", &["Dbtr.Nm"]),
+    ("debtor_account", &["DbtrAcct.Id.IBAN", "DbtrAcct.Id.Othr.Id"]),
+    ("debtor_agent", &["DbtrAgt.FinInstnId.BICFI"]),
+    ("creditor_name", &["Cdtr.Nm"]),
+    ("creditor_account", &["CdtrAcct.Id.IBAN", "CdtrAcct.Id.Othr.Id"]),
+    ("creditor_agent", &["CdtrAgt.FinInstnId.BICFI"]),
+    ("instruction_id", &["PmtId.InstrId"]),
+    ("end_to_end_id", &["PmtId.EndToEndId"]),
+    ("uetr", &["PmtId.UETR"]),
+    ("creation_date_time", &["GrpHdr.CreDtTm"]),
+    ("settlement_date", &["ReqdExctnDt.Dt", "ReqdExctnDt.DtTm"]),
+    ("remittance_information", &["RmtInf.Ustrd"]),
+];
+
+// pacs.004 TxInf, parties are taken from the return chain
+const RETURN_FIELDS: &FieldPaths = &[
+    ("amount", &["RtrdIntrBkSttlmAmt.$value"]),
+    ("currency", &["RtrdIntrBkSttlmAmt.@Ccy"]),
+    ("debtor_name", &["RtrChain.Dbtr.Pty.Nm", "RtrChain.Dbtr.Agt.FinInstnId.Nm"]),
+    ("debtor_account", &["RtrChain.DbtrAcct.Id.IBAN", "RtrChain.DbtrAcct.Id.Othr.Id"]),
+    ("debtor_agent", &["RtrChain.DbtrAgt.FinInstnId.BICFI"]),
+    ("creditor_name", &["RtrChain.Cdtr.Pty.Nm", "RtrChain.Cdtr.Agt.FinInstnId.Nm"]),
+    ("creditor_account", &["RtrChain.CdtrAcct.Id.IBAN", "RtrChain.CdtrAcct.Id.Othr.Id"]),
+    ("creditor_agent", &["RtrChain.CdtrAgt.FinInstnId.BICFI"]),
+    ("instruction_id", &["OrgnlInstrId"]),
+    ("end_to_end_id", &["OrgnlEndToEndId"]),
+    ("transaction_id", &["RtrId"]),
+    ("uetr", &["OrgnlUETR"]),
+    ("creation_date_time", &["GrpHdr.CreDtTm"]),
+    ("settlement_date", &["IntrBkSttlmDt", "GrpHdr.IntrBkSttlmDt"]),
+];
+
+// camt.054 TxDtls, falling back to the entry and notification
+const NOTIFICATION_FIELDS: &FieldPaths = &[
+    ("amount", &["Amt.$value"]),
+    ("currency", &["Amt.@Ccy"]),
+    ("credit_debit", &["CdtDbtInd"]),
+    ("debtor_name", &["RltdPties.Dbtr.Pty.Nm", "RltdPties.Dbtr.Agt.FinInstnId.Nm"]),
+    ("debtor_account", &["RltdPties.DbtrAcct.Id.IBAN", "RltdPties.DbtrAcct.Id.Othr.Id"]),
+    ("debtor_agent", &["RltdAgts.DbtrAgt.FinInstnId.BICFI"]),
+    ("creditor_name", &["RltdPties.Cdtr.Pty.Nm", "RltdPties.Cdtr.Agt.FinInstnId.Nm"]),
+    ("creditor_account", &["RltdPties.CdtrAcct.Id.IBAN", "RltdPties.CdtrAcct.Id.Othr.Id"]),
+    ("creditor_agent", &["RltdAgts.CdtrAgt.FinInstnId.BICFI"]),
+    ("instruction_id", &["Refs.InstrId"]),
+    ("end_to_end_id", &["Refs.EndToEndId"]),
+    ("transaction_id", &["Refs.TxId"]),
+    ("uetr", &["Refs.UETR"]),
+    ("creation_date_time", &["GrpHdr.CreDtTm"]),
+    ("settlement_date", &["BookgDt.Dt", "ValDt.Dt", "BookgDt.DtTm"]),
+    ("remittance_information", &["RmtInf.Ustrd"]),
+];
+
+/// Message-independent view of one payment transaction of a parsed ISO20022 message.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct Payment {
+    /// ISO20022 message name, e.g. pacs.008.001.12
+    pub message_name: String,
+
+    /// Location of the transaction in the message, e.g. data.document.FIToFICstmrCdtTrf.CdtTrfTxInf.0
+    pub path: String,
+
+    pub amount: Option<f64>,
+
+    pub currency: Option<String>,
+
+    /// CRDT or DBIT, for notifications
+    pub credit_debit: Option<String>,
+
+    pub debtor_name: Option<String>,
+
+    /// IBAN, or the proprietary account identification
+    pub debtor_account: Option<String>,
+
+    /// BIC of the debtor agent
+    pub debtor_agent: Option<String>,
+
+    pub creditor_name: Option<String>,
+
+    pub creditor_account: Option<String>,
+
+    pub creditor_agent: Option<String>,
+
+    pub instruction_id: Option<String>,
+
+    pub end_to_end_id: Option<String>,
+
+    pub transaction_id: Option<String>,
+
+    pub uetr: Option<String>,
+
+    pub creation_date_time: Option<String>,
+
+    /// Interbank settlement date, requested execution date or booking date
+    pub settlement_date: Option<String>,
+
+    /// Unstructured remittance information
+    #[serde(default)]
+    pub remittance_information: Vec<String>,
+}
+
+/// One transaction to extract: its path and the elements to search, innermost first.
+struct Transaction<'a> {
+    path: String,
+    scopes: Vec<&'a Value>,
+}
+
+fn items(value: &Value) -> impl Iterator<Item = (usize, &Value)> {
+    value.as_array().into_iter().flatten().enumerate()
+}
+
+fn transactions<'a>(root: &str, body: &'a Value) -> Option<(&'static FieldPaths, Vec<Transaction<'a>>)> {
+    let prefix = format!("data.document.{}", root);
+    let mut transactions = Vec::new();
+    let fields = match root {
+        "FIToFICstmrCdtTrf" | "FICdtTrf" => {
+            for (index, transaction) in items(&body["CdtTrfTxInf"]) {
+                transactions.push(Transaction { path: format!("{}.CdtTrfTxInf.{}", prefix, index), scopes: vec![transaction, body] });
+            }
+            CREDIT_TRANSFER_FIELDS
+        }
+        "CstmrCdtTrfInitn" => {
+            for (instruction_index, instruction) in items(&body["PmtInf"]) {
+                for (index, transaction) in items(&instruction["CdtTrfTxInf"]) {
+                    transactions.push(Transaction {
+                        path: format!("{}.PmtInf.{}.CdtTrfTxInf.{}", prefix, instruction_index, index),
+                        scopes: vec![transaction, instruction, body],
+                    });
+                }
+            }
+            INITIATION_FIELDS
+        }
+        "PmtRtr" => {
+            for (index, transaction) in items(&body["TxInf"]) {
+                transactions.push(Transaction { path: format!("{}.TxInf.{}", prefix, index), scopes: vec![transaction, body] });
+            }
+            RETURN_FIELDS
+        }
+        "BkToCstmrDbtCdtNtfctn" => {
+            for (notification_index, notification) in items(&body["Ntfctn"]) {
+                for (entry_index, entry) in items(&notification["Ntry"]) {
+                    let entry_path = format!("{}.Ntfctn.{}.Ntry.{}", prefix, notification_index, entry_index);
+                    let mut detailed = false;
+                    for (details_index, details) in items(&entry["NtryDtls"]) {
+                        for (index, transaction) in items(&details["TxDtls"]) {
+                            detailed = true;
+                            transactions.push(Transaction {
+                                path: format!("{}.NtryDtls.{}.TxDtls.{}", entry_path, details_index, index),
+                                scopes: vec![transaction, entry, notification, body],
+                            });
+                        }
+                    }
+                    // An entry without transaction details is a payment on its own
+                    if !detailed {
+                        transactions.push(Transaction { path: entry_path, scopes: vec![entry, notification, body] });
+                    }
+                }
+            }
+            NOTIFICATION_FIELDS
+        }
+        _ => return None,
+    };
+    Some((fields, transactions))
+}
+
+fn extract(message_name: &str, transaction: &Transaction, fields: &FieldPaths) -> Result<Payment, FunctionResponseError> {
+    let mut payment = Map::new();
+    payment.insert("message_name".to_string(), json!(message_name));
+    payment.insert("path".to_string(), json!(transaction.path));
+    for (field, paths) in fields {
+        let found = transaction.scopes.iter()
+            .find_map(|scope| paths.iter().find_map(|path| value_at(scope, path)));
+        let value = match (found, *field) {
+            // Unstructured remittance information may hold one or several lines
+            (Some(Value::String(line)), "remittance_information") => json!([line]),
+            (Some(value), _) => value.clone(),
+            (None, _) => continue,
+        };
+        payment.insert(field.to_string(), value);
+    }
+    serde_json::from_value(Value::Object(payment))
+        .map_err(|e| error("Payment", format!("Payment extraction failed at {}: {}", transaction.path, e)))
+}
+
+impl Message {
+    /// Normalized payments of a parsed pacs.008, pacs.009, pain.001, pacs.004 or camt.054
+    /// message, one per transaction.
+    pub fn payments(&self) -> Result<Vec<Payment>, FunctionResponseError> {
+        let (root, body) = document_root(self.data())
+            .ok_or_else(|| error("Payment", "Message has no parsed document".to_string()))?;
+        let (fields, transactions) = transactions(root, body)
+            .ok_or_else(|| error("Payment", format!("Payments are not supported for {}", root)))?;
+        let message_name = document_message_name(self, root);
+
+        transactions.iter()
+            .map(|transaction| extract(&message_name, transaction, fields))
+            .collect()
+    }
+}
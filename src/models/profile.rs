@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use time::OffsetDateTime;
+
+use crate::models::auditlog::*;
+use crate::models::errors::{error, FunctionResponseError};
+use crate::models::message::Message;
+
+// Market practice profiles shipped with the library
+const BUILTIN_PROFILES: [(&str, &str); 3] = [
+    ("cbpr_plus", include_str!("../../profiles/cbpr_plus.json")),
+    ("sepa_sct", include_str!("../../profiles/sepa_sct.json")),
+    ("fednow", include_str!("../../profiles/fednow.json")),
+];
+
+/// Usage guidelines a scheme layers on top of the ISO20022 schema.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ValidationProfile {
+    pub name: String,
+
+    pub description: String,
+
+    pub version: String,
+
+    pub rules: Vec<ProfileRule>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ProfileRule {
+    pub id: String,
+
+    pub description: String,
+
+    /// Path below `data.document` starting at the document root, `*` matches every array element
+    pub path: String,
+
+    pub check: ProfileCheck,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProfileCheck {
+    /// The element must be present
+    Required,
+    /// The element must be absent
+    Forbidden,
+    /// The element, when present, is limited to `max` characters
+    MaxLength { max: usize },
+    /// The element, when present, must be one of `codes`
+    Codes { codes: Vec<String> },
+    /// The element, when present, must match the regular expression, e.g. a character set
+    Pattern { pattern: ProfilePattern },
+}
+
+/// Regular expression of a `Pattern` check, compiled when the profile is loaded.
+#[derive(Debug, Clone)]
+pub struct ProfilePattern(Regex);
+
+impl ProfilePattern {
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(pattern).map(ProfilePattern)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl PartialEq for ProfilePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Serialize for ProfilePattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ProfilePattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        ProfilePattern::new(&pattern).map_err(|e| serde::de::Error::custom(format!("invalid pattern {}: {}", pattern, e)))
+    }
+}
+
+/// Validation profiles by name, the builtin ones included.
+#[derive(Debug, Clone)]
+pub struct ProfileRegistry {
+    profiles: HashMap<String, ValidationProfile>,
+}
+
+impl Default for ProfileRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProfileRegistry {
+    /// Registry holding the builtin profiles.
+    pub fn new() -> Self {
+        Self::builtins().clone()
+    }
+
+    pub(crate) fn builtins() -> &'static ProfileRegistry {
+        static BUILTINS: OnceLock<ProfileRegistry> = OnceLock::new();
+        BUILTINS.get_or_init(|| ProfileRegistry {
+            profiles: BUILTIN_PROFILES.iter()
+                .map(|(name, definition)| (name.to_string(), serde_json::from_str(definition).expect("Invalid builtin profile")))
+                .collect(),
+        })
+    }
+
+    /// Adds a profile under `name`, replacing the one registered under it before.
+    pub fn register(&mut self, name: &str, profile: ValidationProfile) -> Option<ValidationProfile> {
+        self.profiles.insert(name.to_string(), profile)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ValidationProfile> {
+        self.profiles.get(name)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ProfileViolation {
+    pub rule_id: String,
+
+    /// Location of the offending element, e.g. data.document.FIToFICstmrCdtTrf.CdtTrfTxInf.0.PmtId.UETR
+    pub path: String,
+
+    pub message: String,
+}
+
+/// Every element addressed by `segments`, with `None` where the path is absent.
+fn resolve<'a>(value: &'a Value, segments: &[&str], path: String, found: &mut Vec<(String, Option<&'a Value>)>) {
+    let Some((segment, rest)) = segments.split_first() else {
+        found.push((path, Some(value)));
+        return;
+    };
+    match (*segment, value) {
+        ("*", Value::Array(items)) => {
+            for (index, item) in items.iter().enumerate() {
+                resolve(item, rest, format!("{}.{}", path, index), found);
+            }
+        }
+        (_, Value::Object(map)) if map.get(*segment).is_some_and(|v| !v.is_null()) => {
+            resolve(&map[*segment], rest, format!("{}.{}", path, segment), found);
+        }
+        _ => found.push((format!("{}.{}", path, segments.join(".")), None)),
+    }
+}
+
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+impl ProfileCheck {
+    /// Why `value` breaks the check, if it does.
+    fn violation(&self, value: Option<&Value>) -> Option<String> {
+        let violation = match (self, value) {
+            (ProfileCheck::Required, None) => Some("is missing".to_string()),
+            (ProfileCheck::Forbidden, Some(_)) => Some("is not allowed".to_string()),
+            (ProfileCheck::MaxLength { max }, Some(value)) => text(value)
+                .filter(|s| s.chars().count() > *max)
+                .map(|s| format!("has {} characters, at most {} allowed", s.chars().count(), max)),
+            (ProfileCheck::Codes { codes }, Some(value)) => text(value)
+                .filter(|s| !codes.contains(s))
+                .map(|s| format!("{} is not one of {}", s, codes.join(", "))),
+            (ProfileCheck::Pattern { pattern }, Some(value)) => text(value)
+                .filter(|s| !pattern.0.is_match(s))
+                .map(|s| format!("{} does not match {}", s, pattern.as_str())),
+            _ => None,
+        };
+        violation
+    }
+}
+
+impl ValidationProfile {
+    /// One of the profiles shipped with the library: cbpr_plus, sepa_sct or fednow.
+    pub fn builtin(name: &str) -> Option<ValidationProfile> {
+        ProfileRegistry::builtins().get(name).cloned()
+    }
+
+    /// Loads a profile definition, compiling the patterns of its rules.
+    pub fn from_json(definition: &str) -> Result<ValidationProfile, FunctionResponseError> {
+        serde_json::from_str(definition)
+            .map_err(|e| error("Validate", format!("Invalid validation profile: {}", e)))
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<ValidationProfile, FunctionResponseError> {
+        let definition = fs::read_to_string(path.as_ref())
+            .map_err(|e| error("Validate", format!("Cannot read profile {}: {}", path.as_ref().display(), e)))?;
+        ValidationProfile::from_json(&definition)
+    }
+
+    /// Every guideline violation in `data`. Only rules written for the document root of
+    /// `data` apply; a profile without any is an error.
+    pub fn check(&self, data: &Value) -> Result<Vec<ProfileViolation>, FunctionResponseError> {
+        let root = data["document"].as_object()
+            .and_then(|document| document.keys().next())
+            .ok_or_else(|| error("Validate", "Message has no parsed document".to_string()))?;
+        let rules: Vec<&ProfileRule> = self.rules.iter()
+            .filter(|rule| rule.path.split('.').next() == Some(root.as_str()))
+            .collect();
+        if rules.is_empty() {
+            return Err(error("Validate", format!("Profile {} has no rules for {}", self.name, root)));
+        }
+
+        let mut violations = Vec::new();
+        for rule in rules {
+            let segments: Vec<&str> = rule.path.split('.').collect();
+            let mut found = Vec::new();
+            resolve(&data["document"], &segments, "data.document".to_string(), &mut found);
+            for (path, value) in found {
+                if let Some(reason) = rule.check.violation(value) {
+                    violations.push(ProfileViolation {
+                        rule_id: rule.id.clone(),
+                        message: format!("{}: {}", rule.description, reason),
+                        path,
+                    });
+                }
+            }
+        }
+        Ok(violations)
+    }
+}
+
+impl Message {
+    /// Checks the parsed document against a market practice profile. The error lists every
+    /// violation with its rule id and path; see `ValidationProfile::check` for the structured list.
+    pub fn validate_profile(&mut self, profile: &ValidationProfile, description: Option<String>, workflow: String, task: String) -> Result<(), FunctionResponseError> {
+        let start_time = OffsetDateTime::now_utc();
+        let violations = profile.check(self.data())?;
+        if !violations.is_empty() {
+            let details: Vec<String> = violations.iter()
+                .map(|v| format!("{} at {}: {}", v.rule_id, v.path, v.message))
+                .collect();
+            return Err(error("Validate", format!(
+                "{} violations of profile {}: {}", violations.len(), profile.name, details.join("; ")
+            )));
+        }
+
+        self.push_audit(AuditLog::new(
+            workflow,
+            task,
+            start_time,
+            description.unwrap_or_else(|| "Profile validation passed".to_string()),
+            vec![ChangeLog::new(
+                "data".to_string(),
+                format!("Validated against profile {} {}", profile.name, profile.version),
+                None,
+                None
+            )]
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use serde_json::{Map, Value};
+
+use crate::models::errors::{error, FunctionResponseError};
+use crate::models::operators::{sha256, OperatorRegistry};
+
+/// A named table of records loaded from a local CSV or JSON file and indexed by one key field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReferenceDataset {
+    name: String,
+    version: String,
+    key: String,
+    source: PathBuf,
+    records: Vec<Value>,
+    index: HashMap<String, usize>,
+}
+
+fn key_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+thread_local! {
+    // "name version" of the datasets read by `lookup` calls while recording on this thread
+    static LOOKUPS: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Runs `evaluate` and returns the "name version" of each dataset its `lookup` calls read,
+/// as they were at the time of the call.
+pub(crate) fn record_lookups<T>(evaluate: impl FnOnce() -> T) -> (T, Vec<String>) {
+    let outer = LOOKUPS.with(|lookups| lookups.replace(Some(Vec::new())));
+    let result = evaluate();
+    let mut used = LOOKUPS.with(|lookups| {
+        let used = lookups.replace(outer).unwrap_or_default();
+        if let Some(outer) = lookups.borrow_mut().as_mut() {
+            outer.extend(used.iter().cloned());
+        }
+        used
+    });
+    used.sort();
+    used.dedup();
+    (result, used)
+}
+
+fn parse_csv(content: &str) -> Result<Vec<Value>, String> {
+    let mut reader = csv::Reader::from_reader(content.as_bytes());
+    let header = reader.headers().map_err(|e| e.to_string())?.clone();
+    if header.is_empty() {
+        return Err("empty file".to_string());
+    }
+    reader.records()
+        .map(|record| {
+            let record = record.map_err(|e| e.to_string())?;
+            Ok(Value::Object(header.iter().zip(record.iter())
+                .map(|(field, value)| (field.to_string(), Value::String(value.to_string())))
+                .collect::<Map<_, _>>()))
+        })
+        .collect()
+}
+
+impl ReferenceDataset {
+    /// Loads `path` as CSV (with a header row) or JSON, by extension. JSON files hold an array
+    /// of records or `{"version": ..., "records": [...]}`; otherwise the version is derived
+    /// from the file content.
+    pub fn load<P: AsRef<Path>>(name: &str, path: P, key: &str) -> Result<ReferenceDataset, FunctionResponseError> {
+        let source = path.as_ref().to_path_buf();
+        let content = fs::read_to_string(&source)
+            .map_err(|e| FunctionResponseError::new("ReferenceData".to_string(), 503, format!("Cannot read dataset {} from {}: {}", name, source.display(), e)))?;
+        let fail = |message: String| error("ReferenceData", format!("Invalid dataset {}: {}", name, message));
+
+        let mut version = None;
+        let records = match source.extension().and_then(|e| e.to_str()) {
+            Some("csv") => parse_csv(&content).map_err(fail)?,
+            Some("json") => match serde_json::from_str(&content).map_err(|e| fail(e.to_string()))? {
+                Value::Array(records) => records,
+                Value::Object(mut file) => {
+                    version = file.get("version").and_then(key_text);
+                    match file.remove("records") {
+                        Some(Value::Array(records)) => records,
+                        _ => return Err(fail("records array missing".to_string())),
+                    }
+                }
+                _ => return Err(fail("expected an array of records".to_string())),
+            },
+            _ => return Err(fail(format!("unsupported file {}", source.display()))),
+        };
+
+        let mut index = HashMap::with_capacity(records.len());
+        for (position, record) in records.iter().enumerate() {
+            let value = key_text(&record[key]).ok_or_else(|| fail(format!("record {} has no {}", position, key)))?;
+            if index.insert(value.clone(), position).is_some() {
+                return Err(fail(format!("duplicate key {}", value)));
+            }
+        }
+
+        Ok(ReferenceDataset {
+            name: name.to_string(),
+            version: version.unwrap_or_else(|| sha256(content.as_bytes())[..12].to_string()),
+            key: key.to_string(),
+            source,
+            records,
+            index,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.index.get(key).map(|position| &self.records[*position])
+    }
+
+    /// The same dataset read again from its source file.
+    pub fn reload(&self) -> Result<ReferenceDataset, FunctionResponseError> {
+        ReferenceDataset::load(&self.name, &self.source, &self.key)
+    }
+}
+
+/// Reference datasets shared by the rules of every message, replaceable while in use.
+#[derive(Debug, Default)]
+pub struct ReferenceData {
+    datasets: RwLock<HashMap<String, Arc<ReferenceDataset>>>,
+}
+
+impl ReferenceData {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a dataset, replacing any dataset with the same name.
+    pub fn insert(&self, dataset: ReferenceDataset) {
+        self.datasets.write().unwrap().insert(dataset.name.clone(), Arc::new(dataset));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<ReferenceDataset>> {
+        self.datasets.read().unwrap().get(name).cloned()
+    }
+
+    /// Reloads a dataset from its file. Rules running meanwhile keep the previous version.
+    pub fn reload(&self, name: &str) -> Result<(), FunctionResponseError> {
+        let dataset = self.get(name)
+            .ok_or_else(|| error("ReferenceData", format!("Unknown dataset {}", name)))?;
+        self.insert(dataset.reload()?);
+        Ok(())
+    }
+
+    /// Registers the `lookup` operator: `{"lookup": [dataset, key, field?]}` returns the record
+    /// with that key, or one of its fields, and null when there is none.
+    pub fn register(self: &Arc<Self>, registry: &mut OperatorRegistry) -> Result<(), FunctionResponseError> {
+        let reference_data = Arc::clone(self);
+        registry.register("lookup", move |args| {
+            let name = args.first().and_then(|n| n.as_str()).ok_or("dataset name required")?;
+            let dataset = reference_data.get(name).ok_or_else(|| format!("unknown dataset {}", name))?;
+            LOOKUPS.with(|lookups| if let Some(lookups) = lookups.borrow_mut().as_mut() {
+                lookups.push(format!("{} {}", dataset.name, dataset.version));
+            });
+            let Some(record) = args.get(1).and_then(key_text).and_then(|key| dataset.get(&key)) else {
+                return Ok(Value::Null);
+            };
+            Ok(match args.get(2).and_then(|f| f.as_str()) {
+                Some(field) => record.get(field).cloned().unwrap_or(Value::Null),
+                None => record.clone(),
+            })
+        })
+    }
+}
@@ -0,0 +1,124 @@
+use std::path::Path;
+use serde_json::json;
+use time::OffsetDateTime;
+
+use crate::models::auditlog::*;
+use crate::models::errors::{error, FunctionResponseError};
+use crate::models::library::TaskLibrary;
+use crate::models::logic::RuleEvaluator;
+use crate::models::message::Message;
+use crate::models::workflow::*;
+
+/// Compiled workflows, selected for each incoming message by their `condition`.
+#[derive(Clone, Default)]
+pub struct WorkflowRegistry {
+    logic: RuleEvaluator,
+    workflows: Vec<CompiledWorkflow>,
+}
+
+impl WorkflowRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry compiling its workflows with `logic`, e.g. one carrying application operators.
+    pub fn with_evaluator(logic: RuleEvaluator) -> Self {
+        Self { logic, workflows: Vec::new() }
+    }
+
+    /// Registry with every workflow definition in `dir`.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<Self, FunctionResponseError> {
+        Self::from_workflows(Workflow::from_dir(dir)?)
+    }
+
+    /// Registry with every workflow definition in `dir`, using `library` tasks.
+    pub fn from_dir_with<P: AsRef<Path>>(dir: P, library: &TaskLibrary) -> Result<Self, FunctionResponseError> {
+        Self::from_workflows(Workflow::from_dir_with(dir, library)?)
+    }
+
+    fn from_workflows(workflows: Vec<Workflow>) -> Result<Self, FunctionResponseError> {
+        let mut registry = Self::new();
+        for workflow in workflows {
+            registry.register(workflow)?;
+        }
+        Ok(registry)
+    }
+
+    /// Validates, compiles and adds a workflow. A name and version can only be registered once.
+    pub fn register(&mut self, workflow: Workflow) -> Result<(), FunctionResponseError> {
+        if self.get(&workflow.name, workflow.version).is_some() {
+            return Err(error("Workflow", format!("Workflow {} version {} is already registered", workflow.name, workflow.version)));
+        }
+        self.workflows.push(CompiledWorkflow::new(workflow, &self.logic)?);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str, version: u16) -> Option<&CompiledWorkflow> {
+        self.workflows.iter()
+            .find(|compiled| compiled.workflow().name == name && compiled.workflow().version == version)
+    }
+
+    /// The highest active version of `name`.
+    pub fn latest(&self, name: &str) -> Option<&CompiledWorkflow> {
+        self.workflows.iter()
+            .filter(|compiled| compiled.workflow().name == name && compiled.workflow().status == WorkflowStatus::Active)
+            .max_by_key(|compiled| compiled.workflow().version)
+    }
+
+    pub fn workflows(&self) -> &[CompiledWorkflow] {
+        &self.workflows
+    }
+
+    /// The workflow for `message`: among non-draft workflows whose condition matches, the highest
+    /// active version of each name, or its highest deprecated version when no active one matches.
+    /// Matches under several names are ambiguous. The choice is recorded in `Progress` and the
+    /// audit trail, with a warning when the workflow is deprecated.
+    pub fn select(&self, message: &mut Message, description: Option<String>) -> Result<&CompiledWorkflow, FunctionResponseError> {
+        let start_time = OffsetDateTime::now_utc();
+        let mut selected: Vec<&CompiledWorkflow> = Vec::new();
+        for compiled in &self.workflows {
+            let workflow = compiled.workflow();
+            if workflow.status == WorkflowStatus::Draft || !compiled.matches(message)? {
+                continue;
+            }
+            let rank = |w: &Workflow| (w.status == WorkflowStatus::Active, w.version);
+            match selected.iter_mut().find(|s| s.workflow().name == workflow.name) {
+                Some(current) if rank(current.workflow()) < rank(workflow) => *current = compiled,
+                Some(_) => {}
+                None => selected.push(compiled),
+            }
+        }
+
+        let compiled = match selected.as_slice() {
+            [compiled] => *compiled,
+            [] => return Err(error("Workflow", format!("No workflow matches message {}", message.id()))),
+            matches => {
+                let names: Vec<String> = matches.iter()
+                    .map(|m| format!("{} version {}", m.workflow().name, m.workflow().version))
+                    .collect();
+                return Err(error("Workflow", format!("Ambiguous workflows for message {}: {}", message.id(), names.join(", "))));
+            }
+        };
+
+        let workflow = compiled.workflow();
+        let mut reason = format!("Selected workflow {} version {}", workflow.name, workflow.version);
+        if workflow.status == WorkflowStatus::Deprecated {
+            reason.push_str(", warning: workflow is deprecated");
+        }
+        let old_value = json!({"workflow_id": message.progress().workflow_id, "workflow_version": message.progress().workflow_version});
+        message.set_workflow(&workflow.name, workflow.version);
+        message.push_audit(AuditLog::new(
+            workflow.name.clone(),
+            "WorkflowSelection".to_string(),
+            start_time,
+            description.unwrap_or_else(|| "Workflow selected".to_string()),
+            vec![ChangeLog::new(
+                "progress".to_string(),
+                reason,
+                Some(old_value),
+                Some(json!({"workflow_id": workflow.name, "workflow_version": workflow.version}))
+            )]
+        ));
+        Ok(compiled)
+    }
+}
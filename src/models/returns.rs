@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+
+use crate::models::auditlog::*;
+use crate::models::errors::{error, FunctionResponseError};
+use crate::models::message::Message;
+use crate::models::amount::*;
+use crate::models::document::*;
+use crate::models::path::*;
+
+// Original pacs.008 fields carried into the return, keyed by their pacs.004 name
+const ORIGINAL_REFERENCES: [(&str, &str); 6] = [
+    ("PmtId.InstrId", "OrgnlInstrId"),
+    ("PmtId.EndToEndId", "OrgnlEndToEndId"),
+    ("PmtId.TxId", "OrgnlTxId"),
+    ("PmtId.UETR", "OrgnlUETR"),
+    ("IntrBkSttlmAmt", "OrgnlIntrBkSttlmAmt"),
+    ("IntrBkSttlmDt", "OrgnlIntrBkSttlmDt"),
+];
+
+// The return travels back along the original chain, so debtor and creditor sides swap
+const RETURN_CHAIN: [(&str, &str); 6] = [
+    ("Cdtr", "Dbtr"),
+    ("CdtrAcct", "DbtrAcct"),
+    ("CdtrAgt", "DbtrAgt"),
+    ("DbtrAgt", "CdtrAgt"),
+    ("Dbtr", "Cdtr"),
+    ("DbtrAcct", "CdtrAcct"),
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Pacs004Config {
+    /// BIC of the agent returning the funds
+    pub instructing_agent: String,
+
+    /// BIC of the agent receiving the return
+    pub instructed_agent: String,
+
+    /// Settlement method code: INDA, INGA, COVE or CLRG
+    pub settlement_method: String,
+
+    pub clearing_system: Option<String>,
+
+    /// ISO external return reason code, e.g. AC04 or MD06
+    pub reason_code: String,
+
+    pub additional_info: Option<String>,
+
+    /// Amount to return, defaults to the original settlement amount
+    pub amount: Option<f64>,
+
+    /// EndToEndId of the transaction to return, defaults to every transaction
+    pub end_to_end_id: Option<String>,
+}
+
+fn return_transaction(original: &Value, index: usize, config: &Pacs004Config, changes: &mut Vec<ChangeLog>) -> Result<Value, FunctionResponseError> {
+    let currency = original["IntrBkSttlmAmt"]["@Ccy"].as_str().unwrap_or_default();
+    let original_amount = match &original["IntrBkSttlmAmt"]["$value"] {
+        Value::Null => return Err(error("Return", "Original transaction has no settlement amount".to_string())),
+        amount => minor_units(amount, currency)?,
+    };
+    let amount = match config.amount {
+        Some(amount) => minor_units(&json!(amount), currency)?,
+        None => original_amount,
+    };
+    if amount <= 0 || amount > original_amount {
+        return Err(error("Return", format!(
+            "Return amount {} must be positive and not exceed the original amount {}",
+            format_amount(amount, currency), format_amount(original_amount, currency)
+        )));
+    }
+
+    let prefix = format!("data.document.PmtRtr.TxInf.{}", index);
+    let mut transaction = json!({
+        "RtrId": message_id(),
+        "RtrdIntrBkSttlmAmt": {"@Ccy": original["IntrBkSttlmAmt"]["@Ccy"], "$value": amount_value(amount, currency)},
+        "IntrBkSttlmDt": OffsetDateTime::now_utc().date().to_string(),
+        "ChrgBr": "SLEV",
+        "RtrRsnInf": [{"Rsn": {"Cd": config.reason_code}}],
+    });
+    if let Some(info) = &config.additional_info {
+        transaction["RtrRsnInf"][0]["AddtlInf"] = json!([info]);
+    }
+    for (field, value) in transaction.as_object().unwrap() {
+        changes.push(ChangeLog::new(format!("{}.{}", prefix, field), "Return details".to_string(), None, Some(value.clone())));
+    }
+
+    for (from, to) in ORIGINAL_REFERENCES {
+        if let Some(value) = value_at(original, from) {
+            transaction[to] = value.clone();
+            changes.push(ChangeLog::new(format!("{}.{}", prefix, to), format!("Original {}", from), None, Some(value.clone())));
+        }
+    }
+
+    let mut chain = json!({});
+    for (from, to) in RETURN_CHAIN {
+        if let Some(value) = value_at(original, from) {
+            // Parties are a choice between a party and an agent in the return chain
+            chain[to] = match to {
+                "Dbtr" | "Cdtr" => json!({"Pty": value}),
+                _ => value.clone(),
+            };
+            changes.push(ChangeLog::new(format!("{}.RtrChain.{}", prefix, to), format!("Original {}", from), None, Some(chain[to].clone())));
+        }
+    }
+    transaction["RtrChain"] = chain;
+    Ok(transaction)
+}
+
+impl Message {
+    /// Generates a pacs.004 payment return for this original pacs.008. The return references
+    /// the original identifiers and amount, is linked back through `parent_id` and is recorded
+    /// in the audit trail of both messages.
+    pub fn to_pacs004(&mut self, config: &Pacs004Config, description: Option<String>, workflow: String, task: String) -> Result<Message, FunctionResponseError> {
+        let count = value_at(self.data(), "document.FIToFICstmrCdtTrf.CdtTrfTxInf")
+            .and_then(|transactions| transactions.as_array())
+            .map_or(0, |transactions| transactions.len());
+        self.return_transactions(config, &(0..count).collect::<Vec<_>>(), description, workflow, task)
+    }
+
+    /// Generates a pacs.004 returning only the transactions of this original at `indices`,
+    /// further narrowed by `config.end_to_end_id` when set.
+    pub(crate) fn return_transactions(&mut self, config: &Pacs004Config, indices: &[usize], description: Option<String>, workflow: String, task: String) -> Result<Message, FunctionResponseError> {
+        let start_time = OffsetDateTime::now_utc();
+        let transfer = value_at(self.data(), "document.FIToFICstmrCdtTrf")
+            .ok_or_else(|| error("Return", "Message is not a parsed pacs.008".to_string()))?;
+        let originals: Vec<&Value> = indices.iter()
+            .filter_map(|index| transfer["CdtTrfTxInf"].get(*index))
+            .filter(|t| config.end_to_end_id.as_ref().is_none_or(|id| t["PmtId"]["EndToEndId"] == *id))
+            .collect();
+        if originals.is_empty() {
+            return Err(error("Return", "No matching transaction to return".to_string()));
+        }
+        if config.amount.is_some() && originals.len() > 1 {
+            return Err(error("Return", "A return amount requires a single transaction".to_string()));
+        }
+
+        let mut changes = Vec::new();
+        let mut transactions = Vec::new();
+        for (index, original) in originals.iter().enumerate() {
+            transactions.push(return_transaction(original, index, config, &mut changes)?);
+        }
+        let currency = transactions[0]["RtrdIntrBkSttlmAmt"]["@Ccy"].as_str().unwrap_or_default().to_string();
+        let total = transactions.iter()
+            .map(|t| minor_units(&t["RtrdIntrBkSttlmAmt"]["$value"], &currency))
+            .sum::<Result<i128, _>>()?;
+
+        let mut original_group = json!({
+            "OrgnlMsgId": transfer["GrpHdr"]["MsgId"],
+            "OrgnlMsgNmId": document_message_name(self, "FIToFICstmrCdtTrf"),
+        });
+        if let Some(created) = value_at(transfer, "GrpHdr.CreDtTm") {
+            original_group["OrgnlCreDtTm"] = created.clone();
+        }
+        changes.push(ChangeLog::new(
+            "data.document.PmtRtr.OrgnlGrpInf".to_string(),
+            "Original group header".to_string(),
+            None,
+            Some(original_group.clone())
+        ));
+
+        let mut header = json!({
+            "MsgId": message_id(),
+            "CreDtTm": timestamp(),
+            "NbOfTxs": transactions.len().to_string(),
+            "SttlmInf": settlement(&config.settlement_method, config.clearing_system.as_ref()),
+            "InstgAgt": agent(&config.instructing_agent),
+            "InstdAgt": agent(&config.instructed_agent),
+        });
+        if transactions.iter().all(|t| t["RtrdIntrBkSttlmAmt"]["@Ccy"] == currency) {
+            header["TtlRtrdIntrBkSttlmAmt"] = json!({"@Ccy": currency, "$value": amount_value(total, &currency)});
+        }
+
+        let data = json!({
+            "document": {
+                "PmtRtr": {
+                    "GrpHdr": header,
+                    "OrgnlGrpInf": original_group,
+                    "TxInf": transactions,
+                }
+            }
+        });
+        let mut payment_return = self.child(data, workflow.clone(), task.clone(), Some("pacs.004".to_string()));
+        payment_return.push_audit(AuditLog::new(
+            workflow.clone(),
+            task.clone(),
+            start_time,
+            format!("Return of pacs.008 {} with reason {}", self.id(), config.reason_code),
+            changes
+        ));
+        validate(&payment_return, "Return")?;
+
+        self.push_audit(AuditLog::new(
+            workflow,
+            task,
+            start_time,
+            description.unwrap_or_else(|| "pacs.004 payment return generated".to_string()),
+            vec![ChangeLog::new(
+                "children".to_string(),
+                format!("Returned {} {} with reason {}", format_amount(total, &currency), currency, config.reason_code),
+                None,
+                Some(json!(payment_return.id().to_string()))
+            )]
+        ));
+        Ok(payment_return)
+    }
+}
@@ -0,0 +1,178 @@
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::models::auditlog::{AuditLog, ChangeLog};
+use crate::models::errors::FunctionResponseError;
+use crate::models::executor::{ExecutionReport, Publisher, WorkflowExecutor};
+use crate::models::message::{CompiledEnrichment, Message, MessageStatus};
+use crate::models::workflow::CompiledWorkflow;
+
+/// A field of `Message::data` changed by a simulation, `None` standing for an absent field.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DataChange {
+    pub path: String,
+
+    pub old_value: Option<Value>,
+
+    pub new_value: Option<Value>,
+}
+
+/// A publication made during a simulation and not sent.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SuppressedPublication {
+    /// Id of the published message, e.g. a generated pacs.002
+    pub message_id: u64,
+
+    pub input: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TaskCondition {
+    pub task_id: String,
+
+    pub matched: bool,
+}
+
+/// A retry delay a simulation skipped instead of waiting.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PlannedWait {
+    pub task_id: String,
+
+    /// The failed attempt the delay follows
+    pub attempt: u32,
+
+    pub delay_ms: u64,
+}
+
+/// Outcome of `WorkflowExecutor::simulate`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SimulationReport {
+    pub workflow: String,
+
+    pub version: u16,
+
+    /// Whether the workflow condition holds for the message
+    pub matched: bool,
+
+    /// Task conditions evaluated, in declaration order
+    pub conditions: Vec<TaskCondition>,
+
+    pub execution: ExecutionReport,
+
+    pub publications: Vec<SuppressedPublication>,
+
+    /// Retry delays of the run, in order
+    pub waits: Vec<PlannedWait>,
+
+    pub changes: Vec<DataChange>,
+
+    /// Audit entries the run added
+    pub audit: Vec<AuditLog>,
+
+    pub status: MessageStatus,
+
+    /// The error the workflow failed with, as "function: message"
+    pub error: Option<String>,
+}
+
+/// Outcome of `Message::simulate_enrichment`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EnrichmentSimulation {
+    pub changes: Vec<DataChange>,
+
+    /// Change log of every enrichment entry, skipped ones included
+    pub entries: Vec<ChangeLog>,
+
+    pub error: Option<String>,
+}
+
+/// Records publications instead of sending them.
+#[derive(Default)]
+struct SuppressingPublisher {
+    publications: Mutex<Vec<SuppressedPublication>>,
+}
+
+impl Publisher for SuppressingPublisher {
+    fn publish(&self, message: &Message, input: &Value) -> Result<(), FunctionResponseError> {
+        self.publications.lock().unwrap().push(SuppressedPublication { message_id: message.id(), input: input.clone() });
+        Ok(())
+    }
+}
+
+fn describe(error: &FunctionResponseError) -> String {
+    format!("{}: {}", error.function, error.message)
+}
+
+fn data_changes(message: &Message, base: &Message) -> Vec<DataChange> {
+    message.changes_since(base).into_iter()
+        .map(|(path, old_value, new_value)| DataChange { path, old_value, new_value })
+        .collect()
+}
+
+impl WorkflowExecutor {
+    /// Runs `workflow` on a copy of `message`, whether its condition matches or not, with every
+    /// publication suppressed, e.g. to try a new workflow version on production samples. The
+    /// message itself is left untouched.
+    pub fn simulate(&self, workflow: &CompiledWorkflow, message: &Message, input: &Value) -> SimulationReport {
+        let publisher = Arc::new(SuppressingPublisher::default());
+        let waits = Arc::new(Mutex::new(Vec::new()));
+        let mut executor = self.clone().with_publisher(publisher.clone());
+        executor.waits = Some(waits.clone());
+        let mut simulated = message.clone();
+        let mut execution = ExecutionReport::default();
+
+        let (matched, mut error) = match workflow.matches(message) {
+            Ok(matched) => (matched, None),
+            Err(e) => (false, Some(describe(&e))),
+        };
+        if let Err(e) = executor.run_into(workflow, &mut simulated, input, &mut execution) {
+            error.get_or_insert_with(|| describe(&e));
+        }
+
+        let conditions = workflow.tasks().iter()
+            .map(|task| task.task().task_id.clone())
+            .filter_map(|task_id| {
+                let ran = [&execution.executed, &execution.repaired, &execution.failed].iter().any(|ids| ids.contains(&task_id));
+                match (ran, execution.skipped.contains(&task_id)) {
+                    (true, _) => Some(TaskCondition { task_id, matched: true }),
+                    (false, true) => Some(TaskCondition { task_id, matched: false }),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        let publications = std::mem::take(&mut *publisher.publications.lock().unwrap());
+        let waits = std::mem::take(&mut *waits.lock().unwrap());
+        SimulationReport {
+            workflow: workflow.workflow().name.clone(),
+            version: workflow.workflow().version,
+            matched,
+            conditions,
+            execution,
+            publications,
+            waits,
+            changes: data_changes(&simulated, message),
+            audit: simulated.audit()[message.audit().len()..].to_vec(),
+            status: simulated.progress().status.clone(),
+            error,
+        }
+    }
+}
+
+impl Message {
+    /// Applies `enrichment` to a copy of the message and reports what it would change.
+    pub fn simulate_enrichment(&self, enrichment: &CompiledEnrichment, input: Value) -> EnrichmentSimulation {
+        let mut simulated = self.clone();
+        let result = simulated.enrich_compiled(enrichment, input, None, "Simulation".to_string(), "Enrich".to_string());
+        let entries = match &result {
+            Ok(()) => simulated.audit().last().map(|audit| audit.changes().to_vec()).unwrap_or_default(),
+            Err(_) => Vec::new(),
+        };
+        EnrichmentSimulation {
+            changes: data_changes(&simulated, self),
+            entries,
+            error: result.err().map(|e| describe(&e)),
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+
+use crate::models::auditlog::*;
+use crate::models::errors::{error, FunctionResponseError};
+use crate::models::message::{Message, MessageStatus};
+use crate::models::amount::*;
+use crate::models::document::*;
+use crate::models::path::*;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AccountConfig {
+    /// IBAN of the reported account
+    pub iban: String,
+
+    /// Account currency, defaults to the currency of the booked entries
+    pub currency: Option<String>,
+
+    /// BIC of the account servicing institution
+    pub servicer: Option<String>,
+
+    /// Proprietary bank transaction code, defaults to the ISO PMNT domain codes
+    pub bank_transaction_code: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Camt053Config {
+    pub account: AccountConfig,
+
+    /// Opening booked balance, negative for a debit balance
+    pub opening_balance: f64,
+
+    /// Closing booked balance, negative for a debit balance
+    pub closing_balance: f64,
+
+    /// Statement date (YYYY-MM-DD), defaults to today
+    pub date: Option<String>,
+}
+
+fn bank_transaction_code(account: &AccountConfig, credit: bool) -> Value {
+    match &account.bank_transaction_code {
+        Some(code) => json!({"Prtry": {"Cd": code}}),
+        None => json!({
+            "Domn": {
+                "Cd": "PMNT",
+                "Fmly": {"Cd": if credit { "RCDT" } else { "ICDT" }, "SubFmlyCd": "OTHR"},
+            }
+        }),
+    }
+}
+
+fn account(config: &AccountConfig, currency: &str) -> Value {
+    let mut account = json!({"Id": {"IBAN": config.iban}, "Ccy": currency});
+    if let Some(servicer) = &config.servicer {
+        account["Svcr"] = agent(servicer);
+    }
+    account
+}
+
+/// A balance of `amount` minor units, negative for a debit balance.
+fn balance(code: &str, amount: i128, currency: &str, date: &str) -> Value {
+    json!({
+        "Tp": {"CdOrPrtry": {"Cd": code}},
+        "Amt": {"@Ccy": currency, "$value": amount_value(amount.abs(), currency)},
+        "CdtDbtInd": if amount < 0 { "DBIT" } else { "CRDT" },
+        "Dt": {"Dt": date},
+    })
+}
+
+/// Booked entries of a pacs.008 message on `config.iban`, one per transaction debiting or
+/// crediting the account.
+fn booked_entries(message: &Message, config: &AccountConfig) -> Result<Vec<Value>, FunctionResponseError> {
+    let transfer = value_at(message.data(), "document.FIToFICstmrCdtTrf")
+        .ok_or_else(|| error("Statement", format!("Message {} is not a parsed pacs.008", message.id())))?;
+
+    let mut entries = Vec::new();
+    for transaction in transfer["CdtTrfTxInf"].as_array().into_iter().flatten() {
+        let credit = if transaction["CdtrAcct"]["Id"]["IBAN"] == *config.iban {
+            true
+        } else if transaction["DbtrAcct"]["Id"]["IBAN"] == *config.iban {
+            false
+        } else {
+            continue;
+        };
+        let indicator = if credit { "CRDT" } else { "DBIT" };
+        let date = value_at(transaction, "IntrBkSttlmDt")
+            .or_else(|| value_at(transfer, "GrpHdr.IntrBkSttlmDt"))
+            .cloned()
+            .unwrap_or_else(|| json!(OffsetDateTime::now_utc().date().to_string()));
+
+        let mut references = json!({});
+        if let Some(message_id) = value_at(transfer, "GrpHdr.MsgId") {
+            references["MsgId"] = message_id.clone();
+        }
+        for field in ["InstrId", "EndToEndId", "UETR", "TxId"] {
+            if let Some(value) = value_at(transaction, &format!("PmtId.{}", field)) {
+                references[field] = value.clone();
+            }
+        }
+
+        let mut details = json!({
+            "Refs": references,
+            "Amt": transaction["IntrBkSttlmAmt"],
+            "CdtDbtInd": indicator,
+        });
+        for (from, to) in [("Dbtr", "RltdPties.Dbtr"), ("Cdtr", "RltdPties.Cdtr")] {
+            if let Some(value) = value_at(transaction, from) {
+                set_at(&mut details, to, json!({"Pty": value}));
+            }
+        }
+        for (from, to) in [("DbtrAcct", "RltdPties.DbtrAcct"), ("CdtrAcct", "RltdPties.CdtrAcct"), ("DbtrAgt", "RltdAgts.DbtrAgt"), ("CdtrAgt", "RltdAgts.CdtrAgt"), ("RmtInf", "RmtInf")] {
+            if let Some(value) = value_at(transaction, from) {
+                set_at(&mut details, to, value.clone());
+            }
+        }
+
+        let mut entry = json!({
+            "Amt": transaction["IntrBkSttlmAmt"],
+            "CdtDbtInd": indicator,
+            "Sts": {"Cd": "BOOK"},
+            "BookgDt": {"Dt": date},
+            "ValDt": {"Dt": date},
+            "BkTxCd": bank_transaction_code(config, credit),
+            "NtryDtls": [{"TxDtls": [details]}],
+        });
+        if let Some(reference) = value_at(transaction, "PmtId.EndToEndId") {
+            entry["NtryRef"] = reference.clone();
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Currency of the reported account, every entry has to be booked in it.
+fn currency(config: &AccountConfig, entries: &[Value]) -> Result<String, FunctionResponseError> {
+    let currency = config.currency.clone()
+        .or_else(|| entries.first().and_then(|e| e["Amt"]["@Ccy"].as_str()).map(|c| c.to_string()))
+        .ok_or_else(|| error("Statement", "Account currency is required when there are no entries".to_string()))?;
+    if let Some(entry) = entries.iter().find(|e| e["Amt"]["@Ccy"] != *currency) {
+        return Err(error("Statement", format!(
+            "Entry {} is not booked in the account currency {}", entry["NtryRef"], currency
+        )));
+    }
+    Ok(currency)
+}
+
+/// Entry amount in minor units, negative for a debit.
+fn signed_amount(entry: &Value, currency: &str) -> Result<i128, FunctionResponseError> {
+    let amount = minor_units(&entry["Amt"]["$value"], currency)?;
+    Ok(if entry["CdtDbtInd"] == "DBIT" { -amount } else { amount })
+}
+
+fn summary(entries: &[Value], currency: &str) -> Result<Value, FunctionResponseError> {
+    let totals = |indicator: Option<&str>| -> Result<Value, FunctionResponseError> {
+        let booked: Vec<&Value> = entries.iter()
+            .filter(|e| indicator.is_none_or(|indicator| e["CdtDbtInd"] == indicator))
+            .collect();
+        let sum = booked.iter().map(|e| minor_units(&e["Amt"]["$value"], currency)).sum::<Result<i128, _>>()?;
+        Ok(json!({"NbOfNtries": booked.len().to_string(), "Sum": amount_value(sum, currency)}))
+    };
+    Ok(json!({
+        "TtlNtries": totals(None)?,
+        "TtlCdtNtries": totals(Some("CRDT"))?,
+        "TtlDbtNtries": totals(Some("DBIT"))?,
+    }))
+}
+
+impl Message {
+    /// Generates a camt.054 debit/credit notification for the account in `config`, with an
+    /// entry for every transaction of this pacs.008 booked on it.
+    pub fn to_camt054(&mut self, config: &AccountConfig, description: Option<String>, workflow: String, task: String) -> Result<Message, FunctionResponseError> {
+        let start_time = OffsetDateTime::now_utc();
+        let entries = booked_entries(self, config)?;
+        if entries.is_empty() {
+            return Err(error("Statement", format!("No transaction of message {} is booked on {}", self.id(), config.iban)));
+        }
+        let currency = currency(config, &entries)?;
+
+        let changes = entries.iter().enumerate()
+            .map(|(index, entry)| ChangeLog::new(
+                format!("data.document.BkToCstmrDbtCdtNtfctn.Ntfctn.0.Ntry.{}", index),
+                format!("{} entry from message {}", entry["CdtDbtInd"].as_str().unwrap_or_default(), self.id()),
+                None,
+                Some(entry.clone())
+            ))
+            .collect();
+        let data = json!({
+            "document": {
+                "BkToCstmrDbtCdtNtfctn": {
+                    "GrpHdr": {"MsgId": message_id(), "CreDtTm": timestamp()},
+                    "Ntfctn": [{
+                        "Id": message_id(),
+                        "CreDtTm": timestamp(),
+                        "Acct": account(config, &currency),
+                        "Ntry": entries,
+                    }],
+                }
+            }
+        });
+        let mut notification = self.child(data, workflow.clone(), task.clone(), Some("camt.054".to_string()));
+        notification.push_audit(AuditLog::new(
+            workflow.clone(),
+            task.clone(),
+            start_time,
+            format!("Notification of pacs.008 {}", self.id()),
+            changes
+        ));
+        validate(&notification, "Statement")?;
+
+        self.push_audit(AuditLog::new(
+            workflow,
+            task,
+            start_time,
+            description.unwrap_or_else(|| "camt.054 notification generated".to_string()),
+            vec![ChangeLog::new(
+                "children".to_string(),
+                format!("camt.054 notification for {}", config.iban),
+                None,
+                Some(json!(notification.id().to_string()))
+            )]
+        ));
+        Ok(notification)
+    }
+
+    /// Generates a camt.053 statement of the account in `config` from the completed pacs.008
+    /// messages among `messages`. The caller supplied balances must reconcile with the booked
+    /// entries; each message contributing entries records the statement in its audit trail.
+    pub fn to_camt053(messages: &mut [Message], config: &Camt053Config, description: Option<String>, workflow: String, task: String) -> Result<Message, FunctionResponseError> {
+        let start_time = OffsetDateTime::now_utc();
+        let (tenant, origin) = match messages.first() {
+            Some(message) => (message.tenant().clone(), message.origin().clone()),
+            None => return Err(error("Statement", "A statement requires at least one message".to_string())),
+        };
+        let date = config.date.clone().unwrap_or_else(|| OffsetDateTime::now_utc().date().to_string());
+
+        let mut entries = Vec::new();
+        let mut changes = Vec::new();
+        let mut sources = Vec::new();
+        for (index, message) in messages.iter().enumerate() {
+            if message.progress().status != MessageStatus::Completed {
+                continue;
+            }
+            let booked = booked_entries(message, &config.account)?;
+            if booked.is_empty() {
+                continue;
+            }
+            for entry in booked {
+                changes.push(ChangeLog::new(
+                    format!("data.document.BkToCstmrStmt.Stmt.0.Ntry.{}", entries.len()),
+                    format!("{} entry from message {}", entry["CdtDbtInd"].as_str().unwrap_or_default(), message.id()),
+                    None,
+                    Some(entry.clone())
+                ));
+                entries.push(entry);
+            }
+            sources.push(index);
+        }
+        let currency = currency(&config.account, &entries)?;
+
+        let opening = minor_units(&json!(config.opening_balance), &currency)?;
+        let closing = minor_units(&json!(config.closing_balance), &currency)?;
+        let net = entries.iter().map(|entry| signed_amount(entry, &currency)).sum::<Result<i128, _>>()?;
+        if opening + net != closing {
+            return Err(error("Statement", format!(
+                "Closing balance {} does not reconcile with opening balance {} and entries totalling {}",
+                format_amount(closing, &currency), format_amount(opening, &currency), format_amount(net, &currency)
+            )));
+        }
+        let summary = summary(&entries, &currency)?;
+
+        let data = json!({
+            "document": {
+                "BkToCstmrStmt": {
+                    "GrpHdr": {"MsgId": message_id(), "CreDtTm": timestamp()},
+                    "Stmt": [{
+                        "Id": message_id(),
+                        "CreDtTm": timestamp(),
+                        "Acct": account(&config.account, &currency),
+                        "Bal": [
+                            balance("OPBD", opening, &currency, &date),
+                            balance("CLBD", closing, &currency, &date),
+                        ],
+                        "TxsSummry": summary,
+                        "Ntry": entries,
+                    }],
+                }
+            }
+        });
+        let mut statement = Message::generated(data, tenant, origin, workflow.clone(), task.clone(), Some("camt.053".to_string()));
+        statement.push_audit(AuditLog::new(
+            workflow.clone(),
+            task.clone(),
+            start_time,
+            format!("Statement of {} on {}", config.account.iban, date),
+            changes
+        ));
+        validate(&statement, "Statement")?;
+
+        for index in sources {
+            messages[index].push_audit(AuditLog::new(
+                workflow.clone(),
+                task.clone(),
+                start_time,
+                description.clone().unwrap_or_else(|| "Booked on camt.053 statement".to_string()),
+                vec![ChangeLog::new(
+                    "statement".to_string(),
+                    format!("camt.053 statement of {} on {}", config.account.iban, date),
+                    None,
+                    Some(json!(statement.id().to_string()))
+                )]
+            ));
+        }
+        Ok(statement)
+    }
+}
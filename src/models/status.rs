@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+
+use crate::models::auditlog::*;
+use crate::models::errors::{error, FunctionResponseError};
+use crate::models::message::{Message, MessageStatus, StatusCode};
+use crate::models::document::*;
+use crate::models::path::*;
+
+// Max105Text limit of StsRsnInf.AddtlInf
+const ADDITIONAL_INFO_LENGTH: usize = 105;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Pacs002Config {
+    /// BIC of the agent sending the status report
+    pub instructing_agent: String,
+
+    /// BIC of the agent receiving the status report
+    pub instructed_agent: String,
+
+    /// ISO external status reason code used when the message is rejected, defaults to NARR
+    pub reason_code: Option<String>,
+
+    /// Individually rejected transactions: EndToEndId to ISO external status reason code
+    #[serde(default)]
+    pub rejected_transactions: HashMap<String, String>,
+
+    /// Reports a completed message as settled (ACSC) rather than accepted (ACCP)
+    #[serde(default)]
+    pub settled: bool,
+}
+
+fn reason(code: &str, additional_info: Option<&str>) -> Value {
+    json!([reason_entry(code, additional_info)])
+}
+
+fn reason_entry(code: &str, additional_info: Option<&str>) -> Value {
+    let mut reason = json!({"Rsn": {"Cd": code}});
+    if let Some(info) = additional_info {
+        reason["AddtlInf"] = json!([info.chars().take(ADDITIONAL_INFO_LENGTH).collect::<String>()]);
+    }
+    reason
+}
+
+impl Message {
+    /// Generates a pacs.002 payment status report for a processed pacs.008/pacs.009 message.
+    /// The status follows the processing outcome: RJCT when the message failed (or `failure`
+    /// is given) and ACCP otherwise, or ACSC for a completed message the config reports as
+    /// settled. Per-transaction rejections from the config turn the group status into PART.
+    pub fn to_pacs002(&mut self, config: &Pacs002Config, failure: Option<&FunctionResponseError>, description: Option<String>, workflow: String, task: String) -> Result<Message, FunctionResponseError> {
+        let start_time = OffsetDateTime::now_utc();
+        let (root, body) = document_root(self.data())
+            .ok_or_else(|| error("StatusReport", "Message has no parsed document".to_string()))?;
+        let transactions = match body["CdtTrfTxInf"].as_array() {
+            Some(transactions) if root == "FIToFICstmrCdtTrf" || root == "FICdtTrf" => transactions,
+            _ => return Err(error("StatusReport", format!("Status reports are not supported for {}", root))),
+        };
+
+        let failed = failure.is_some()
+            || self.progress().status == MessageStatus::Failed
+            || self.progress().prev_status_code == Some(StatusCode::Failure);
+        let accepted_status = match self.progress().status {
+            MessageStatus::Completed if config.settled => "ACSC",
+            _ => "ACCP",
+        };
+        let reason_code = config.reason_code.clone().unwrap_or_else(|| "NARR".to_string());
+        let failure_info = failure.map(|e| e.message.as_str());
+
+        let mut changes = Vec::new();
+        let mut statuses = Vec::new();
+        let mut rejected = 0;
+        let mut rejection_codes: Vec<&str> = Vec::new();
+        for (index, transaction) in transactions.iter().enumerate() {
+            let end_to_end_id = transaction["PmtId"]["EndToEndId"].as_str().unwrap_or_default();
+            let mut status = json!({
+                "OrgnlEndToEndId": end_to_end_id,
+            });
+            for (from, to) in [("InstrId", "OrgnlInstrId"), ("TxId", "OrgnlTxId"), ("UETR", "OrgnlUETR")] {
+                if let Some(value) = value_at(transaction, &format!("PmtId.{}", from)) {
+                    status[to] = value.clone();
+                }
+            }
+
+            if failed {
+                status["TxSts"] = json!("RJCT");
+                status["StsRsnInf"] = reason(&reason_code, failure_info);
+                rejected += 1;
+            } else if let Some(code) = config.rejected_transactions.get(end_to_end_id) {
+                status["TxSts"] = json!("RJCT");
+                status["StsRsnInf"] = reason(code, None);
+                rejected += 1;
+                if !rejection_codes.contains(&code.as_str()) {
+                    rejection_codes.push(code);
+                }
+            } else {
+                status["TxSts"] = json!(accepted_status);
+            }
+            changes.push(ChangeLog::new(
+                format!("data.document.FIToFIPmtStsRpt.TxInfAndSts.{}.TxSts", index),
+                format!("Status of transaction {}", end_to_end_id),
+                None,
+                Some(status["TxSts"].clone())
+            ));
+            statuses.push(status);
+        }
+
+        let group_status = if transactions.is_empty() || rejected == 0 {
+            accepted_status
+        } else if rejected == transactions.len() {
+            "RJCT"
+        } else {
+            "PART"
+        };
+        let mut group = json!({
+            "OrgnlMsgId": body["GrpHdr"]["MsgId"],
+            "OrgnlMsgNmId": document_message_name(self, root),
+            "GrpSts": group_status,
+        });
+        if let Some(created) = value_at(body, "GrpHdr.CreDtTm") {
+            group["OrgnlCreDtTm"] = created.clone();
+        }
+        if group_status == "RJCT" {
+            // Transactions rejected one by one give their own reasons
+            group["StsRsnInf"] = match failed {
+                true => reason(&reason_code, failure_info),
+                false => rejection_codes.iter().map(|code| reason_entry(code, None)).collect(),
+            };
+        }
+        changes.insert(0, ChangeLog::new(
+            "data.document.FIToFIPmtStsRpt.OrgnlGrpInfAndSts.0.GrpSts".to_string(),
+            format!("Processing status {:?}", self.progress().status),
+            None,
+            Some(json!(group_status))
+        ));
+
+        let data = json!({
+            "document": {
+                "FIToFIPmtStsRpt": {
+                    "GrpHdr": {
+                        "MsgId": message_id(),
+                        "CreDtTm": timestamp(),
+                        "InstgAgt": agent(&config.instructing_agent),
+                        "InstdAgt": agent(&config.instructed_agent),
+                    },
+                    "OrgnlGrpInfAndSts": [group],
+                    "TxInfAndSts": statuses,
+                }
+            }
+        });
+        let mut report = self.child(data, workflow.clone(), task.clone(), Some("pacs.002".to_string()));
+        report.push_audit(AuditLog::new(
+            workflow.clone(),
+            task.clone(),
+            start_time,
+            "Status report generated".to_string(),
+            changes
+        ));
+        validate(&report, "StatusReport")?;
+
+        self.push_audit(AuditLog::new(
+            workflow,
+            task,
+            start_time,
+            description.unwrap_or_else(|| "pacs.002 status report generated".to_string()),
+            vec![ChangeLog::new(
+                "children".to_string(),
+                format!("pacs.002 status report with group status {}", group_status),
+                None,
+                Some(json!(report.id().to_string()))
+            )]
+        ));
+        Ok(report)
+    }
+}
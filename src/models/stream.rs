@@ -0,0 +1,179 @@
+use std::io::BufRead;
+use serde_json::Value;
+use quick_xml::events::Event;
+use quick_xml::reader::Reader;
+use quick_xml::writer::Writer;
+use quick_xml::de::from_reader;
+
+use crate::models::errors::FunctionResponseError;
+use crate::models::iso20022::ISO20022Message;
+
+/// Repeating elements that carry one transaction each in bulk ISO20022 messages.
+pub const TRANSACTION_ELEMENTS: [&str; 4] = ["CdtTrfTxInf", "DrctDbtTxInf", "TxInf", "TxInfAndSts"];
+
+struct Frame {
+    name: Vec<u8>,
+    content: Vec<u8>,
+    has_transactions: bool,
+}
+
+/// Iterates an ISO20022 document one transaction at a time.
+///
+/// Each item is a complete single-transaction document (group header, the enclosing
+/// blocks such as `PmtInf`, and one transaction) in the same JSON shape as `Message::data`,
+/// validated on its own. Only the headers and the current transaction are held in memory.
+pub struct TransactionStream<R: BufRead> {
+    reader: Reader<R>,
+    buffer: Vec<u8>,
+    frames: Vec<Frame>,
+    transaction: Option<(Vec<u8>, usize)>,
+    emitted: usize,
+    finished: bool,
+}
+
+impl<R: BufRead> TransactionStream<R> {
+    pub fn new(source: R) -> Self {
+        let mut reader = Reader::from_reader(source);
+        reader.trim_text(true);
+        TransactionStream {
+            reader,
+            buffer: Vec::new(),
+            frames: Vec::new(),
+            transaction: None,
+            emitted: 0,
+            finished: false,
+        }
+    }
+
+    pub fn emitted(&self) -> usize {
+        self.emitted
+    }
+
+    fn error(message: String) -> FunctionResponseError {
+        FunctionResponseError::new("Parse".to_string(), 400, message)
+    }
+
+    fn write(target: &mut Vec<u8>, event: &Event) -> Result<(), FunctionResponseError> {
+        Writer::new(target)
+            .write_event(event)
+            .map_err(|e| Self::error(format!("ISO20022 streaming error: {:?}", e)))
+    }
+
+    fn document(&self, transaction: &[u8]) -> Vec<u8> {
+        let mut xml = Vec::new();
+        for frame in &self.frames {
+            xml.extend_from_slice(&frame.content);
+        }
+        xml.extend_from_slice(transaction);
+        for frame in self.frames.iter().rev() {
+            xml.extend_from_slice(b"</");
+            xml.extend_from_slice(&frame.name);
+            xml.push(b'>');
+        }
+        xml
+    }
+
+    fn convert(xml: &[u8]) -> Result<Value, FunctionResponseError> {
+        let message = from_reader::<_, ISO20022Message>(xml)
+            .map_err(|e| Self::error(format!("ISO20022 parsing error: {:?}", e)))?;
+        message.validate()
+            .map_err(|e| Self::error(format!("Schema validation error: {:?}", e)))?;
+        serde_json::to_value(message)
+            .map_err(|e| Self::error(format!("ISO20022 conversion error: {:?}", e)))
+    }
+
+    fn next_document(&mut self) -> Result<Option<Vec<u8>>, FunctionResponseError> {
+        loop {
+            self.buffer.clear();
+            let event = self.reader.read_event_into(&mut self.buffer)
+                .map_err(|e| Self::error(format!("ISO20022 parsing error: {:?}", e)))?
+                .into_owned();
+
+            // Inside a transaction: copy everything until its closing tag
+            if let Some((content, depth)) = self.transaction.as_mut() {
+                Self::write(content, &event)?;
+                match event {
+                    Event::Start(_) => *depth += 1,
+                    Event::End(_) => *depth -= 1,
+                    Event::Eof => return Err(Self::error("Unexpected end of document".to_string())),
+                    _ => {}
+                }
+                if *depth == 0 {
+                    let (content, _) = self.transaction.take().unwrap();
+                    if let Some(parent) = self.frames.last_mut() {
+                        parent.has_transactions = true;
+                    }
+                    return Ok(Some(self.document(&content)));
+                }
+                continue;
+            }
+
+            match event {
+                Event::Start(ref start) => {
+                    let name = start.name().as_ref().to_vec();
+                    let local_name = start.local_name();
+                    let is_transaction = self.frames.len() >= 2 && TRANSACTION_ELEMENTS
+                        .iter()
+                        .any(|element| element.as_bytes() == local_name.as_ref());
+                    if is_transaction {
+                        let mut content = Vec::new();
+                        Self::write(&mut content, &event)?;
+                        self.transaction = Some((content, 1));
+                    } else {
+                        let mut content = Vec::new();
+                        Self::write(&mut content, &event)?;
+                        self.frames.push(Frame { name, content, has_transactions: false });
+                    }
+                }
+                Event::End(_) => {
+                    let Some(mut frame) = self.frames.pop() else {
+                        return Err(Self::error("Unbalanced closing element".to_string()));
+                    };
+                    // Blocks that enclosed transactions were already emitted with them
+                    if frame.has_transactions {
+                        continue;
+                    }
+                    Self::write(&mut frame.content, &event)?;
+                    match self.frames.last_mut() {
+                        Some(parent) => parent.content.extend_from_slice(&frame.content),
+                        // Documents without repeating transactions are returned whole
+                        None if self.emitted == 0 => return Ok(Some(frame.content)),
+                        None => {}
+                    }
+                }
+                Event::Empty(_) | Event::Text(_) | Event::CData(_) => {
+                    if let Some(frame) = self.frames.last_mut() {
+                        Self::write(&mut frame.content, &event)?;
+                    }
+                }
+                Event::Eof => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for TransactionStream<R> {
+    type Item = Result<Value, FunctionResponseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        match self.next_document() {
+            Ok(Some(xml)) => {
+                self.emitted += 1;
+                Some(Self::convert(&xml))
+            }
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(e) => {
+                // Malformed XML cannot be resynchronised, so the stream ends here
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
@@ -1,7 +1,12 @@
-use serde::Deserialize;
+use std::time::Duration;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
+use crate::models::errors::{ErrorKind, FunctionResponseError};
+use crate::models::status::Pacs002Config;
 
-#[derive(Debug, Deserialize, Clone, PartialEq)]
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Task {
     pub task_id: String,
 
@@ -13,13 +18,101 @@ pub struct Task {
 
     pub function: FunctionType,
 
+    /// Enrich: a list of `EnrichmentConfig`, Validate: a `ValidateInput`, Publish: an object,
+    /// SubWorkflow: a `SubWorkflowInput`, Foreach: a `ForeachInput`
     pub input: serde_json::Value,
+
+    /// Tasks that must finish first. Without it the task follows the previous one; an empty
+    /// list lets it run alongside other independent tasks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depends_on: Option<Vec<String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+
+    /// What happens when the task fails, instead of the workflow `on_failure`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_failure: Option<FailureHandler>,
+
+    /// Task undoing the effects of this one when a later task fails the workflow
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compensation: Option<Box<Task>>,
+}
+
+
+/// Handling of a failed task once its retries are exhausted.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FailureHandler {
+    /// Runs these tasks in order; when they succeed the workflow carries on
+    Repair { tasks: Vec<Task> },
+
+    /// Publishes a pacs.002 rejection of the message to `queue`
+    Reject { queue: String, status_report: Pacs002Config },
+
+    /// Publishes the failed message to `queue`
+    ExceptionQueue { queue: String },
+}
+
+
+/// How often and how soon a failed task runs again.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts including the first one
+    pub max_attempts: u32,
+
+    pub backoff: Backoff,
+
+    /// Up to this fraction of each delay is added at random, between 0 and 1
+    #[serde(default)]
+    pub jitter: f64,
+
+    #[serde(default = "transient_errors")]
+    pub retry_on: Vec<ErrorKind>,
+}
+
+fn transient_errors() -> Vec<ErrorKind> {
+    vec![ErrorKind::Conflict, ErrorKind::Timeout, ErrorKind::Unavailable]
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Backoff {
+    Fixed { delay_ms: u64 },
+    /// Doubles from `initial_ms` on each retry, up to `max_ms`
+    Exponential { initial_ms: u64, max_ms: u64 },
+}
+
+impl RetryPolicy {
+    /// Whether `error` on attempt `attempt` (starting at 1) is retried.
+    pub fn retries(&self, attempt: u32, error: &FunctionResponseError) -> bool {
+        attempt < self.max_attempts && self.retry_on.contains(&error.kind())
+    }
+
+    /// Wait before retry `retry` (starting at 1), jitter included.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let delay_ms = match self.backoff {
+            Backoff::Fixed { delay_ms } => delay_ms,
+            Backoff::Exponential { initial_ms, max_ms } => initial_ms
+                .saturating_mul(2u64.saturating_pow(retry.saturating_sub(1)))
+                .min(max_ms),
+        };
+        let jitter = match self.jitter > 0.0 {
+            true => rand::thread_rng().gen_range(0.0..=self.jitter),
+            false => 0.0,
+        };
+        Duration::from_millis(delay_ms).mul_f64(1.0 + jitter)
+    }
 }
 
 
-#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum FunctionType {
     Validate,
     Enrich,
     Publish,
+    /// Runs another registered workflow on the message
+    SubWorkflow,
+    /// Runs tasks or an enrichment on each element of an array in the message
+    Foreach,
 }
@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::models::auditlog::AuditLog;
+use crate::models::logic::CompiledRule;
+use crate::models::message::Message;
+
+/// Rule tracing: which messages are traced and how much trace each may carry.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TraceConfig {
+    /// Tenants whose messages are traced, every tenant when empty
+    #[serde(default)]
+    pub tenants: Vec<String>,
+
+    /// Cap on the serialized size of the rule traces attached to the audit entries of one message
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: usize,
+}
+
+fn default_max_bytes() -> usize {
+    4096
+}
+
+impl Default for TraceConfig {
+    fn default() -> Self {
+        Self { tenants: Vec::new(), max_bytes: default_max_bytes() }
+    }
+}
+
+impl TraceConfig {
+    pub fn traces(&self, tenant: &str) -> bool {
+        self.tenants.is_empty() || self.tenants.iter().any(|t| t == tenant)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TraceInput {
+    pub path: String,
+
+    pub value: Value,
+}
+
+/// One rule evaluation: the data it read and its outcome.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RuleTrace {
+    /// Where the rule is defined, e.g. input[0].rule or condition
+    pub rule_id: String,
+
+    pub rule: Value,
+
+    pub inputs: Vec<TraceInput>,
+
+    #[serde(default)]
+    pub result: Option<Value>,
+
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Rule traces attached to an `AuditLog`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct EvaluationTrace {
+    pub rules: Vec<RuleTrace>,
+
+    /// Evaluations left out to stay within `TraceConfig::max_bytes`
+    #[serde(default)]
+    pub omitted: usize,
+}
+
+/// Collects the rule traces of one audit entry, within what is left of the message budget. A
+/// disabled tracer records nothing.
+pub(crate) struct RuleTracer {
+    max_bytes: Option<usize>,
+    used: usize,
+    trace: EvaluationTrace,
+}
+
+fn size(trace: &RuleTrace) -> usize {
+    serde_json::to_string(trace).map(|json| json.len()).unwrap_or(usize::MAX)
+}
+
+/// Serialized size of the rule traces of `audit`.
+fn traced_bytes(audit: &[AuditLog]) -> usize {
+    audit.iter()
+        .filter_map(|audit| audit.trace())
+        .flat_map(|trace| &trace.rules)
+        .map(size)
+        .sum()
+}
+
+/// Trims the traces of the audit entries of `message` from `start` on to the budget the entries
+/// before leave, e.g. once parallel branches each tracing within the whole budget are merged.
+/// Rule traces past the cap are counted as omitted.
+pub(crate) fn cap_traces(config: Option<&TraceConfig>, message: &mut Message, start: usize) {
+    let Some(config) = config else {
+        return;
+    };
+    let mut used = traced_bytes(&message.audit()[..start]);
+    for trace in message.audit_mut()[start..].iter_mut().filter_map(|audit| audit.trace_mut()) {
+        for rule in std::mem::take(&mut trace.rules) {
+            let size = size(&rule);
+            if used + size > config.max_bytes {
+                trace.omitted += 1;
+                continue;
+            }
+            used += size;
+            trace.rules.push(rule);
+        }
+    }
+}
+
+fn omitted(value: &Value) -> Value {
+    Value::String(format!("<{} bytes omitted>", value.to_string().len()))
+}
+
+impl RuleTracer {
+    pub(crate) fn disabled() -> Self {
+        Self { max_bytes: None, used: 0, trace: EvaluationTrace::default() }
+    }
+
+    /// A tracer for `message`, disabled when `config` does not trace its tenant. The traces
+    /// already in its audit count towards the cap.
+    pub(crate) fn new(config: Option<&TraceConfig>, message: &Message) -> Self {
+        match config {
+            Some(config) if config.traces(message.tenant()) => {
+                Self { max_bytes: Some(config.max_bytes), used: traced_bytes(message.audit()), ..Self::disabled() }
+            }
+            _ => Self::disabled(),
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.max_bytes.is_some()
+    }
+
+    /// Records an evaluation of `rule` against `data`. Past the size cap the values read and the
+    /// result are replaced by their size, and traces still too large are counted as omitted.
+    pub(crate) fn record(&mut self, rule_id: &str, rule: &CompiledRule, data: &Value, result: &Result<Value, String>) {
+        let Some(max_bytes) = self.max_bytes else {
+            return;
+        };
+        let mut trace = RuleTrace {
+            rule_id: rule_id.to_string(),
+            rule: rule.source().clone(),
+            inputs: rule.inputs(data).into_iter().map(|(path, value)| TraceInput { path, value }).collect(),
+            result: result.as_ref().ok().cloned(),
+            error: result.as_ref().err().cloned(),
+        };
+        if self.used + size(&trace) > max_bytes {
+            trace.inputs.iter_mut().for_each(|input| input.value = omitted(&input.value));
+            trace.result = trace.result.as_ref().map(omitted);
+        }
+        let size = size(&trace);
+        if self.used + size > max_bytes {
+            self.trace.omitted += 1;
+            return;
+        }
+        self.used += size;
+        self.trace.rules.push(trace);
+    }
+
+    pub(crate) fn finish(self) -> Option<EvaluationTrace> {
+        (self.enabled() && (!self.trace.rules.is_empty() || self.trace.omitted > 0)).then_some(self.trace)
+    }
+}
@@ -0,0 +1,280 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+
+use crate::models::auditlog::*;
+use crate::models::errors::{error, FunctionResponseError};
+use crate::models::message::Message;
+use crate::models::amount::*;
+use crate::models::document::*;
+use crate::models::path::*;
+
+// pain.001 CdtTrfTxInf fields copied onto the pacs.008 CdtTrfTxInf
+const TRANSACTION_MAPPING: [(&str, &str); 21] = [
+    ("PmtId.InstrId", "PmtId.InstrId"),
+    ("PmtId.EndToEndId", "PmtId.EndToEndId"),
+    ("PmtId.UETR", "PmtId.UETR"),
+    ("PmtTpInf", "PmtTpInf"),
+    ("Amt.InstdAmt", "IntrBkSttlmAmt"),
+    ("Amt.InstdAmt", "InstdAmt"),
+    ("ChrgBr", "ChrgBr"),
+    ("UltmtDbtr", "UltmtDbtr"),
+    ("IntrmyAgt1", "IntrmyAgt1"),
+    ("IntrmyAgt2", "IntrmyAgt2"),
+    ("IntrmyAgt3", "IntrmyAgt3"),
+    ("CdtrAgt", "CdtrAgt"),
+    ("CdtrAgtAcct", "CdtrAgtAcct"),
+    ("Cdtr", "Cdtr"),
+    ("CdtrAcct", "CdtrAcct"),
+    ("UltmtCdtr", "UltmtCdtr"),
+    ("InstrForCdtrAgt", "InstrForCdtrAgt"),
+    ("Purp", "Purp"),
+    ("RgltryRptg", "RgltryRptg"),
+    ("Tax", "Tax"),
+    ("RmtInf", "RmtInf"),
+];
+
+// pain.001 PmtInf fields used where the transaction does not override them
+const INSTRUCTION_MAPPING: [(&str, &str); 8] = [
+    ("PmtTpInf", "PmtTpInf"),
+    ("ReqdExctnDt.Dt", "IntrBkSttlmDt"),
+    ("ChrgBr", "ChrgBr"),
+    ("UltmtDbtr", "UltmtDbtr"),
+    ("Dbtr", "Dbtr"),
+    ("DbtrAcct", "DbtrAcct"),
+    ("DbtrAgt", "DbtrAgt"),
+    ("DbtrAgtAcct", "DbtrAgtAcct"),
+];
+
+const REQUIRED_FIELDS: [&str; 5] = ["IntrBkSttlmAmt", "Dbtr", "DbtrAgt", "CdtrAgt", "Cdtr"];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum Bulking {
+    /// One pacs.008 per pain.001 transaction
+    Transaction,
+    /// One pacs.008 per pain.001 payment instruction (`PmtInf`)
+    Instruction,
+    /// A single pacs.008 carrying every transaction
+    Message,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Pacs008Config {
+    /// BIC of the instructing agent (`InstgAgt`)
+    pub instructing_agent: String,
+
+    /// BIC of the instructed agent (`InstdAgt`)
+    pub instructed_agent: String,
+
+    /// Settlement method code: INDA, INGA, COVE or CLRG
+    pub settlement_method: String,
+
+    /// Clearing system code, used with CLRG settlement
+    pub clearing_system: Option<String>,
+
+    /// Interbank settlement date, defaults to the requested execution date
+    pub settlement_date: Option<String>,
+
+    /// Charge bearer used when the pain.001 does not specify one
+    pub charge_bearer: Option<String>,
+
+    pub bulking: Bulking,
+}
+
+struct MappedTransaction {
+    value: Value,
+    changes: Vec<ChangeLog>,
+    group: usize,
+}
+
+fn map_fields(source: &Value, source_prefix: &str, mapping: &[(&str, &str)], target: &mut Value, target_prefix: &str, changes: &mut Vec<ChangeLog>) {
+    for (from, to) in mapping {
+        if value_at(target, to).is_some() {
+            continue;
+        }
+        if let Some(value) = value_at(source, from) {
+            set_at(target, to, value.clone());
+            changes.push(ChangeLog::new(
+                format!("{}.{}", target_prefix, to),
+                format!("Mapped from {}.{}", source_prefix, from),
+                None,
+                Some(value.clone())
+            ));
+        }
+    }
+}
+
+fn map_transaction(initiation: &Value, instruction_index: usize, transaction_index: usize, config: &Pacs008Config) -> (Value, Vec<ChangeLog>) {
+    let instruction = &initiation["PmtInf"][instruction_index];
+    let transaction = &instruction["CdtTrfTxInf"][transaction_index];
+    let source_prefix = format!("CstmrCdtTrfInitn.PmtInf.{}.CdtTrfTxInf.{}", instruction_index, transaction_index);
+    let instruction_prefix = format!("CstmrCdtTrfInitn.PmtInf.{}", instruction_index);
+
+    let mut target = json!({});
+    let mut changes = Vec::new();
+    map_fields(transaction, &source_prefix, &TRANSACTION_MAPPING, &mut target, "CdtTrfTxInf", &mut changes);
+    map_fields(instruction, &instruction_prefix, &INSTRUCTION_MAPPING, &mut target, "CdtTrfTxInf", &mut changes);
+    map_fields(initiation, "CstmrCdtTrfInitn", &[("GrpHdr.InitgPty", "InitgPty")], &mut target, "CdtTrfTxInf", &mut changes);
+
+    // A requested execution date and time settles on its date
+    if let (None, Some(Value::String(date_time))) = (value_at(&target, "IntrBkSttlmDt"), value_at(instruction, "ReqdExctnDt.DtTm")) {
+        let date = date_time.split('T').next().unwrap_or_default().to_string();
+        changes.push(ChangeLog::new(
+            "CdtTrfTxInf.IntrBkSttlmDt".to_string(),
+            format!("Mapped from {}.ReqdExctnDt.DtTm", instruction_prefix),
+            None,
+            Some(json!(date))
+        ));
+        set_at(&mut target, "IntrBkSttlmDt", json!(date));
+    }
+
+    if let Some(date) = &config.settlement_date {
+        set_at(&mut target, "IntrBkSttlmDt", json!(date));
+        changes.push(ChangeLog::new("CdtTrfTxInf.IntrBkSttlmDt".to_string(), "Configured settlement date".to_string(), None, Some(json!(date))));
+    }
+    if value_at(&target, "ChrgBr").is_none() {
+        let charge_bearer = config.charge_bearer.clone().unwrap_or_else(|| "SHAR".to_string());
+        changes.push(ChangeLog::new("CdtTrfTxInf.ChrgBr".to_string(), "Default charge bearer".to_string(), None, Some(json!(charge_bearer))));
+        set_at(&mut target, "ChrgBr", json!(charge_bearer));
+    }
+    (target, changes)
+}
+
+impl Message {
+    /// Maps a parsed pain.001 customer credit transfer initiation into interbank pacs.008
+    /// messages. Each result is a validated child `Message` linked through `parent_id`, with
+    /// every mapped field recorded in its audit trail.
+    pub fn to_pacs008(&mut self, config: &Pacs008Config, description: Option<String>, workflow: String, task: String) -> Result<Vec<Message>, FunctionResponseError> {
+        let start_time = OffsetDateTime::now_utc();
+        let initiation = match value_at(self.data(), "document.CstmrCdtTrfInitn") {
+            Some(initiation) => initiation,
+            None => return Err(error("Transform", "Message is not a parsed pain.001".to_string())),
+        };
+
+        let mut mapped = Vec::new();
+        let instructions = initiation["PmtInf"].as_array().map(|v| v.len()).unwrap_or(0);
+        for instruction_index in 0..instructions {
+            let transactions = initiation["PmtInf"][instruction_index]["CdtTrfTxInf"].as_array().map(|v| v.len()).unwrap_or(0);
+            for transaction_index in 0..transactions {
+                let (value, changes) = map_transaction(initiation, instruction_index, transaction_index, config);
+                for field in REQUIRED_FIELDS {
+                    if value_at(&value, field).is_none() {
+                        return Err(error("Transform", format!(
+                            "PmtInf {} transaction {} has no value for {}", instruction_index, transaction_index, field
+                        )));
+                    }
+                }
+                let group = match config.bulking {
+                    Bulking::Transaction => mapped.len(),
+                    Bulking::Instruction => instruction_index,
+                    Bulking::Message => 0,
+                };
+                mapped.push(MappedTransaction { value, changes, group });
+            }
+        }
+        if mapped.is_empty() {
+            return Err(error("Transform", "pain.001 contains no transactions".to_string()));
+        }
+
+        let mut groups: Vec<Vec<MappedTransaction>> = Vec::new();
+        for transaction in mapped {
+            match groups.last_mut() {
+                Some(group) if group[0].group == transaction.group => group.push(transaction),
+                _ => groups.push(vec![transaction]),
+            }
+        }
+
+        let mut messages = Vec::new();
+        let mut changes = Vec::new();
+        for group in groups {
+            let child = self.pacs008(group, config, workflow.clone(), task.clone())?;
+            validate(&child, "Transform")?;
+            changes.push(ChangeLog::new(
+                "children".to_string(),
+                "pacs.008 message created".to_string(),
+                None,
+                Some(json!(child.id().to_string()))
+            ));
+            messages.push(child);
+        }
+
+        self.push_audit(AuditLog::new(
+            workflow,
+            task,
+            start_time,
+            description.unwrap_or_else(|| "pain.001 transformed to pacs.008".to_string()),
+            changes
+        ));
+        Ok(messages)
+    }
+
+    fn pacs008(&self, transactions: Vec<MappedTransaction>, config: &Pacs008Config, workflow: String, task: String) -> Result<Message, FunctionResponseError> {
+        let start_time = OffsetDateTime::now_utc();
+        let message_id = message_id();
+        let mut changes = Vec::new();
+
+        let currency = transactions[0].value["IntrBkSttlmAmt"]["@Ccy"].as_str().unwrap_or_default().to_string();
+        let single_currency = transactions.iter().all(|t| t.value["IntrBkSttlmAmt"]["@Ccy"] == currency);
+        let total = match single_currency {
+            true => Some(transactions.iter()
+                .map(|t| minor_units(&t.value["IntrBkSttlmAmt"]["$value"], &currency))
+                .sum::<Result<i128, _>>()?),
+            false => None,
+        };
+        let settlement_date = transactions[0].value["IntrBkSttlmDt"].clone();
+
+        let mut items = Vec::new();
+        for (index, transaction) in transactions.into_iter().enumerate() {
+            for change in transaction.changes {
+                changes.push(ChangeLog::new(
+                    format!("data.document.FIToFICstmrCdtTrf.{}", change.field().replacen("CdtTrfTxInf", &format!("CdtTrfTxInf.{}", index), 1)),
+                    change.reason().to_string(),
+                    None,
+                    change.new_value().cloned()
+                ));
+            }
+            items.push(transaction.value);
+        }
+
+        let mut header = json!({
+            "MsgId": message_id,
+            "CreDtTm": timestamp(),
+            "NbOfTxs": items.len().to_string(),
+            "SttlmInf": settlement(&config.settlement_method, config.clearing_system.as_ref()),
+            "InstgAgt": agent(&config.instructing_agent),
+            "InstdAgt": agent(&config.instructed_agent),
+        });
+        if let Some(total) = total {
+            header["TtlIntrBkSttlmAmt"] = json!({"@Ccy": currency, "$value": amount_value(total, &currency)});
+        }
+        if !settlement_date.is_null() {
+            header["IntrBkSttlmDt"] = settlement_date;
+        }
+        for (field, value) in header.as_object().unwrap() {
+            changes.push(ChangeLog::new(
+                format!("data.document.FIToFICstmrCdtTrf.GrpHdr.{}", field),
+                "Generated group header".to_string(),
+                None,
+                Some(value.clone())
+            ));
+        }
+
+        let data = json!({
+            "document": {
+                "FIToFICstmrCdtTrf": {
+                    "GrpHdr": header,
+                    "CdtTrfTxInf": items,
+                }
+            }
+        });
+        let mut child = self.child(data, workflow.clone(), task.clone(), Some("pacs.008".to_string()));
+        child.push_audit(AuditLog::new(
+            workflow,
+            task,
+            start_time,
+            "Mapped from pain.001".to_string(),
+            changes
+        ));
+        Ok(child)
+    }
+}
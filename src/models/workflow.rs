@@ -1,8 +1,19 @@
-use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use crate::models::task::*;
+use crate::models::errors::{error, FunctionResponseError};
+use crate::models::library::TaskLibrary;
+use crate::models::logic::{is_truthy, CompiledRule, RuleEvaluator};
+use crate::models::message::{CompiledEnrichment, EnrichmentConfig, Message};
+use crate::models::operators::sha256;
+use crate::models::status::Pacs002Config;
+use crate::models::trace::RuleTracer;
 
 
-#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Workflow {
     pub name: String,
 
@@ -18,10 +29,14 @@ pub struct Workflow {
     pub tasks: Vec<Task>,
 
     pub condition: serde_json::Value,
+
+    /// Handling of failed tasks without their own `on_failure`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_failure: Option<FailureHandler>,
 }
 
 
-#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum WorkflowStatus {
     Draft,
     Active,
@@ -29,3 +44,520 @@ pub enum WorkflowStatus {
 }
 
 
+/// A problem in a workflow definition, e.g. at tasks[2].input[0].rule
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DefinitionError {
+    pub location: String,
+
+    pub message: String,
+}
+
+/// `input` of a `Validate` task: the market practice profile and identifier checks to apply.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ValidateInput {
+    /// Name of a builtin profile, or of one in the `ProfileRegistry` of the executor
+    #[serde(default)]
+    pub profile: Option<String>,
+
+    #[serde(default)]
+    pub identifiers: bool,
+}
+
+/// `input` of a `SubWorkflow` task: the registered workflow to run and the input it receives.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SubWorkflowInput {
+    pub workflow: String,
+
+    /// Defaults to the highest active version
+    #[serde(default)]
+    pub version: Option<u16>,
+
+    /// Rule evaluated against the message context giving the sub-workflow input. Without it
+    /// the sub-workflow gets the input of the calling workflow.
+    #[serde(default)]
+    pub input: Option<Value>,
+}
+
+/// `input` of a `Foreach` task: the array of `Message::data` to iterate and what runs on each
+/// element, with the element under `item` and its position under `index` in the rule context.
+/// Fields starting with `item.` are written to the element.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ForeachInput {
+    /// e.g. data.document.FIToFICstmrCdtTrf.CdtTrfTxInf
+    pub path: String,
+
+    /// Tasks run in order on each element
+    #[serde(default)]
+    pub tasks: Vec<Task>,
+
+    /// Enrichment applied to each element, instead of `tasks`
+    #[serde(default)]
+    pub enrichment: Vec<EnrichmentConfig>,
+
+    #[serde(default)]
+    pub on_element_failure: ElementFailurePolicy,
+}
+
+/// What happens when a `Foreach` task fails on an element.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ElementFailurePolicy {
+    /// Fails the task, rolling back the changes made to every element
+    #[default]
+    Abort,
+    /// Rolls back the changes made to the element and carries on with the next one
+    Skip,
+}
+
+fn validate_enrichment(config: &[EnrichmentConfig], location: &str, logic: &RuleEvaluator, errors: &mut Vec<DefinitionError>) {
+    for (entry, cfg) in config.iter().enumerate() {
+        if let Err(e) = logic.compile(&cfg.rule) {
+            errors.push(DefinitionError { location: format!("{}[{}].rule", location, entry), message: e });
+        }
+        if let Some(Err(e)) = cfg.condition.as_ref().map(|condition| logic.compile(condition)) {
+            errors.push(DefinitionError { location: format!("{}[{}].condition", location, entry), message: e });
+        }
+    }
+}
+
+fn validate_task(task: &Task, location: &str, logic: &RuleEvaluator, top_level: bool, errors: &mut Vec<DefinitionError>) {
+    let report = |errors: &mut Vec<DefinitionError>, location: String, message: String| errors.push(DefinitionError { location, message });
+    if let Err(e) = logic.compile(&task.condition) {
+        report(errors, format!("{}.condition", location), e);
+    }
+
+    let input = format!("{}.input", location);
+    match task.function {
+        FunctionType::Enrich => match serde_json::from_value::<Vec<EnrichmentConfig>>(task.input.clone()) {
+            Ok(config) => validate_enrichment(&config, &input, logic, errors),
+            Err(e) => report(errors, input, format!("expected a list of enrichment configs: {}", e)),
+        },
+        FunctionType::Validate => match serde_json::from_value::<ValidateInput>(task.input.clone()) {
+            Ok(ValidateInput { profile: Some(profile), .. }) if profile.trim().is_empty() => {
+                report(errors, format!("{}.profile", input), "is empty".to_string());
+            }
+            Ok(_) => {}
+            Err(e) => report(errors, input, format!("expected a validation input: {}", e)),
+        },
+        FunctionType::Publish => if !task.input.is_object() {
+            report(errors, input, "expected an object".to_string());
+        },
+        FunctionType::SubWorkflow => match serde_json::from_value::<SubWorkflowInput>(task.input.clone()) {
+            Ok(call) => {
+                if call.workflow.trim().is_empty() {
+                    report(errors, format!("{}.workflow", input), "is empty".to_string());
+                }
+                if let Some(Err(e)) = call.input.as_ref().map(|rule| logic.compile(rule)) {
+                    report(errors, format!("{}.input", input), e);
+                }
+            }
+            Err(e) => report(errors, input, format!("expected a sub-workflow input: {}", e)),
+        },
+        FunctionType::Foreach => match serde_json::from_value::<ForeachInput>(task.input.clone()) {
+            Ok(foreach) => {
+                if !foreach.path.starts_with("data.") {
+                    report(errors, format!("{}.path", input), "must start with data.".to_string());
+                }
+                if foreach.tasks.is_empty() == foreach.enrichment.is_empty() {
+                    report(errors, input.clone(), "expected either tasks or enrichment".to_string());
+                }
+                validate_enrichment(&foreach.enrichment, &format!("{}.enrichment", input), logic, errors);
+                for (index, nested) in foreach.tasks.iter().enumerate() {
+                    let location = format!("{}.tasks[{}]", input, index);
+                    if nested.function == FunctionType::Foreach {
+                        report(errors, format!("{}.function", location), "Foreach tasks cannot be nested".to_string());
+                    }
+                    validate_task(nested, &location, logic, false, errors);
+                }
+            }
+            Err(e) => report(errors, input, format!("expected a foreach input: {}", e)),
+        },
+    }
+
+    if let Some(retry) = &task.retry {
+        if retry.max_attempts == 0 {
+            report(errors, format!("{}.retry.max_attempts", location), "must be at least 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&retry.jitter) {
+            report(errors, format!("{}.retry.jitter", location), "must be between 0 and 1".to_string());
+        }
+    }
+
+    // Repair and compensation tasks run on their own, outside the dependency graph
+    if !top_level {
+        for (field, set) in [("depends_on", task.depends_on.is_some()), ("on_failure", task.on_failure.is_some()), ("compensation", task.compensation.is_some())] {
+            if set {
+                report(errors, format!("{}.{}", location, field), "is only supported on workflow tasks".to_string());
+            }
+        }
+        return;
+    }
+    if let Some(handler) = &task.on_failure {
+        validate_handler(handler, &format!("{}.on_failure", location), logic, errors);
+    }
+    if let Some(compensation) = &task.compensation {
+        validate_task(compensation, &format!("{}.compensation", location), logic, false, errors);
+    }
+}
+
+fn validate_handler(handler: &FailureHandler, location: &str, logic: &RuleEvaluator, errors: &mut Vec<DefinitionError>) {
+    match handler {
+        FailureHandler::Repair { tasks } => for (index, task) in tasks.iter().enumerate() {
+            validate_task(task, &format!("{}.tasks[{}]", location, index), logic, false, errors);
+        },
+        FailureHandler::Reject { queue, .. } | FailureHandler::ExceptionQueue { queue } => if queue.trim().is_empty() {
+            errors.push(DefinitionError { location: format!("{}.queue", location), message: "is empty".to_string() });
+        },
+    }
+}
+
+fn definition_files(dir: &Path) -> Result<Vec<PathBuf>, FunctionResponseError> {
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .map_err(|e| error("Workflow", format!("Cannot read directory {}: {}", dir.display(), e)))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| matches!(path.extension().and_then(|e| e.to_str()), Some("json" | "yaml" | "yml")))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+impl Workflow {
+    pub fn from_json(definition: &str) -> Result<Workflow, FunctionResponseError> {
+        serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_str(definition))
+            .map_err(|e| error("Workflow", format!("Invalid JSON workflow: {}: {}", e.path(), e.inner())))
+    }
+
+    pub fn from_yaml(definition: &str) -> Result<Workflow, FunctionResponseError> {
+        // YAML errors carry their location already
+        serde_yaml::from_str(definition)
+            .map_err(|e| error("Workflow", format!("Invalid YAML workflow: {}", e)))
+    }
+
+    /// A workflow from an already parsed definition, e.g. one resolved by a `TaskLibrary`.
+    pub fn from_value(definition: Value) -> Result<Workflow, FunctionResponseError> {
+        serde_path_to_error::deserialize(definition)
+            .map_err(|e| error("Workflow", format!("Invalid workflow: {}: {}", e.path(), e.inner())))
+    }
+
+    pub fn to_json(&self) -> Result<String, FunctionResponseError> {
+        serde_json::to_string_pretty(self).map_err(|e| error("Workflow", e.to_string()))
+    }
+
+    pub fn to_yaml(&self) -> Result<String, FunctionResponseError> {
+        serde_yaml::to_string(self).map_err(|e| error("Workflow", e.to_string()))
+    }
+
+    /// Loads a .json, .yaml or .yml workflow definition.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Workflow, FunctionResponseError> {
+        let path = path.as_ref();
+        let definition = fs::read_to_string(path)
+            .map_err(|e| error("Workflow", format!("Cannot read workflow {}: {}", path.display(), e)))?;
+        let workflow = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Workflow::from_json(&definition),
+            Some("yaml" | "yml") => Workflow::from_yaml(&definition),
+            _ => return Err(error("Workflow", format!("Unsupported workflow file {}", path.display()))),
+        };
+        workflow.map_err(|e| error("Workflow", format!("{}: {}", path.display(), e.message)))
+    }
+
+    /// Loads a .json, .yaml or .yml workflow definition whose tasks may use `library` tasks.
+    pub fn from_file_with<P: AsRef<Path>>(path: P, library: &TaskLibrary) -> Result<Workflow, FunctionResponseError> {
+        let path = path.as_ref();
+        let definition = fs::read_to_string(path)
+            .map_err(|e| error("Workflow", format!("Cannot read workflow {}: {}", path.display(), e)))?;
+        let definition: Value = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&definition)
+                .map_err(|e| error("Workflow", format!("Invalid JSON workflow: {}", e))),
+            Some("yaml" | "yml") => serde_yaml::from_str(&definition)
+                .map_err(|e| error("Workflow", format!("Invalid YAML workflow: {}", e))),
+            _ => return Err(error("Workflow", format!("Unsupported workflow file {}", path.display()))),
+        }.map_err(|e| error("Workflow", format!("{}: {}", path.display(), e.message)))?;
+        library.resolve(definition)
+            .and_then(Workflow::from_value)
+            .map_err(|e| error("Workflow", format!("{}: {}", path.display(), e.message)))
+    }
+
+    /// Loads every workflow definition in `dir`, in file name order. Other files are ignored.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<Workflow>, FunctionResponseError> {
+        definition_files(dir.as_ref())?.iter().map(Workflow::from_file).collect()
+    }
+
+    /// Like `from_dir`, the definitions using `library` tasks.
+    pub fn from_dir_with<P: AsRef<Path>>(dir: P, library: &TaskLibrary) -> Result<Vec<Workflow>, FunctionResponseError> {
+        definition_files(dir.as_ref())?.iter().map(|path| Workflow::from_file_with(path, library)).collect()
+    }
+
+    /// Task indices grouped in levels that only depend on earlier levels, each in declaration
+    /// order. Fails on a dependency cycle.
+    pub fn execution_levels(&self) -> Result<Vec<Vec<usize>>, DefinitionError> {
+        let dependencies: Vec<Vec<usize>> = self.tasks.iter().enumerate()
+            .map(|(index, task)| match &task.depends_on {
+                Some(depends_on) => depends_on.iter()
+                    .filter_map(|id| self.tasks.iter().position(|t| &t.task_id == id))
+                    .collect(),
+                None => index.checked_sub(1).into_iter().collect(),
+            })
+            .collect();
+
+        let mut done = vec![false; self.tasks.len()];
+        let mut remaining: Vec<usize> = (0..self.tasks.len()).collect();
+        let mut levels = Vec::new();
+        while !remaining.is_empty() {
+            let (ready, blocked): (Vec<usize>, Vec<usize>) = remaining.into_iter()
+                .partition(|index| dependencies[*index].iter().all(|dependency| done[*dependency]));
+            if ready.is_empty() {
+                let ids: Vec<&str> = blocked.iter().map(|index| self.tasks[*index].task_id.as_str()).collect();
+                return Err(DefinitionError {
+                    location: "tasks".to_string(),
+                    message: format!("dependency cycle between {}", ids.join(", ")),
+                });
+            }
+            ready.iter().for_each(|index| done[*index] = true);
+            levels.push(ready);
+            remaining = blocked;
+        }
+        Ok(levels)
+    }
+
+    /// Every definition error, checked with the standard operator library.
+    pub fn validate(&self) -> Result<(), Vec<DefinitionError>> {
+        self.validate_with(RuleEvaluator::shared())
+    }
+
+    /// Every definition error: duplicate or empty task ids, rules `logic` cannot compile and
+    /// task inputs that do not fit their function.
+    pub fn validate_with(&self, logic: &RuleEvaluator) -> Result<(), Vec<DefinitionError>> {
+        let mut errors = Vec::new();
+        let report = |errors: &mut Vec<DefinitionError>, location: String, message: String| errors.push(DefinitionError { location, message });
+
+        if self.name.trim().is_empty() {
+            report(&mut errors, "name".to_string(), "is empty".to_string());
+        }
+        if let Err(e) = logic.compile(&self.condition) {
+            report(&mut errors, "condition".to_string(), e);
+        }
+        if let Some(handler) = &self.on_failure {
+            validate_handler(handler, "on_failure", logic, &mut errors);
+        }
+
+        let mut task_ids = HashSet::new();
+        for (index, task) in self.tasks.iter().enumerate() {
+            let location = format!("tasks[{}]", index);
+            if task.task_id.trim().is_empty() {
+                report(&mut errors, format!("{}.task_id", location), "is empty".to_string());
+            } else if !task_ids.insert(task.task_id.as_str()) {
+                report(&mut errors, format!("{}.task_id", location), format!("duplicate task id {}", task.task_id));
+            }
+            validate_task(task, &location, logic, true, &mut errors);
+            for dependency in task.depends_on.iter().flatten() {
+                if !self.tasks.iter().any(|t| &t.task_id == dependency) {
+                    report(&mut errors, format!("{}.depends_on", location), format!("unknown task {}", dependency));
+                }
+            }
+        }
+        if let Err(e) = self.execution_levels() {
+            errors.push(e);
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+}
+
+/// A workflow whose conditions and enrichment rules are compiled once at load time.
+#[derive(Clone)]
+pub struct CompiledWorkflow {
+    workflow: Workflow,
+    logic: RuleEvaluator,
+    levels: Vec<Vec<usize>>,
+    condition: CompiledRule,
+    tasks: Vec<CompiledTask>,
+    on_failure: Option<CompiledHandler>,
+}
+
+#[derive(Clone)]
+pub struct CompiledTask {
+    task: Task,
+    logic: RuleEvaluator,
+    condition: CompiledRule,
+    enrichment: Option<CompiledEnrichment>,
+    call: Option<(SubWorkflowInput, Option<CompiledRule>)>,
+    foreach: Option<(ForeachInput, Vec<CompiledTask>)>,
+    fingerprint: String,
+    on_failure: Option<CompiledHandler>,
+    compensation: Option<Box<CompiledTask>>,
+}
+
+/// A `FailureHandler` with its repair tasks compiled.
+#[derive(Clone)]
+pub enum CompiledHandler {
+    Repair(Vec<CompiledTask>),
+    Reject { queue: String, status_report: Pacs002Config },
+    ExceptionQueue { queue: String },
+}
+
+impl CompiledHandler {
+    fn new(handler: &FailureHandler, logic: &RuleEvaluator) -> Result<Self, FunctionResponseError> {
+        Ok(match handler {
+            FailureHandler::Repair { tasks } => CompiledHandler::Repair(
+                tasks.iter().map(|task| CompiledTask::new(task, logic)).collect::<Result<_, _>>()?
+            ),
+            FailureHandler::Reject { queue, status_report } => CompiledHandler::Reject { queue: queue.clone(), status_report: status_report.clone() },
+            FailureHandler::ExceptionQueue { queue } => CompiledHandler::ExceptionQueue { queue: queue.clone() },
+        })
+    }
+}
+
+fn rule_applies(logic: &RuleEvaluator, rule: &CompiledRule, context: &Value, tracer: &mut RuleTracer) -> Result<bool, FunctionResponseError> {
+    let result = logic.evaluate(rule, context);
+    tracer.record("condition", rule, context, &result);
+    result.map(|result| is_truthy(&result))
+        .map_err(|e| error("Workflow", format!("Condition evaluation failed: {}", e)))
+}
+
+impl CompiledWorkflow {
+    /// Validates the workflow and compiles its conditions and the configs of `Enrich` tasks.
+    pub fn new(workflow: Workflow, logic: &RuleEvaluator) -> Result<Self, FunctionResponseError> {
+        if let Err(errors) = workflow.validate_with(logic) {
+            let details: Vec<String> = errors.iter().map(|e| format!("{}: {}", e.location, e.message)).collect();
+            return Err(error("Workflow", format!("Invalid workflow {}: {}", workflow.name, details.join("; "))));
+        }
+        let tasks = workflow.tasks.iter().map(|task| CompiledTask::new(task, logic)).collect::<Result<_, _>>()?;
+        let on_failure = workflow.on_failure.as_ref().map(|handler| CompiledHandler::new(handler, logic)).transpose()?;
+        let levels = workflow.execution_levels().map_err(|e| error("Workflow", e.message))?;
+        let condition = logic.compile(&workflow.condition).map_err(|e| error("Workflow", e))?;
+        Ok(Self { condition, workflow, logic: logic.clone(), levels, tasks, on_failure })
+    }
+
+    pub fn workflow(&self) -> &Workflow {
+        &self.workflow
+    }
+
+    pub fn tasks(&self) -> &[CompiledTask] {
+        &self.tasks
+    }
+
+    /// Indices into `tasks`, grouped by `Workflow::execution_levels`.
+    pub fn levels(&self) -> &[Vec<usize>] {
+        &self.levels
+    }
+
+    pub fn on_failure(&self) -> Option<&CompiledHandler> {
+        self.on_failure.as_ref()
+    }
+
+    pub fn task(&self, task_id: &str) -> Option<&CompiledTask> {
+        self.tasks.iter().find(|task| task.task.task_id == task_id)
+    }
+
+    /// Whether the workflow condition holds for `message`.
+    pub fn matches(&self, message: &Message) -> Result<bool, FunctionResponseError> {
+        rule_applies(&self.logic, &self.condition, &message.rule_context(Value::Null), &mut RuleTracer::disabled())
+    }
+}
+
+impl CompiledTask {
+    fn new(task: &Task, logic: &RuleEvaluator) -> Result<Self, FunctionResponseError> {
+        let foreach = match task.function {
+            FunctionType::Foreach => {
+                let foreach: ForeachInput = serde_json::from_value(task.input.clone()).map_err(|e| error("Workflow", e.to_string()))?;
+                let tasks = foreach.tasks.iter().map(|task| CompiledTask::new(task, logic)).collect::<Result<_, _>>()?;
+                Some((foreach, tasks))
+            }
+            _ => None,
+        };
+        let enrichment = match (&task.function, &foreach) {
+            (FunctionType::Enrich, _) => {
+                let config = serde_json::from_value(task.input.clone()).map_err(|e| error("Workflow", e.to_string()))?;
+                Some(CompiledEnrichment::new(logic, config)?)
+            }
+            (FunctionType::Foreach, Some((foreach, _))) if !foreach.enrichment.is_empty() => {
+                Some(CompiledEnrichment::new(logic, foreach.enrichment.clone())?)
+            }
+            _ => None,
+        };
+        let call = match task.function {
+            FunctionType::SubWorkflow => {
+                let call: SubWorkflowInput = serde_json::from_value(task.input.clone()).map_err(|e| error("Workflow", e.to_string()))?;
+                let mapping = call.input.as_ref().map(|rule| logic.compile(rule)).transpose().map_err(|e| error("Workflow", e))?;
+                Some((call, mapping))
+            }
+            _ => None,
+        };
+        let effects = json!({"function": task.function, "input": task.input});
+        Ok(Self {
+            fingerprint: sha256(effects.to_string().as_bytes())[..16].to_string(),
+            task: task.clone(),
+            logic: logic.clone(),
+            condition: logic.compile(&task.condition).map_err(|e| error("Workflow", e))?,
+            enrichment,
+            call,
+            foreach,
+            on_failure: task.on_failure.as_ref().map(|handler| CompiledHandler::new(handler, logic)).transpose()?,
+            compensation: task.compensation.as_deref().map(|task| CompiledTask::new(task, logic).map(Box::new)).transpose()?,
+        })
+    }
+
+    pub fn task(&self) -> &Task {
+        &self.task
+    }
+
+    pub fn on_failure(&self) -> Option<&CompiledHandler> {
+        self.on_failure.as_ref()
+    }
+
+    pub fn compensation(&self) -> Option<&CompiledTask> {
+        self.compensation.as_deref()
+    }
+
+    /// The compiled `input` of an `Enrich` task, or the enrichment of a `Foreach` task.
+    pub fn enrichment(&self) -> Option<&CompiledEnrichment> {
+        self.enrichment.as_ref()
+    }
+
+    /// Digest of the task function and input: a task with the same fingerprint has the same effects.
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    /// The parsed `input` of a `SubWorkflow` task.
+    pub fn call(&self) -> Option<&SubWorkflowInput> {
+        self.call.as_ref().map(|(call, _)| call)
+    }
+
+    /// The parsed `input` of a `Foreach` task.
+    pub fn foreach(&self) -> Option<&ForeachInput> {
+        self.foreach.as_ref().map(|(foreach, _)| foreach)
+    }
+
+    /// Tasks a `Foreach` task runs on each element.
+    pub fn element_tasks(&self) -> &[CompiledTask] {
+        self.foreach.as_ref().map(|(_, tasks)| tasks.as_slice()).unwrap_or_default()
+    }
+
+    /// Whether the task condition holds for `message`.
+    pub fn applies(&self, message: &Message) -> Result<bool, FunctionResponseError> {
+        self.applies_in(&message.rule_context(Value::Null), &mut RuleTracer::disabled())
+    }
+
+    /// `applies` against a rule context built by the caller, e.g. once for the tasks of a level,
+    /// recording the condition evaluation in `tracer`.
+    pub(crate) fn applies_in(&self, context: &Value, tracer: &mut RuleTracer) -> Result<bool, FunctionResponseError> {
+        rule_applies(&self.logic, &self.condition, context, tracer)
+    }
+
+    /// Input of a `SubWorkflow` task's workflow: its input rule applied to the message context,
+    /// or `input` unchanged.
+    pub fn sub_workflow_input(&self, message: &Message, input: &Value) -> Result<Value, FunctionResponseError> {
+        match &self.call {
+            Some((_, Some(rule))) => self.logic.evaluate(rule, &message.rule_context(input.clone()))
+                .map_err(|e| error("SubWorkflow", format!("Input mapping failed: {}", e))),
+            _ => Ok(input.clone()),
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::fs;
+use core_data::models::message::*;
+use core_data::models::payload::*;
+use core_data::models::cancellation::*;
+use core_data::models::returns::Pacs004Config;
+
+fn parsed(path: &str, origin: &str) -> Message {
+    let xml_bytes = fs::read(path).expect("Failed to read test XML file");
+    parsed_bytes(xml_bytes, origin)
+}
+
+fn parsed_bytes(xml_bytes: Vec<u8>, origin: &str) -> Message {
+    let payload = Payload::new_inline(
+        Some(xml_bytes),
+        PayloadFormat::Xml,
+        PayloadSchema::ISO20022,
+        Encoding::Utf8
+    );
+
+    let mut message = Message::new(
+        payload,
+        "banking".to_string(),
+        origin.to_string(),
+        "test_cancellation".to_string(),
+        "ISOIncoming".to_string(),
+        None
+    );
+    message.parse(None, "test_cancellation".to_string(), "ISOIncoming".to_string())
+        .expect("Failed to parse message");
+    message
+}
+
+fn pending_cancellation() -> (Vec<Message>, Message) {
+    let mut originals = vec![
+        parsed("examples/pain001_001_09_bulk.xml", "pain.001.001.09"),
+        parsed("examples/pacs008_001_07_cct_outgoing.xml", "pacs.008.001.07"),
+    ];
+    let request = parsed("examples/camt056_001_08_cancellation.xml", "camt.056.001.08");
+
+    let original = request.correlate_cancellation(&mut originals)
+        .expect("Failed to correlate cancellation");
+    original.request_cancellation(&request, None, "test_cancellation".to_string(), "Cancel".to_string())
+        .expect("Failed to request cancellation");
+    (originals, request)
+}
+
+#[test]
+fn test_cancellation_references() {
+    let request = parsed("examples/camt056_001_08_cancellation.xml", "camt.056.001.08");
+    let references = request.cancellation_references().expect("Failed to read references");
+
+    assert_eq!(references.len(), 1);
+    assert_eq!(references[0].cancellation_id.as_deref(), Some("CXLID0001"));
+    assert_eq!(references[0].original_message_id.as_deref(), Some("VOLCUSTMSGID0001"));
+    assert_eq!(references[0].original_end_to_end_id.as_deref(), Some("VOLCUSTETEID0001"));
+    assert_eq!(references[0].reason_code.as_deref(), Some("DUPL"));
+}
+
+#[test]
+fn test_cancellation_rejected_with_camt029() {
+    let (mut originals, mut request) = pending_cancellation();
+    let original = &mut originals[1];
+    assert_eq!(original.progress().status, MessageStatus::CancellationPending);
+
+    let resolution = original.resolve_cancellation(
+        &mut request,
+        &CancellationResolution::Reject { reason_code: "ARDT".to_string(), additional_info: None },
+        None,
+        "test_cancellation".to_string(),
+        "Resolve".to_string(),
+    ).expect("Failed to resolve cancellation");
+
+    let document = &resolution.data()["document"]["RsltnOfInvstgtn"];
+    assert_eq!(document["Sts"]["Conf"], "RJCR");
+    assert_eq!(document["RslvdCase"]["Id"], "CXLCASE0001");
+    assert_eq!(document["Assgnmt"]["Assgnr"]["Agt"]["FinInstnId"]["BICFI"], "AIBKIE2DXXX");
+    assert_eq!(document["CxlDtls"][0]["TxInfAndSts"][0]["OrgnlEndToEndId"], "VOLCUSTETEID0001");
+    assert_eq!(document["CxlDtls"][0]["TxInfAndSts"][0]["CxlStsRsnInf"][0]["Rsn"]["Cd"], "ARDT");
+    assert_eq!(resolution.parent_id(), &Some(request.id().to_string()));
+
+    assert_eq!(original.progress().status, MessageStatus::Recieved);
+    assert_eq!(request.audit().last().unwrap().description(), "Cancellation resolved");
+}
+
+#[test]
+fn test_cancellation_accepted_with_pacs004() {
+    let (mut originals, mut request) = pending_cancellation();
+    let original = &mut originals[1];
+
+    let config = Pacs004Config {
+        instructing_agent: "AIBKIE2DXXX".to_string(),
+        instructed_agent: "IRCEIE2DXXX".to_string(),
+        settlement_method: "CLRG".to_string(),
+        clearing_system: None,
+        reason_code: "FOCR".to_string(),
+        additional_info: None,
+        amount: None,
+        end_to_end_id: None,
+    };
+    let payment_return = original.resolve_cancellation(
+        &mut request,
+        &CancellationResolution::Accept(config),
+        None,
+        "test_cancellation".to_string(),
+        "Resolve".to_string(),
+    ).expect("Failed to resolve cancellation");
+
+    assert_eq!(payment_return.data()["document"]["PmtRtr"]["TxInf"][0]["RtrRsnInf"][0]["Rsn"]["Cd"], "FOCR");
+    assert_eq!(payment_return.parent_id(), &Some(original.id().to_string()));
+    assert_eq!(original.progress().status, MessageStatus::Cancelled);
+    assert!(original.request_cancellation(&request, None, "test_cancellation".to_string(), "Cancel".to_string()).is_ok());
+}
+
+/// The outgoing pacs.008 with a second transaction, only the first of which is cancelled.
+fn two_transaction_pacs008() -> Message {
+    let xml = fs::read_to_string("examples/pacs008_001_07_cct_outgoing.xml").expect("Failed to read test XML file");
+    let start = xml.find("<CdtTrfTxInf>").unwrap();
+    let end = xml.find("</CdtTrfTxInf>").unwrap() + "</CdtTrfTxInf>".len();
+    let second = xml[start..end].replace("VOLCUSTETEID0001", "VOLCUSTETEID0002");
+    let xml = format!("{}\n\t\t{}{}", &xml[..end], second, &xml[end..])
+        .replace("<NbOfTxs>1</NbOfTxs>", "<NbOfTxs>2</NbOfTxs>")
+        .replace("<TtlIntrBkSttlmAmt Ccy=\"EUR\">100.00</TtlIntrBkSttlmAmt>", "<TtlIntrBkSttlmAmt Ccy=\"EUR\">200.00</TtlIntrBkSttlmAmt>");
+    parsed_bytes(xml.into_bytes(), "pacs.008.001.07")
+}
+
+#[test]
+fn test_cancellation_returns_only_cancelled_transactions() {
+    let mut originals = vec![two_transaction_pacs008()];
+    let mut request = parsed("examples/camt056_001_08_cancellation.xml", "camt.056.001.08");
+    let original = request.correlate_cancellation(&mut originals).expect("Failed to correlate cancellation");
+    assert_eq!(original.data()["document"]["FIToFICstmrCdtTrf"]["CdtTrfTxInf"].as_array().unwrap().len(), 2);
+    let status = original.progress().status.clone();
+    original.request_cancellation(&request, None, "test_cancellation".to_string(), "Cancel".to_string())
+        .expect("Failed to request cancellation");
+
+    let config = Pacs004Config {
+        instructing_agent: "AIBKIE2DXXX".to_string(),
+        instructed_agent: "IRCEIE2DXXX".to_string(),
+        settlement_method: "CLRG".to_string(),
+        clearing_system: None,
+        reason_code: "FOCR".to_string(),
+        additional_info: None,
+        amount: None,
+        end_to_end_id: None,
+    };
+    let payment_return = original.resolve_cancellation(
+        &mut request,
+        &CancellationResolution::Accept(config),
+        None,
+        "test_cancellation".to_string(),
+        "Resolve".to_string(),
+    ).expect("Failed to resolve cancellation");
+
+    let returned = payment_return.data()["document"]["PmtRtr"]["TxInf"].as_array().unwrap();
+    assert_eq!(returned.len(), 1);
+    assert_eq!(returned[0]["OrgnlEndToEndId"], "VOLCUSTETEID0001");
+    assert_eq!(payment_return.data()["document"]["PmtRtr"]["GrpHdr"]["NbOfTxs"], "1");
+
+    // The second transaction still stands
+    assert_eq!(original.progress().status, status);
+    let change = &original.audit().last().unwrap().changes()[0];
+    assert_eq!(change.reason(), format!("1 of 2 transactions cancelled by {}", payment_return.id()));
+    assert_eq!(change.new_value(), Some(&serde_json::json!(status)));
+}
+
+#[test]
+fn test_cancellation_without_original() {
+    let mut originals = vec![parsed("examples/pain001_001_09_bulk.xml", "pain.001.001.09")];
+    let request = parsed("examples/camt056_001_08_cancellation.xml", "camt.056.001.08");
+
+    assert!(request.correlate_cancellation(&mut originals).is_err());
+
+    let mut unrelated = parsed("examples/pacs008_001_07_cct_outgoing.xml", "pacs.008.001.07");
+    unrelated.request_cancellation(&request, None, "test_cancellation".to_string(), "Cancel".to_string()).unwrap();
+    let mut other = parsed("examples/camt056_001_08_cancellation.xml", "camt.056.001.08");
+    let unknown = serde_json::from_value(serde_json::json!([
+        {"field": "data.document.FIToFIPmtCxlReq.Undrlyg.0.TxInf.0.OrgnlEndToEndId", "rule": "UNKNOWN", "description": null}
+    ])).unwrap();
+    other.enrich(unknown, serde_json::json!({}), None, "test_cancellation".to_string(), "Enrich".to_string()).unwrap();
+    let rejected = unrelated.resolve_cancellation(
+        &mut other,
+        &CancellationResolution::Reject { reason_code: "ARDT".to_string(), additional_info: None },
+        None,
+        "test_cancellation".to_string(),
+        "Resolve".to_string(),
+    );
+    assert!(rejected.is_err());
+    assert_eq!(unrelated.progress().status, MessageStatus::CancellationPending);
+}
@@ -0,0 +1,20 @@
+#![allow(dead_code)]
+
+use std::fs;
+use serde_json::{json, Value};
+use core_data::models::message::Message;
+use core_data::models::payload::*;
+
+/// The example pacs.008, parsed by the `workflow` test workflow.
+pub fn parsed_pacs008(workflow: &str) -> Message {
+    let xml_bytes = fs::read("examples/pacs008_001_07_cct_outgoing.xml").expect("Failed to read test XML file");
+    let payload = Payload::new_inline(Some(xml_bytes), PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+    let mut message = Message::new(payload, "banking".to_string(), "pacs.008.001.07".to_string(), workflow.to_string(), "ISOIncoming".to_string(), None);
+    message.parse(None, workflow.to_string(), "ISOIncoming".to_string()).expect("Failed to parse message");
+    message
+}
+
+/// Task definition that always runs `function` with `input`.
+pub fn task(task_id: &str, function: &str, input: Value) -> Value {
+    json!({"task_id": task_id, "name": task_id, "description": "", "condition": true, "function": function, "input": input})
+}
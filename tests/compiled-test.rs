@@ -0,0 +1,142 @@
+use std::sync::Arc;
+use std::thread;
+use serde_json::json;
+use core_data::models::logic::*;
+use core_data::models::message::*;
+use core_data::models::task::*;
+use core_data::models::workflow::*;
+
+mod common;
+use common::parsed_pacs008;
+
+#[test]
+fn test_compile_validates_rules() {
+    let evaluator = RuleEvaluator::shared();
+    let rule = json!({"if": [{"valid_bic": {"var": "bic"}}, {"upper": {"var": "bic"}}, {"cat": ["invalid ", {"var": "bic"}]}]});
+    let compiled = evaluator.compile(&rule).expect("Rule should compile");
+    assert_eq!(compiled.source(), &rule);
+    for (data, expected) in [(json!({"bic": "AIBKIE2D"}), json!("AIBKIE2D")), (json!({"bic": "bad"}), json!("invalid bad"))] {
+        assert_eq!(evaluator.evaluate(&compiled, &data).unwrap(), expected);
+        assert_eq!(evaluator.apply(&rule, &data).unwrap(), expected);
+    }
+
+    assert_eq!(evaluator.compile(&json!({"unknown": [1]})).unwrap_err(), "Unknown operator unknown");
+    assert_eq!(evaluator.compile(&json!({"upper": {"reduce": [[1], 2]}})).unwrap_err(), "Operator reduce called with 2 arguments");
+    assert_eq!(evaluator.compile(&json!({"==": [1]})).unwrap_err(), "Operator == called with 1 arguments");
+    assert!(evaluator.compile(&json!({"var": "a", "cat": ["b"]})).is_err());
+    assert!(evaluator.compile(&json!({"preserve": {"unknown": [1]}})).is_ok());
+}
+
+#[test]
+fn test_compiled_standard_operators() {
+    let evaluator = RuleEvaluator::shared();
+    let data = json!({"a": {"b": 2}, "s": "payment", "list": [1, 2, 3], "n": null});
+    for (rule, expected) in [
+        (json!({"log": {"var": "a.b"}}), json!(2)),
+        (json!({"var": ["x.y", "default"]}), json!("default")),
+        (json!({"missing": ["a.b", "x", "s"]}), json!(["x"])),
+        (json!({"missing_some": [1, ["x", "y", "s"]]}), json!([])),
+        (json!({"<": [1, {"var": "a.b"}, 3]}), json!(true)),
+        (json!({"==": ["2", {"var": "a.b"}]}), json!(true)),
+        (json!({"===": ["2", {"var": "a.b"}]}), json!(false)),
+        (json!({"substr": [{"var": "s"}, -4]}), json!("ment")),
+        (json!({"in": ["pay", {"var": "s"}]}), json!(true)),
+        (json!({"map": [{"var": "list"}, {"*": [{"var": ""}, 2]}]}), json!([2, 4, 6])),
+        (json!({"reduce": [{"var": "list"}, {"+": [{"var": "current"}, {"var": "accumulator"}]}, 0]}), json!(6)),
+        (json!({"or": [{"var": "n"}, "fallback"]}), json!("fallback")),
+        (json!({"upper": {"log": {"var": "s"}}}), json!("PAYMENT")),
+        // Evaluated as JsonLogic does, custom operators or not
+        (json!({"missing": [["a", "zz"]]}), json!([])),
+        (json!({"var": ["zz", {"var": "a"}]}), json!({"var": "a"})),
+        (json!({"===": [1, 1.0]}), json!(false)),
+        (json!({"<=": ["a", "b"]}), json!(false)),
+        (json!({"<=": [{"upper": "a"}, "b"]}), json!(false))
+    ] {
+        let compiled = evaluator.compile(&rule).expect("Rule should compile");
+        assert_eq!(evaluator.evaluate(&compiled, &data).unwrap(), expected, "{}", rule);
+    }
+}
+
+#[test]
+fn test_compiled_enrichment_shared_across_threads() {
+    let invalid: Vec<EnrichmentConfig> = serde_json::from_value(json!([
+        {"field": "data.metadata.a", "rule": {"var": "input.a"}, "description": null},
+        {"field": "data.metadata.b", "rule": {"lower": []}, "description": null, "condition": {"!": []}},
+        {"field": "data.metadata.c", "rule": {"nope": 1}, "description": null}
+    ])).unwrap();
+    let error = CompiledEnrichment::new(RuleEvaluator::shared(), invalid).err().expect("Invalid rules should fail");
+    assert_eq!(error.message, "Invalid enrichment rules: [1].condition: Operator ! called with 0 arguments; [2].rule: Unknown operator nope");
+
+    let config: Vec<EnrichmentConfig> = serde_json::from_value(json!([
+        {"field": "data.metadata.message_id", "rule": {"lower": {"var": "data.document.FIToFICstmrCdtTrf.GrpHdr.MsgId"}}, "description": null},
+        {"field": "data.metadata.worker", "rule": {"var": "input.worker"}, "description": null}
+    ])).unwrap();
+    let compiled = Arc::new(CompiledEnrichment::new(RuleEvaluator::shared(), config).expect("Rules should compile"));
+    assert_eq!(compiled.configs().count(), 2);
+
+    let message = parsed_pacs008("test_compiled");
+    let workers: Vec<_> = (0..4)
+        .map(|worker| {
+            let (compiled, mut message) = (Arc::clone(&compiled), message.clone());
+            thread::spawn(move || {
+                message.enrich_compiled(&compiled, json!({"worker": worker}), None, "test_compiled".to_string(), "Enrich".to_string())
+                    .expect("Failed to enrich message");
+                message
+            })
+        })
+        .collect();
+    for (worker, handle) in workers.into_iter().enumerate() {
+        let message = handle.join().unwrap();
+        assert_eq!(message.data()["metadata"]["message_id"], "volcustmsgid0001");
+        assert_eq!(message.data()["metadata"]["worker"], worker);
+    }
+}
+
+#[test]
+fn test_compiled_workflow() {
+    let task = |task_id: &str, condition, function, input| Task {
+        task_id: task_id.to_string(),
+        name: task_id.to_string(),
+        description: String::new(),
+        condition,
+        function,
+        input,
+        depends_on: None,
+        retry: None,
+        on_failure: None,
+        compensation: None,
+    };
+    let workflow = |tasks| Workflow {
+        name: "incoming".to_string(),
+        description: String::new(),
+        version: 1,
+        tags: vec![],
+        status: WorkflowStatus::Active,
+        on_failure: None,
+        tasks,
+        condition: json!({"==": [{"var": "origin"}, "pacs.008.001.07"]}),
+    };
+
+    let invalid = workflow(vec![
+        task("enrich", json!({"unknown": []}), FunctionType::Enrich, json!([{"field": "data.x", "rule": {"!": []}, "description": null}])),
+        task("publish", json!(true), FunctionType::Publish, json!({})),
+    ]);
+    let error = CompiledWorkflow::new(invalid, RuleEvaluator::shared()).err().expect("Invalid workflow should fail");
+    assert_eq!(error.message, "Invalid workflow incoming: tasks[0].condition: Unknown operator unknown; tasks[0].input[0].rule: Operator ! called with 0 arguments");
+
+    let compiled = CompiledWorkflow::new(workflow(vec![
+        task("enrich", json!({"valid_bic": "AIBKIE2DXXX"}), FunctionType::Enrich, json!([{"field": "data.metadata.tenant", "rule": {"upper": {"var": "tenant"}}, "description": null}])),
+        task("publish", json!({"var": "data.metadata.tenant"}), FunctionType::Publish, json!({})),
+    ]), RuleEvaluator::shared()).expect("Workflow should compile");
+
+    let mut message = parsed_pacs008("test_compiled");
+    assert!(compiled.matches(&message).unwrap());
+    let enrich = &compiled.tasks()[0];
+    assert!(enrich.applies(&message).unwrap());
+    assert!(!compiled.tasks()[1].applies(&message).unwrap());
+    message.enrich_compiled(enrich.enrichment().unwrap(), json!({}), None, "incoming".to_string(), enrich.task().task_id.clone())
+        .expect("Failed to enrich message");
+    assert_eq!(message.data()["metadata"]["tenant"], "BANKING");
+    assert!(compiled.tasks()[1].applies(&message).unwrap());
+    assert!(compiled.tasks()[1].enrichment().is_none());
+}
@@ -0,0 +1,158 @@
+use std::sync::{Arc, Mutex};
+use serde_json::{json, Value};
+use core_data::models::errors::FunctionResponseError;
+use core_data::models::executor::*;
+use core_data::models::logic::*;
+use core_data::models::message::*;
+use core_data::models::task::*;
+use core_data::models::workflow::*;
+
+mod common;
+use common::parsed_pacs008;
+
+#[derive(Default)]
+struct RecordingPublisher {
+    published: Mutex<Vec<Value>>,
+}
+
+impl Publisher for RecordingPublisher {
+    fn publish(&self, message: &Message, input: &Value) -> Result<(), FunctionResponseError> {
+        self.published.lock().unwrap().push(json!({"topic": input["topic"], "metadata": message.data()["metadata"]}));
+        Ok(())
+    }
+}
+
+fn task(task_id: &str, function: FunctionType, input: Value, depends_on: Option<Vec<&str>>) -> Task {
+    let mut definition = common::task(task_id, "", input);
+    definition["function"] = json!(function);
+    definition["depends_on"] = json!(depends_on);
+    serde_json::from_value(definition).expect("Invalid task")
+}
+
+fn set(field: &str, rule: Value) -> Value {
+    json!([{"field": field, "rule": rule, "description": null}])
+}
+
+fn workflow(tasks: Vec<Task>) -> Workflow {
+    Workflow {
+        name: "incoming".to_string(),
+        description: String::new(),
+        version: 1,
+        tags: vec![],
+        status: WorkflowStatus::Active,
+        on_failure: None,
+        tasks,
+        condition: json!(true),
+    }
+}
+
+#[test]
+fn test_dependency_levels() {
+    let sequential = workflow(vec![
+        task("a", FunctionType::Publish, json!({}), None),
+        task("b", FunctionType::Publish, json!({}), None),
+    ]);
+    assert_eq!(sequential.execution_levels().unwrap(), vec![vec![0], vec![1]]);
+
+    let graph = workflow(vec![
+        task("join", FunctionType::Publish, json!({}), Some(vec!["left", "right"])),
+        task("left", FunctionType::Publish, json!({}), Some(vec![])),
+        task("right", FunctionType::Publish, json!({}), Some(vec![])),
+        task("after_left", FunctionType::Publish, json!({}), Some(vec!["left"])),
+    ]);
+    assert_eq!(graph.execution_levels().unwrap(), vec![vec![1, 2], vec![0, 3]]);
+
+    let cyclic = workflow(vec![
+        task("a", FunctionType::Publish, json!({}), Some(vec!["c"])),
+        task("b", FunctionType::Publish, json!({}), Some(vec!["a"])),
+        task("c", FunctionType::Publish, json!({}), Some(vec!["b", "missing"])),
+        task("d", FunctionType::Publish, json!({}), Some(vec![])),
+    ]);
+    let errors = cyclic.validate().expect_err("Cycle should be rejected");
+    assert_eq!(errors[0], DefinitionError { location: "tasks[2].depends_on".to_string(), message: "unknown task missing".to_string() });
+    assert_eq!(errors[1], DefinitionError { location: "tasks".to_string(), message: "dependency cycle between a, b, c".to_string() });
+    assert!(CompiledWorkflow::new(cyclic, RuleEvaluator::shared()).is_err());
+}
+
+#[test]
+fn test_parallel_branches_merge() {
+    let definition = workflow(vec![
+        task("validate", FunctionType::Validate, json!({"identifiers": true}), Some(vec![])),
+        task("message_id", FunctionType::Enrich, set("data.metadata.message_id", json!({"var": "data.document.FIToFICstmrCdtTrf.GrpHdr.MsgId"})), Some(vec![])),
+        task("channel", FunctionType::Enrich, set("data.metadata.channel", json!({"var": "input.channel"})), Some(vec![])),
+        task("reference", FunctionType::Enrich, set("data.metadata.reference", json!({"cat": [{"var": "data.metadata.channel"}, "/", {"var": "data.metadata.message_id"}]})), Some(vec!["message_id", "channel"])),
+        task("publish", FunctionType::Publish, json!({"topic": "payments"}), None),
+    ]);
+    let compiled = CompiledWorkflow::new(definition, RuleEvaluator::shared()).expect("Workflow should compile");
+    let publisher = Arc::new(RecordingPublisher::default());
+
+    let mut results = Vec::new();
+    for parallel in [true, false] {
+        let executor = WorkflowExecutor::new().with_publisher(publisher.clone()).with_parallelism(parallel);
+        let mut message = parsed_pacs008("test_executor");
+        let report = executor.run(&compiled, &mut message, &json!({"channel": "SWIFT"})).expect("Workflow should run");
+        assert_eq!(report.executed, vec!["validate", "message_id", "channel", "reference", "publish"]);
+        assert_eq!(message.progress().status, MessageStatus::Completed);
+        assert_eq!(message.data()["metadata"]["reference"], "SWIFT/VOLCUSTMSGID0001");
+        let tasks: Vec<&str> = message.audit().iter().skip(2).map(|audit| audit.task()).collect();
+        assert_eq!(tasks, vec!["validate", "message_id", "channel", "reference", "publish"]);
+        results.push(message.data().clone());
+    }
+    assert_eq!(results[0], results[1]);
+    assert_eq!(publisher.published.lock().unwrap()[0]["metadata"]["channel"], "SWIFT");
+}
+
+#[test]
+fn test_parallel_conflicts_fail() {
+    let definition = workflow(vec![
+        task("first", FunctionType::Enrich, set("data.metadata.route", json!("RTGS")), Some(vec![])),
+        task("second", FunctionType::Enrich, set("data.metadata.route", json!("ACH")), Some(vec![])),
+        task("same", FunctionType::Enrich, set("data.metadata.checked", json!(true)), Some(vec![])),
+        task("again", FunctionType::Enrich, set("data.metadata.checked", json!(true)), Some(vec![])),
+    ]);
+    let compiled = CompiledWorkflow::new(definition, RuleEvaluator::shared()).unwrap();
+    let mut message = parsed_pacs008("test_executor");
+    let error = WorkflowExecutor::new().run(&compiled, &mut message, &json!({})).expect_err("Conflict expected");
+    assert_eq!(error.message, "Conflicting changes from parallel tasks: metadata.route (first, second)");
+    assert_eq!(message.progress().status, MessageStatus::Failed);
+    assert!(message.data().get("metadata").is_none());
+
+    // Elements of one array changed by different branches merge
+    let definition = workflow(vec![
+        task("tags", FunctionType::Enrich, set("data.metadata.tags", json!(["a", "b"])), None),
+        task("first", FunctionType::Enrich, set("data.metadata.tags.0", json!("A")), Some(vec!["tags"])),
+        task("second", FunctionType::Enrich, set("data.metadata.tags.1", json!("B")), Some(vec!["tags"])),
+    ]);
+    let compiled = CompiledWorkflow::new(definition, RuleEvaluator::shared()).unwrap();
+    let mut message = parsed_pacs008("test_executor");
+    WorkflowExecutor::new().run(&compiled, &mut message, &json!({})).expect("Elements should merge");
+    assert_eq!(message.data()["metadata"]["tags"], json!(["A", "B"]));
+
+    let publish = CompiledWorkflow::new(workflow(vec![task("publish", FunctionType::Publish, json!({}), None)]), RuleEvaluator::shared()).unwrap();
+    let error = WorkflowExecutor::new().run(&publish, &mut parsed_pacs008("test_executor"), &json!({})).expect_err("Publisher required");
+    assert_eq!(error.message, "No publisher configured");
+}
+
+#[test]
+fn test_parallel_failed_branch_stays_audited() {
+    let mut broken = task("broken", FunctionType::Enrich, json!([
+        {"field": "data.document.FIToFICstmrCdtTrf.GrpHdr.MsgId", "rule": "SEPA", "description": null, "operation": {"type": "append"}}
+    ]), Some(vec![]));
+    broken.retry = Some(serde_json::from_value(json!({"max_attempts": 2, "backoff": {"type": "fixed", "delay_ms": 1}, "retry_on": ["invalid"]})).unwrap());
+    let definition = workflow(vec![
+        task("route", FunctionType::Enrich, set("data.metadata.route", json!("RTGS")), Some(vec![])),
+        broken,
+        task("channel", FunctionType::Enrich, set("data.metadata.channel", json!("SWIFT")), Some(vec![])),
+    ]);
+    let compiled = CompiledWorkflow::new(definition, RuleEvaluator::shared()).unwrap();
+    let mut message = parsed_pacs008("test_executor");
+    WorkflowExecutor::new().run(&compiled, &mut message, &json!({})).expect_err("Broken task fails");
+
+    assert_eq!(message.data()["metadata"]["route"], "RTGS");
+    assert_eq!(message.data()["metadata"]["channel"], "SWIFT");
+    let attempt = message.audit().iter().find(|a| a.description() == "Attempt 1 of 2 failed").expect("Retry should be audited");
+    assert_eq!(attempt.task(), "broken");
+    let tasks: Vec<&str> = message.audit().iter().skip(2).map(|a| a.task()).collect();
+    assert_eq!(&tasks[..3], &["route", "broken", "channel"]);
+    assert_eq!(message.progress().status, MessageStatus::Failed);
+}
@@ -0,0 +1,151 @@
+use std::fs;
+use std::sync::{Arc, Mutex};
+use serde_json::{json, Value};
+use core_data::models::errors::FunctionResponseError;
+use core_data::models::executor::*;
+use core_data::models::logic::*;
+use core_data::models::message::*;
+use core_data::models::payload::*;
+use core_data::models::workflow::*;
+
+mod common;
+use common::task;
+
+/// Records what it publishes, failing for inputs with `"fail": true` and for the `closed` queue.
+#[derive(Default)]
+struct QueuePublisher {
+    published: Mutex<Vec<(Value, Value)>>,
+}
+
+impl Publisher for QueuePublisher {
+    fn publish(&self, message: &Message, input: &Value) -> Result<(), FunctionResponseError> {
+        if input["fail"] == true {
+            return Err(FunctionResponseError::new("Publish".to_string(), 400, "Route not found".to_string()));
+        }
+        if input["queue"] == "closed" {
+            return Err(FunctionResponseError::new("Publish".to_string(), 400, "Queue closed is closed".to_string()));
+        }
+        self.published.lock().unwrap().push((input.clone(), message.data().clone()));
+        Ok(())
+    }
+}
+
+fn set(field: &str, rule: Value) -> Value {
+    json!([{"field": field, "rule": rule, "description": null}])
+}
+
+fn compile(tasks: Vec<Value>, on_failure: Value) -> CompiledWorkflow {
+    let definition = json!({
+        "name": "incoming",
+        "description": "",
+        "version": 1,
+        "tags": [],
+        "status": "Active",
+        "condition": true,
+        "on_failure": on_failure,
+        "tasks": tasks
+    });
+    let workflow = Workflow::from_json(&definition.to_string()).expect("Invalid workflow");
+    CompiledWorkflow::new(workflow, RuleEvaluator::shared()).expect("Workflow should compile")
+}
+
+fn run(workflow: &CompiledWorkflow) -> (Message, Result<ExecutionReport, FunctionResponseError>, Arc<QueuePublisher>) {
+    let xml_bytes = fs::read("examples/pacs008_001_07_cct_outgoing.xml").expect("Failed to read test XML file");
+    let payload = Payload::new_inline(Some(xml_bytes), PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+    let mut message = Message::new(payload, "banking".to_string(), "pacs.008.001.07".to_string(), "test_failure".to_string(), "ISOIncoming".to_string(), None);
+    message.parse(None, "test_failure".to_string(), "ISOIncoming".to_string()).unwrap();
+    let publisher = Arc::new(QueuePublisher::default());
+    let result = WorkflowExecutor::new().with_publisher(publisher.clone()).run(workflow, &mut message, &json!({}));
+    (message, result, publisher)
+}
+
+#[test]
+fn test_repair_tasks_recover_failure() {
+    let mut route = task("route", "Publish", json!({"topic": "payments", "fail": true}));
+    route["on_failure"] = json!({"type": "repair", "tasks": [
+        task("manual_route", "Enrich", set("data.metadata.route", json!("MANUAL"))),
+    ]});
+    let workflow = compile(vec![route, task("stamp", "Enrich", set("data.metadata.stamped", json!(true)))], Value::Null);
+
+    let (message, result, _) = run(&workflow);
+    let report = result.expect("Repaired workflow should complete");
+    assert_eq!(report.repaired, vec!["route"]);
+    assert_eq!(report.executed, vec!["stamp"]);
+    assert_eq!(message.progress().status, MessageStatus::Completed);
+    assert_eq!(message.data()["metadata"]["route"], "MANUAL");
+    assert_eq!(message.data()["metadata"]["stamped"], true);
+
+    let repairing = message.audit().iter().find(|a| a.description() == "Task route failed, repairing").unwrap();
+    assert_eq!(repairing.changes()[0].reason(), "Publish: Route not found");
+    assert_eq!(repairing.changes()[0].new_value(), Some(&json!("Repairing")));
+}
+
+#[test]
+fn test_compensation_and_rejection() {
+    let mut stamp = task("stamp", "Enrich", set("data.metadata.stamped", json!(true)));
+    stamp["compensation"] = task("unstamp", "Enrich", json!([{"field": "data.metadata.stamped", "description": null, "operation": {"type": "remove"}}]));
+    let mut route = task("route", "Enrich", set("data.metadata.route", json!("SEPA")));
+    route["compensation"] = task("unroute", "Publish", json!({"fail": true}));
+    let mut send = task("send", "Publish", json!({"topic": "payments", "fail": true}));
+    send["on_failure"] = json!({"type": "reject", "queue": "rejections", "status_report": {
+        "instructing_agent": "AIBKIE2DXXX", "instructed_agent": "IRCEIE2DXXX", "reason_code": "AC01"
+    }});
+    let workflow = compile(vec![stamp, route, send], Value::Null);
+
+    let (message, result, publisher) = run(&workflow);
+    let error = result.expect_err("Rejected workflow should fail");
+    assert_eq!(error.message, "Route not found");
+    assert_eq!(message.progress().status, MessageStatus::Failed);
+    assert!(message.data()["metadata"].get("stamped").is_none());
+    assert_eq!(message.data()["metadata"]["route"], "SEPA");
+
+    let descriptions: Vec<&str> = message.audit().iter().map(|a| a.description()).collect();
+    let compensating = descriptions.iter().position(|d| *d == "Task send failed, compensating").unwrap();
+    assert_eq!(descriptions[compensating + 1], "Compensation failed");
+    assert!(descriptions[compensating + 2..].iter().any(|d| d.ends_with("published to rejections")));
+
+    let published = publisher.published.lock().unwrap();
+    assert_eq!(published.len(), 1);
+    assert_eq!(published[0].0, json!({"queue": "rejections", "error": "Route not found"}));
+    let status = &published[0].1["document"]["FIToFIPmtStsRpt"]["TxInfAndSts"][0];
+    assert_eq!(status["TxSts"], "RJCT");
+    assert_eq!(status["StsRsnInf"][0]["Rsn"]["Cd"], "AC01");
+}
+
+#[test]
+fn test_workflow_exception_queue_and_validation() {
+    let workflow = compile(
+        vec![task("send", "Publish", json!({"topic": "payments", "fail": true}))],
+        json!({"type": "exception_queue", "queue": "exceptions"}),
+    );
+    let (message, result, publisher) = run(&workflow);
+    assert!(result.is_err());
+    let published = publisher.published.lock().unwrap();
+    assert_eq!(published[0].0["queue"], "exceptions");
+    assert_eq!(published[0].1, message.data().clone());
+    drop(published);
+
+    // A failing handler is audited, but the task failure is what the caller gets
+    let broken = compile(
+        vec![task("send", "Publish", json!({"topic": "payments", "fail": true}))],
+        json!({"type": "exception_queue", "queue": "closed"}),
+    );
+    let (message, result, publisher) = run(&broken);
+    assert_eq!(result.expect_err("Workflow should fail").message, "Route not found");
+    assert!(publisher.published.lock().unwrap().is_empty());
+    let handler = message.audit().iter().find(|a| a.description() == "Failure handler failed").unwrap();
+    assert_eq!(handler.changes()[0].reason(), "Publish: Queue closed is closed");
+
+    let mut route = task("route", "Publish", json!({}));
+    route["on_failure"] = json!({"type": "repair", "tasks": [task("fix", "Enrich", json!({"not": "a list"}))]});
+    route["compensation"] = task("undo", "Publish", json!({}));
+    route["compensation"]["depends_on"] = json!([]);
+    let definition = json!({
+        "name": "incoming", "description": "", "version": 1, "tags": [], "status": "Draft", "condition": true,
+        "on_failure": {"type": "exception_queue", "queue": " "},
+        "tasks": [route]
+    });
+    let errors = Workflow::from_json(&definition.to_string()).unwrap().validate().expect_err("Workflow should be invalid");
+    let locations: Vec<&str> = errors.iter().map(|e| e.location.as_str()).collect();
+    assert_eq!(locations, vec!["on_failure.queue", "tasks[0].on_failure.tasks[0].input", "tasks[0].compensation.depends_on"]);
+}
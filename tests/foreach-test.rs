@@ -0,0 +1,129 @@
+use std::fs;
+use serde_json::{json, Value};
+use core_data::models::executor::*;
+use core_data::models::logic::*;
+use core_data::models::message::*;
+use core_data::models::payload::*;
+use core_data::models::workflow::*;
+
+const TRANSACTIONS: &str = "data.document.CstmrCdtTrfInitn.PmtInf.0.CdtTrfTxInf";
+
+fn parsed_pain001() -> Message {
+    let xml_bytes = fs::read("examples/pain001_001_09_bulk.xml").expect("Failed to read test XML file");
+    let payload = Payload::new_inline(Some(xml_bytes), PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+    let mut message = Message::new(payload, "banking".to_string(), "pain.001.001.09".to_string(), "test_foreach".to_string(), "ISOIncoming".to_string(), None);
+    message.parse(None, "test_foreach".to_string(), "ISOIncoming".to_string()).unwrap();
+    message
+}
+
+fn foreach_workflow(input: Value) -> Workflow {
+    Workflow::from_value(json!({
+        "name": "bulk", "description": "", "version": 1, "tags": [], "status": "Active", "condition": true,
+        "tasks": [{"task_id": "per_transaction", "name": "Per transaction", "description": "", "condition": true, "function": "Foreach", "input": input}]
+    })).unwrap()
+}
+
+fn compile(input: Value) -> CompiledWorkflow {
+    CompiledWorkflow::new(foreach_workflow(input), RuleEvaluator::shared()).expect("Workflow should compile")
+}
+
+fn transaction(message: &Message, index: usize) -> &Value {
+    &message.data()["document"]["CstmrCdtTrfInitn"]["PmtInf"][0]["CdtTrfTxInf"][index]
+}
+
+/// Marks every transaction, then fails on the second one by appending to a string.
+fn failing_tasks() -> Value {
+    json!([
+        {"task_id": "mark", "name": "mark", "description": "", "condition": true, "function": "Enrich",
+         "input": [{"field": "item.Checked", "rule": true, "description": null}]},
+        {"task_id": "break", "name": "break", "description": "", "condition": {"==": [{"var": "index"}, 1]}, "function": "Enrich",
+         "input": [{"field": "item.PmtId.EndToEndId", "rule": "x", "description": null, "operation": {"type": "append"}}]}
+    ])
+}
+
+#[test]
+fn test_foreach_enriches_each_element() {
+    let workflow = compile(json!({"path": TRANSACTIONS, "enrichment": [
+        {"field": "item.Purp.Cd", "rule": {"if": [{"==": [{"var": "index"}, 0]}, "SALA", "SUPP"]}, "description": null},
+        {"field": "item.PmtId.InstrId", "rule": {"cat": [{"var": "input.batch"}, "/", {"var": "item.PmtId.EndToEndId"}]}, "description": null}
+    ]}));
+    let mut message = parsed_pain001();
+    let report = WorkflowExecutor::new().run(&workflow, &mut message, &json!({"batch": "B1"})).expect("Foreach should succeed");
+    assert_eq!(report.executed, vec!["per_transaction"]);
+
+    assert_eq!(transaction(&message, 0)["Purp"]["Cd"], "SALA");
+    assert_eq!(transaction(&message, 1)["Purp"]["Cd"], "SUPP");
+    assert_eq!(transaction(&message, 1)["PmtId"]["InstrId"], "B1/E2E-0002");
+    assert!(message.data()["document"]["CstmrCdtTrfInitn"]["PmtInf"][1]["CdtTrfTxInf"].get("Purp").is_none());
+
+    let audit = message.audit();
+    let element = &audit[audit.len() - 2];
+    assert_eq!(element.description(), format!("Enriched element 1 of {}", TRANSACTIONS));
+    assert_eq!(element.changes()[0].field(), format!("{}.1.Purp.Cd", TRANSACTIONS));
+    assert_eq!(audit.last().unwrap().description(), format!("Processed 2 elements of {}", TRANSACTIONS));
+    assert_eq!(message.progress().prev_task, "per_transaction");
+}
+
+#[test]
+fn test_foreach_skips_failed_elements() {
+    let workflow = compile(json!({"path": TRANSACTIONS, "tasks": failing_tasks(), "on_element_failure": "skip"}));
+    let mut message = parsed_pain001();
+    WorkflowExecutor::new().run(&workflow, &mut message, &json!({})).expect("Failed elements are skipped");
+
+    assert_eq!(transaction(&message, 0)["Checked"], true);
+    assert!(transaction(&message, 1).get("Checked").is_none());
+    assert_eq!(transaction(&message, 1)["PmtId"]["EndToEndId"], "E2E-0002");
+
+    let summary = message.audit().last().unwrap();
+    assert_eq!(summary.description(), format!("Processed 2 elements of {}, 1 failed", TRANSACTIONS));
+    assert_eq!(summary.changes().len(), 1);
+    assert_eq!(summary.changes()[0].field(), format!("{}.1", TRANSACTIONS));
+    assert!(summary.changes()[0].reason().contains("Cannot append"));
+    assert_eq!(message.progress().status, MessageStatus::Completed);
+}
+
+#[test]
+fn test_foreach_aborts_on_failed_element() {
+    let workflow = compile(json!({"path": TRANSACTIONS, "tasks": failing_tasks()}));
+    let mut message = parsed_pain001();
+    let error = WorkflowExecutor::new().run(&workflow, &mut message, &json!({})).expect_err("Second element fails");
+    assert_eq!(error.function, "Foreach");
+    assert!(error.message.starts_with(&format!("Element 1 of {} failed: Enrichment: Cannot append", TRANSACTIONS)));
+    assert!(transaction(&message, 0).get("Checked").is_none());
+    assert_eq!(message.progress().status, MessageStatus::Failed);
+
+    let invalid = foreach_workflow(json!({
+        "path": "document.CstmrCdtTrfInitn.PmtInf",
+        "enrichment": [{"field": "item.Checked", "rule": true, "description": null}],
+        "tasks": [{"task_id": "inner", "name": "inner", "description": "", "condition": true, "function": "Foreach", "input": {"path": "data.items", "tasks": []}}]
+    }));
+    let locations: Vec<String> = invalid.validate().unwrap_err().into_iter().map(|e| e.location).collect();
+    assert_eq!(locations, vec![
+        "tasks[0].input.path",
+        "tasks[0].input",
+        "tasks[0].input.tasks[0].function",
+        "tasks[0].input.tasks[0].input",
+    ]);
+}
+
+#[test]
+fn test_foreach_stops_at_removed_elements() {
+    // The first element truncates the array to itself
+    let workflow = compile(json!({"path": TRANSACTIONS, "tasks": [
+        {"task_id": "truncate", "name": "truncate", "description": "", "condition": {"==": [{"var": "index"}, 0]}, "function": "Enrich",
+         "input": [{"field": TRANSACTIONS, "rule": [{"var": "item"}], "description": null}]},
+        {"task_id": "mark", "name": "mark", "description": "", "condition": true, "function": "Enrich",
+         "input": [{"field": "item.Checked", "rule": true, "description": null}]}
+    ]}));
+    let mut message = parsed_pain001();
+    WorkflowExecutor::new().run(&workflow, &mut message, &json!({})).expect("Foreach should succeed");
+    let transactions = &message.data()["document"]["CstmrCdtTrfInitn"]["PmtInf"][0]["CdtTrfTxInf"];
+    assert_eq!(transactions.as_array().unwrap().len(), 1);
+    assert_eq!(transactions[0]["Checked"], true);
+    assert_eq!(message.audit().last().unwrap().description(), format!("Processed 1 elements of {}", TRANSACTIONS));
+
+    let config = serde_json::from_value(json!([{"field": format!("{}.5.Checked", TRANSACTIONS), "rule": true, "description": null}])).unwrap();
+    let error = message.enrich(config, json!({}), None, "test_foreach".to_string(), "Enrich".to_string()).expect_err("No element 5");
+    assert_eq!(error.message, format!("Cannot set {}.5.Checked: 5 is not an index of an array of 1 elements", TRANSACTIONS));
+    assert!(message.data()["document"]["CstmrCdtTrfInitn"]["PmtInf"][0]["CdtTrfTxInf"].is_array());
+}
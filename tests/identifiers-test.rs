@@ -0,0 +1,109 @@
+use std::fs;
+use serde_json::json;
+use core_data::models::identifiers::*;
+use core_data::models::logic::*;
+use core_data::models::message::*;
+use core_data::models::payload::*;
+
+fn parsed(file: &str) -> Message {
+    let xml_bytes = fs::read(file).expect("Failed to read test XML file");
+
+    let payload = Payload::new_inline(
+        Some(xml_bytes),
+        PayloadFormat::Xml,
+        PayloadSchema::ISO20022,
+        Encoding::Utf8
+    );
+
+    let mut message = Message::new(
+        payload,
+        "banking".to_string(),
+        "ISO20022".to_string(),
+        "test_identifiers".to_string(),
+        "ISOIncoming".to_string(),
+        None
+    );
+    message.parse(None, "test_identifiers".to_string(), "ISOIncoming".to_string())
+        .expect("Failed to parse message");
+    message
+}
+
+#[test]
+fn test_identifier_checks() {
+    assert!(is_valid_iban("DE89370400440532013000"));
+    assert!(is_valid_iban("GB82WEST12345698765432"));
+    assert!(!is_valid_iban("DE89370400440532013001"));
+    assert!(!is_valid_iban("DE8937040044053201300"));
+    assert!(!is_valid_iban("QQ89370400440532013000"));
+
+    assert!(is_valid_bic("COBADEFFXXX"));
+    assert!(is_valid_bic("DEUTDEFF"));
+    assert!(!is_valid_bic("COBAQQFFXXX"));
+    assert!(!is_valid_bic("COBADEFFXX"));
+
+    assert!(is_valid_lei("5493001KJTIIGC8Y1R12"));
+    assert!(!is_valid_lei("5493001KJTIIGC8Y1R13"));
+
+    assert!(is_valid_creditor_reference("RF18539007547034"));
+    assert!(!is_valid_creditor_reference("RF19539007547034"));
+    assert!(!is_valid_creditor_reference("XX18539007547034"));
+}
+
+#[test]
+fn test_identifier_operators() {
+    let evaluator = RuleEvaluator::new();
+    let data = json!({"iban": "DE89370400440532013000", "bic": "COBAQQFFXXX", "amount": 10});
+
+    let rule = json!({"and": [{"valid_iban": {"var": "iban"}}, {">": [{"var": "amount"}, 5]}]});
+    assert_eq!(evaluator.apply(&rule, &data).unwrap(), json!(true));
+
+    let rule = json!({"if": [{"valid_bic": {"var": "bic"}}, "routed", "repair"]});
+    assert_eq!(evaluator.apply(&rule, &data).unwrap(), json!("repair"));
+
+    let rule = json!({"cat": ["LEI ", {"valid_lei": "5493001KJTIIGC8Y1R12"}]});
+    assert_eq!(evaluator.apply(&rule, &data).unwrap(), json!("LEI true"));
+
+    let rule = json!({"filter": [["RF18539007547034", "RF19539007547034"], {"valid_creditor_reference": {"var": ""}}]});
+    assert_eq!(evaluator.apply(&rule, &data).unwrap(), json!(["RF18539007547034"]));
+
+    let mut message = parsed("examples/pain001_001_09_bulk.xml");
+    message.enrich(
+        vec![EnrichmentConfig {
+            field: "data.routing.iban_valid".to_string(),
+            rule: json!({"valid_iban": {"var": "input.iban"}}),
+            description: None,
+            ..Default::default()
+        }],
+        data,
+        None,
+        "test_identifiers".to_string(),
+        "Enrich".to_string()
+    ).expect("Failed to enrich message");
+    assert_eq!(message.data()["routing"]["iban_valid"], json!(true));
+}
+
+#[test]
+fn test_validate_identifiers_in_message() {
+    let mut message = parsed("examples/pain001_001_09_bulk.xml");
+    message.validate_identifiers(None, "test_identifiers".to_string(), "Validate".to_string())
+        .expect("Identifiers should be valid");
+    let (checked, invalid) = invalid_identifiers(message.data());
+    assert!(checked >= 10);
+    assert!(invalid.is_empty());
+
+    message.enrich(
+        vec![EnrichmentConfig {
+            field: "data.document.CstmrCdtTrfInitn.PmtInf.0.DbtrAcct.Id.IBAN".to_string(),
+            rule: json!("DE89370400440532013001"),
+            description: None,
+            ..Default::default()
+        }],
+        json!({}),
+        None,
+        "test_identifiers".to_string(),
+        "Enrich".to_string()
+    ).unwrap();
+    let error = message.validate_identifiers(None, "test_identifiers".to_string(), "Validate".to_string())
+        .expect_err("Corrupted IBAN should fail");
+    assert!(error.message.contains("Iban DE89370400440532013001 at data.document.CstmrCdtTrfInitn.PmtInf.0.DbtrAcct.Id.IBAN"));
+}
@@ -2,6 +2,10 @@ use std::fs;
 use core_data::models::message::*;
 use core_data::models::payload::*;
 use serde_json::json;
+use open_payments_iso20022::document::Document;
+
+mod common;
+use common::parsed_pacs008;
 
 #[test]
 fn test_message_lifecycle() {
@@ -49,11 +53,13 @@ fn test_message_lifecycle() {
             field: "data.metadata.processing_date".to_string(),
             rule: json!({"var": ["processing_date"]}),
             description: Some("Add processing timestamp".to_string()),
+            ..Default::default()
         },
         EnrichmentConfig {
             field: "data.metadata.message_type".to_string(),
             rule: json!({"var": ["message_type"]}),
             description: Some("Add message classification".to_string()),
+            ..Default::default()
         }
     ];
 
@@ -86,4 +92,218 @@ fn test_message_lifecycle() {
     assert_eq!(audit_trail[0].description(), "Payment created");
     assert_eq!(audit_trail[1].description(), "Parsed payment message");
     assert_eq!(audit_trail[2].description(), "Applied metadata enrichment");
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_typed_document_follows_enrichment() {
+    let xml_bytes = fs::read("examples/pacs008_001_07_cct_outgoing.xml")
+        .expect("Failed to read test XML file");
+
+    let payload = Payload::new_inline(
+        Some(xml_bytes),
+        PayloadFormat::Xml,
+        PayloadSchema::ISO20022,
+        Encoding::Utf8
+    );
+
+    let mut message = Message::new(
+        payload,
+        "banking".to_string(),
+        "pacs.008.001.07".to_string(),
+        "test_typed_document".to_string(),
+        "ISOOutgoing".to_string(),
+        None
+    );
+    assert!(message.document().is_err());
+
+    message.parse(None, "test_typed_document".to_string(), "ISOOutgoing".to_string())
+        .expect("Failed to parse message");
+
+    match message.document().expect("Typed document should be available") {
+        Document::FIToFICustomerCreditTransferV12(transfer) => {
+            assert_eq!(transfer.grp_hdr.msg_id, "VOLCUSTMSGID0001");
+        }
+        _ => panic!("Unexpected document type"),
+    }
+
+    message.enrich(
+        vec![EnrichmentConfig {
+            field: "data.document.FIToFICstmrCdtTrf.GrpHdr.MsgId".to_string(),
+            rule: json!({"var": ["input.message_id"]}),
+            description: None,
+            ..Default::default()
+        }],
+        json!({"message_id": "ENRICHEDMSGID0001"}),
+        None,
+        "test_typed_document".to_string(),
+        "ISOOutgoing".to_string(),
+    ).expect("Failed to enrich message");
+
+    match message.document().expect("Typed document should be available") {
+        Document::FIToFICustomerCreditTransferV12(transfer) => {
+            assert_eq!(transfer.grp_hdr.msg_id, "ENRICHEDMSGID0001");
+            assert_eq!(transfer.cdt_trf_tx_inf[0].pmt_id.end_to_end_id, "VOLCUSTETEID0001");
+        }
+        _ => panic!("Unexpected document type"),
+    }
+
+    // The typed document cache is not part of message equality
+    let saved: Message = serde_json::from_str(&serde_json::to_string(&message).unwrap()).unwrap();
+    assert_eq!(saved, message);
+}
+
+#[test]
+fn test_enrichment_rules_see_message_context() {
+    let xml_bytes = fs::read("examples/pacs008_001_07_cct_outgoing.xml")
+        .expect("Failed to read test XML file");
+    let payload = Payload::new_inline(Some(xml_bytes), PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+    let mut message = Message::new(
+        payload,
+        "banking".to_string(),
+        "pacs.008.001.07".to_string(),
+        "test_rule_context".to_string(),
+        "ISOOutgoing".to_string(),
+        None
+    );
+    message.parse(None, "test_rule_context".to_string(), "ISOOutgoing".to_string())
+        .expect("Failed to parse message");
+
+    let context = message.rule_context(json!({"channel": "SWIFT"}));
+    assert_eq!(context["tenant"], "banking");
+    assert_eq!(context["origin"], "pacs.008.001.07");
+    assert_eq!(context["progress"]["prev_task"], "ISOOutgoing");
+    assert_eq!(context["input"]["channel"], "SWIFT");
+    assert_eq!(context["channel"], "SWIFT");
+    assert_eq!(message.rule_context(json!({"tenant": "retail"}))["tenant"], "banking");
+
+    message.enrich(
+        vec![
+            EnrichmentConfig {
+                field: "data.metadata.message_id".to_string(),
+                rule: json!({"var": "data.document.FIToFICstmrCdtTrf.GrpHdr.MsgId"}),
+                description: None,
+                ..Default::default()
+            },
+            EnrichmentConfig {
+                field: "data.metadata.reference".to_string(),
+                rule: json!({"cat": [{"var": "tenant"}, "/", {"var": "input.channel"}, "/", {"var": "data.metadata.message_id"}]}),
+                description: None,
+                ..Default::default()
+            },
+        ],
+        json!({"channel": "SWIFT"}),
+        None,
+        "test_rule_context".to_string(),
+        "Enrich".to_string(),
+    ).expect("Failed to enrich message");
+
+    assert_eq!(message.data()["metadata"]["message_id"], "VOLCUSTMSGID0001");
+    assert_eq!(message.data()["metadata"]["reference"], "banking/SWIFT/VOLCUSTMSGID0001");
+}
+
+#[test]
+fn test_conditional_enrichment_operations() {
+    let mut message = parsed_pacs008("test_enrichment");
+    let config: Vec<EnrichmentConfig> = serde_json::from_value(json!([
+        {"field": "data.metadata.tags", "rule": "inbound", "description": null, "operation": {"type": "append"}},
+        {"field": "data.metadata.tags", "rule": {"var": "input.channel"}, "description": null, "operation": {"type": "append"}},
+        {"field": "data.metadata.routing", "rule": {"preserve": {"priority": "HIGH"}}, "description": null, "operation": {"type": "merge"}},
+        {"field": "data.metadata.routing", "rule": {"preserve": {"channel": "SWIFT"}}, "description": null, "operation": {"type": "merge"}},
+        {"field": "data.metadata.tags", "rule": ["ignored"], "description": null, "operation": {"type": "set_if_absent"}},
+        {"field": "data.metadata.urgent", "rule": true, "description": null, "condition": {"==": [{"var": "input.channel"}, "RTGS"]}},
+        {"field": "data.metadata.channel", "description": null, "operation": {"type": "rename", "from": "data.metadata.routing.channel"}},
+        {"field": "data.metadata.tags", "description": null, "operation": {"type": "remove"}, "condition": {"var": "input.untagged"}}
+    ])).expect("Invalid enrichment config");
+
+    message.enrich(config, json!({"channel": "SWIFT", "untagged": true}), None, "test_enrichment".to_string(), "Enrich".to_string())
+        .expect("Failed to enrich message");
+
+    let metadata = &message.data()["metadata"];
+    assert_eq!(metadata["routing"], json!({"priority": "HIGH"}));
+    assert_eq!(metadata["channel"], "SWIFT");
+    assert!(metadata.get("tags").is_none());
+    assert!(metadata.get("urgent").is_none());
+
+    let changes = message.audit().last().unwrap().changes();
+    assert_eq!(changes.len(), 8);
+    assert_eq!(changes[1].new_value(), Some(&json!(["inbound", "SWIFT"])));
+    assert_eq!(changes[4].reason(), "Enriched absent field data.metadata.tags");
+    assert_eq!(changes[4].new_value(), Some(&json!(["inbound", "SWIFT"])));
+    assert_eq!(changes[5].reason(), "Enriched field data.metadata.urgent: skipped, condition not met");
+    assert_eq!(changes[6].reason(), "Moved field data.metadata.routing.channel to data.metadata.channel");
+    assert_eq!(changes[7].old_value(), Some(&json!(["inbound", "SWIFT"])));
+    assert_eq!(changes[7].new_value(), None);
+}
+
+#[test]
+fn test_enrichment_failure_policies() {
+    let mut message = parsed_pacs008("test_enrichment");
+    let failing = |on_failure: FailurePolicy| EnrichmentConfig {
+        field: "data.metadata.tags".to_string(),
+        rule: json!("second"),
+        description: None,
+        operation: EnrichmentOperation::Append,
+        on_failure,
+        ..Default::default()
+    };
+    let config = vec![
+        EnrichmentConfig {
+            field: "data.metadata.tags".to_string(),
+            rule: json!("not an array"),
+            description: None,
+            ..Default::default()
+        },
+        failing(FailurePolicy::Skip),
+        EnrichmentConfig {
+            field: "data.metadata.reviewed".to_string(),
+            rule: json!(null),
+            description: None,
+            ..Default::default()
+        },
+        EnrichmentConfig {
+            field: "data.metadata.fallback".to_string(),
+            rule: json!({"unknown_operator": [1]}),
+            description: Some("Fallback".to_string()),
+            on_failure: FailurePolicy::Default(json!("N/A")),
+            ..Default::default()
+        },
+    ];
+    message.enrich(config, json!({}), None, "test_enrichment".to_string(), "Enrich".to_string())
+        .expect("Failed to enrich message");
+    assert_eq!(message.data()["metadata"]["tags"], "not an array");
+    assert_eq!(message.data()["metadata"]["fallback"], "N/A");
+    let changes = message.audit().last().unwrap().changes();
+    assert!(changes[1].reason().contains("skipped after failure: Cannot append to data.metadata.tags: not an array"));
+    assert!(changes[3].reason().starts_with("Fallback: default value after failure: Rule application failed"));
+    assert_eq!(message.data()["metadata"].get("reviewed"), Some(&json!(null)));
+
+    let audit_entries = message.audit().len();
+    let config = vec![
+        EnrichmentConfig {
+            field: "data.metadata.channel".to_string(),
+            rule: json!("SWIFT"),
+            description: None,
+            ..Default::default()
+        },
+        EnrichmentConfig {
+            field: "data.metadata.reviewed".to_string(),
+            rule: json!(true),
+            description: None,
+            ..Default::default()
+        },
+        EnrichmentConfig {
+            field: "data.metadata.fallback".to_string(),
+            description: None,
+            operation: EnrichmentOperation::Remove,
+            ..Default::default()
+        },
+        failing(FailurePolicy::Abort),
+    ];
+    let error = message.enrich(config, json!({}), None, "test_enrichment".to_string(), "Enrich".to_string())
+        .expect_err("Abort policy should fail the enrichment");
+    assert_eq!(error.message, "Cannot append to data.metadata.tags: not an array");
+    assert!(message.data()["metadata"].get("channel").is_none());
+    assert_eq!(message.data()["metadata"]["fallback"], "N/A");
+    assert_eq!(message.data()["metadata"].get("reviewed"), Some(&json!(null)));
+    assert_eq!(message.audit().len(), audit_entries);
+}
@@ -0,0 +1,83 @@
+use std::fs;
+use serde_json::json;
+use core_data::models::logic::*;
+use core_data::models::message::*;
+use core_data::models::operators::*;
+use core_data::models::payload::*;
+
+fn apply(rule: serde_json::Value) -> serde_json::Value {
+    RuleEvaluator::new().apply(&rule, &json!({"amount": "100.10", "date": "2024-11-15", "name": "Zoë Müller"}))
+        .expect("Failed to apply rule")
+}
+
+#[test]
+fn test_standard_operators() {
+    // 2024-11-15 is a Friday
+    assert_eq!(apply(json!({"business_days_add": [{"var": "date"}, 1]})), json!("2024-11-18"));
+    assert_eq!(apply(json!({"business_days_add": [{"var": "date"}, 2, ["2024-11-18"]]})), json!("2024-11-20"));
+    assert_eq!(apply(json!({"business_days_add": ["2024-11-18", -1]})), json!("2024-11-15"));
+
+    assert_eq!(apply(json!({"amount_compare": [{"var": "amount"}, 100.1]})), json!(0));
+    assert_eq!(apply(json!({"amount_compare": [{"var": "amount"}, "100.11"]})), json!(-1));
+    assert_eq!(apply(json!({"decimal_add": [0.1, 0.2]})), json!("0.3"));
+    assert_eq!(apply(json!({"decimal_sub": [{"var": "amount"}, "0.1", 50]})), json!("50"));
+    assert_eq!(apply(json!({"decimal_round": ["2.345", 2]})), json!("2.35"));
+    assert_eq!(apply(json!({"decimal_round": ["2.3", 2]})), json!("2.30"));
+    assert_eq!(apply(json!({"decimal_add": ["1.5E3", 1e-7, "-2e0"]})), json!("1498.0000001"));
+    assert_eq!(apply(json!({"amount_compare": ["1.001e2", {"var": "amount"}]})), json!(0));
+    assert_eq!(apply(json!({"decimal_add": ["1.50000000000", "0.000000000100"]})), json!("1.5000000001"));
+
+    assert_eq!(apply(json!({"substr_bytes": [{"var": "name"}, 0, 3]})), json!("Zo"));
+    assert_eq!(apply(json!({"pad_left": ["42", 5, "0"]})), json!("00042"));
+    assert_eq!(apply(json!({"pad_right": ["AB", 4]})), json!("AB  "));
+    assert_eq!(apply(json!({"regex_match": [{"var": "date"}, "^\\d{4}-\\d{2}-\\d{2}$"]})), json!(true));
+    assert_eq!(apply(json!({"sha256": "abc"})), json!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"));
+
+    let uuid = apply(json!({"uuid": []}));
+    assert_eq!(apply(json!({"regex_match": [uuid, "^[a-f0-9]{8}-[a-f0-9]{4}-4[a-f0-9]{3}-[89ab][a-f0-9]{3}-[a-f0-9]{12}$"]})), json!(true));
+    assert_eq!(apply(json!({"now": "date"})).as_str().unwrap().len(), 10);
+}
+
+#[test]
+fn test_operator_errors() {
+    let evaluator = RuleEvaluator::new();
+    let result = evaluator.apply(&json!({"business_days_add": ["15/11/2024", 1]}), &json!({}));
+    assert!(result.unwrap_err().starts_with("business_days_add: invalid date"));
+    assert!(evaluator.apply(&json!({"amount_compare": ["1.2.3", 1]}), &json!({})).is_err());
+    let result = evaluator.apply(&json!({"business_days_add": ["2024-11-15", i64::MIN]}), &json!({}));
+    assert_eq!(result.unwrap_err(), "business_days_add: argument 2 must be at most 10000 business days");
+    assert!(evaluator.apply(&json!({"decimal_add": ["1.00000000001", 0]}), &json!({})).is_err());
+    assert!(evaluator.apply(&json!({"unknown_operator": [1]}), &json!({})).is_err());
+}
+
+#[test]
+fn test_registered_operator_in_enrichment() {
+    let mut registry = OperatorRegistry::standard();
+    registry.register("fee", |args| {
+        let amount = args.first().and_then(|a| a.as_f64()).ok_or("amount required")?;
+        Ok(json!((amount * 0.002 * 100.0).round() / 100.0))
+    }).expect("Failed to register operator");
+    assert!(registry.register("cat", |_| Ok(json!(null))).is_err());
+    assert!(registry.names().contains(&"fee"));
+    let evaluator = RuleEvaluator::with_operators(registry);
+
+    let xml_bytes = fs::read("examples/pacs008_001_07_cct_outgoing.xml").expect("Failed to read test XML file");
+    let payload = Payload::new_inline(Some(xml_bytes), PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+    let mut message = Message::new(payload, "banking".to_string(), "pacs.008.001.07".to_string(), "test_operators".to_string(), "ISOIncoming".to_string(), None);
+    message.parse(None, "test_operators".to_string(), "ISOIncoming".to_string()).unwrap();
+
+    message.enrich_with(
+        &evaluator,
+        vec![EnrichmentConfig {
+            field: "data.charges.fee".to_string(),
+            rule: json!({"fee": {"var": "input.amount"}}),
+            description: Some("Processing fee".to_string()),
+            ..Default::default()
+        }],
+        json!({"amount": 2500}),
+        None,
+        "test_operators".to_string(),
+        "Enrich".to_string()
+    ).expect("Failed to enrich message");
+    assert_eq!(message.data()["charges"]["fee"], json!(5.0));
+}
@@ -0,0 +1,139 @@
+use std::fs;
+use serde_json::json;
+use core_data::models::executor::*;
+use core_data::models::logic::*;
+use core_data::models::message::*;
+use core_data::models::payload::*;
+use core_data::models::returns::*;
+use core_data::models::statement::*;
+use core_data::models::workflow::*;
+
+fn parsed(file: &str, origin: &str) -> Message {
+    let xml_bytes = fs::read(file).expect("Failed to read test XML file");
+
+    let payload = Payload::new_inline(
+        Some(xml_bytes),
+        PayloadFormat::Xml,
+        PayloadSchema::ISO20022,
+        Encoding::Utf8
+    );
+
+    let mut message = Message::new(
+        payload,
+        "banking".to_string(),
+        origin.to_string(),
+        "test_payment".to_string(),
+        "ISOIncoming".to_string(),
+        None
+    );
+    message.parse(None, "test_payment".to_string(), "ISOIncoming".to_string())
+        .expect("Failed to parse message");
+    message
+}
+
+#[test]
+fn test_payments_from_pacs008() {
+    let message = parsed("examples/pacs008_001_07_cct_outgoing.xml", "pacs.008.001.07");
+
+    let payments = message.payments().expect("Failed to extract payments");
+    assert_eq!(payments.len(), 1);
+    let payment = &payments[0];
+    assert_eq!(payment.message_name, "pacs.008.001.07");
+    assert_eq!(payment.path, "data.document.FIToFICstmrCdtTrf.CdtTrfTxInf.0");
+    assert_eq!(payment.amount, Some(100.0));
+    assert_eq!(payment.currency.as_deref(), Some("EUR"));
+    assert_eq!(payment.debtor_name.as_deref(), Some("Mr. Jones"));
+    assert_eq!(payment.debtor_account.as_deref(), Some("IE64IRCE92050112345678"));
+    assert_eq!(payment.creditor_name.as_deref(), Some("ZZ Insurances"));
+    assert_eq!(payment.creditor_account.as_deref(), Some("IE29AIBK93115212345678"));
+    assert_eq!(payment.end_to_end_id.as_deref(), Some("VOLCUSTETEID0001"));
+    assert_eq!(payment.settlement_date.as_deref(), Some("2020-06-20"));
+}
+
+#[test]
+fn test_payments_from_pain001_use_instruction_fields() {
+    let message = parsed("examples/pain001_001_09_bulk.xml", "pain.001.001.09");
+
+    let payments = message.payments().expect("Failed to extract payments");
+    assert_eq!(payments.len(), 3);
+    assert_eq!(payments[1].path, "data.document.CstmrCdtTrfInitn.PmtInf.0.CdtTrfTxInf.1");
+    assert_eq!(payments[1].debtor_name.as_deref(), Some("Acme Corporation"));
+    assert_eq!(payments[1].debtor_agent.as_deref(), Some("COBADEFFXXX"));
+    assert_eq!(payments[1].end_to_end_id.as_deref(), Some("E2E-0002"));
+    assert_eq!(payments[1].remittance_information, vec!["Invoice 2024-1002".to_string()]);
+    assert_eq!(payments[2].settlement_date.as_deref(), Some("2024-11-16"));
+    assert!(payments[2].uetr.is_some());
+}
+
+#[test]
+fn test_payments_from_generated_return_and_notification() {
+    let mut original = parsed("examples/pacs008_001_07_cct_outgoing.xml", "pacs.008.001.07");
+    let config = Pacs004Config {
+        instructing_agent: "AIBKIE2DXXX".to_string(),
+        instructed_agent: "IRCEIE2DXXX".to_string(),
+        settlement_method: "CLRG".to_string(),
+        clearing_system: None,
+        reason_code: "AC04".to_string(),
+        additional_info: None,
+        amount: Some(40.0),
+        end_to_end_id: None,
+    };
+    let payment_return = original.to_pacs004(&config, None, "test_payment".to_string(), "Return".to_string())
+        .expect("Failed to generate return");
+    let returned = &payment_return.payments().expect("Failed to extract payments")[0];
+    assert_eq!(returned.amount, Some(40.0));
+    assert_eq!(returned.debtor_name.as_deref(), Some("ZZ Insurances"));
+    assert_eq!(returned.end_to_end_id.as_deref(), Some("VOLCUSTETEID0001"));
+
+    let account = AccountConfig {
+        iban: "IE29AIBK93115212345678".to_string(),
+        currency: None,
+        servicer: None,
+        bank_transaction_code: None,
+    };
+    let notification = original.to_camt054(&account, None, "test_payment".to_string(), "Notify".to_string())
+        .expect("Failed to generate notification");
+    let notified = &notification.payments().expect("Failed to extract payments")[0];
+    assert_eq!(notified.message_name, "camt.054.001.12");
+    assert_eq!(notified.credit_debit.as_deref(), Some("CRDT"));
+    assert_eq!(notified.creditor_account.as_deref(), Some("IE29AIBK93115212345678"));
+    assert_eq!(notified.settlement_date.as_deref(), Some("2020-06-20"));
+}
+
+#[test]
+fn test_payments_as_rule_data() {
+    let workflow = Workflow::from_value(json!({
+        "name": "screening", "description": "", "version": 1, "tags": [], "status": "Active", "condition": true,
+        "tasks": [{
+            "task_id": "large", "name": "Large payments", "description": "",
+            "condition": {"some": [{"var": "payments"}, {">": [{"var": "amount"}, 1000]}]},
+            "function": "Enrich",
+            "input": [{
+                "field": "data.metadata.large_payments",
+                "rule": {"filter": [{"var": "payments"}, {">": [{"var": "amount"}, 1000]}]},
+                "description": null
+            }]
+        }]
+    })).unwrap();
+    let compiled = CompiledWorkflow::new(workflow, RuleEvaluator::shared()).unwrap();
+
+    let mut message = parsed("examples/pain001_001_09_bulk.xml", "pain.001.001.09");
+    WorkflowExecutor::new().run(&compiled, &mut message, &json!({})).unwrap();
+    let large = message.data()["metadata"]["large_payments"].as_array().expect("Task should run");
+    assert!(!large.is_empty());
+    assert!(large.iter().all(|payment| payment["amount"].as_f64().unwrap() > 1000.0));
+
+    let mut pacs008 = parsed("examples/pacs008_001_07_cct_outgoing.xml", "pacs.008.001.07");
+    assert_eq!(pacs008.rule_context(json!({}))["payments"][0]["debtor_name"], "Mr. Jones");
+    let rename: Vec<EnrichmentConfig> = serde_json::from_value(json!([
+        {"field": "data.document.FIToFICstmrCdtTrf.CdtTrfTxInf.0.Dbtr.Nm", "rule": "Ms. Smith", "description": null}
+    ])).unwrap();
+    pacs008.enrich(rename, json!({}), None, "screening".to_string(), "rename".to_string()).unwrap();
+    assert_eq!(pacs008.rule_context(json!({}))["payments"][0]["debtor_name"], "Ms. Smith");
+
+    let mut cancellation = parsed("examples/camt056_001_08_cancellation.xml", "camt.056.001.08");
+    assert!(cancellation.payments().is_err());
+    assert_eq!(cancellation.rule_context(json!({}))["payments"], json!([]));
+    WorkflowExecutor::new().run(&compiled, &mut cancellation, &json!({})).unwrap();
+    assert!(cancellation.data()["metadata"].get("large_payments").is_none());
+}
@@ -0,0 +1,129 @@
+use std::fs;
+use std::sync::Arc;
+use serde_json::json;
+use core_data::models::executor::*;
+use core_data::models::logic::*;
+use core_data::models::message::*;
+use core_data::models::payload::*;
+use core_data::models::profile::*;
+use core_data::models::workflow::*;
+
+fn parsed(file: &str) -> Message {
+    let xml_bytes = fs::read(file).expect("Failed to read test XML file");
+
+    let payload = Payload::new_inline(
+        Some(xml_bytes),
+        PayloadFormat::Xml,
+        PayloadSchema::ISO20022,
+        Encoding::Utf8
+    );
+
+    let mut message = Message::new(
+        payload,
+        "banking".to_string(),
+        "ISO20022".to_string(),
+        "test_profile".to_string(),
+        "ISOIncoming".to_string(),
+        None
+    );
+    message.parse(None, "test_profile".to_string(), "ISOIncoming".to_string())
+        .expect("Failed to parse message");
+    message
+}
+
+#[test]
+fn test_cbpr_plus_reports_every_violation() {
+    let message = parsed("examples/pacs008_001_07_cct_outgoing.xml");
+    let profile = ValidationProfile::builtin("cbpr_plus").expect("Missing builtin profile");
+
+    let violations = profile.check(message.data()).expect("Failed to check profile");
+    let rule_ids: Vec<&str> = violations.iter().map(|v| v.rule_id.as_str()).collect();
+    assert!(rule_ids.contains(&"CBPR-001"));
+    assert!(rule_ids.contains(&"CBPR-004"));
+    assert!(rule_ids.contains(&"CBPR-005"));
+    assert!(rule_ids.contains(&"CBPR-006"));
+    assert!(!rule_ids.contains(&"CBPR-002"));
+
+    let uetr = violations.iter().find(|v| v.rule_id == "CBPR-001").unwrap();
+    assert_eq!(uetr.path, "data.document.FIToFICstmrCdtTrf.CdtTrfTxInf.0.PmtId.UETR");
+    assert_eq!(uetr.message, "UETR is mandatory: is missing");
+}
+
+#[test]
+fn test_sepa_profile_on_message() {
+    let mut payment = parsed("examples/pacs008_001_07_cct_outgoing.xml");
+    let profile = ValidationProfile::builtin("sepa_sct").expect("Missing builtin profile");
+
+    payment.validate_profile(&profile, None, "test_profile".to_string(), "Validate".to_string())
+        .expect("SEPA payment should pass");
+    assert_eq!(payment.audit().last().unwrap().changes()[0].reason(), "Validated against profile sepa_sct 2023");
+
+    let mut initiation = parsed("examples/pain001_001_09_bulk.xml");
+    initiation.validate_profile(&profile, None, "test_profile".to_string(), "Validate".to_string())
+        .expect("SEPA initiation should pass");
+}
+
+#[test]
+fn test_fednow_profile_rejects_euro_payment() {
+    let mut payment = parsed("examples/pacs008_001_07_cct_outgoing.xml");
+    let profile = ValidationProfile::builtin("fednow").expect("Missing builtin profile");
+
+    let error = payment.validate_profile(&profile, None, "test_profile".to_string(), "Validate".to_string())
+        .expect_err("Euro payment should fail");
+    assert!(error.message.contains("FDN-001 at data.document.FIToFICstmrCdtTrf.CdtTrfTxInf.0.IntrBkSttlmAmt.@Ccy"));
+    assert_eq!(payment.audit().len(), 2);
+}
+
+#[test]
+fn test_custom_profile_definition() {
+    let definition = r#"{
+        "name": "internal",
+        "description": "Internal routing rules",
+        "version": "1",
+        "rules": [
+            {"id": "INT-001", "description": "Remittance is not allowed", "path": "FIToFICstmrCdtTrf.CdtTrfTxInf.*.RmtInf", "check": {"type": "forbidden"}},
+            {"id": "INT-002", "description": "End to end id is limited to 10 characters", "path": "FIToFICstmrCdtTrf.CdtTrfTxInf.*.PmtId.EndToEndId", "check": {"type": "max_length", "max": 10}}
+        ]
+    }"#;
+    let profile = ValidationProfile::from_json(definition).expect("Failed to load profile");
+    let message = parsed("examples/pacs008_001_07_cct_outgoing.xml");
+
+    let violations = profile.check(message.data()).unwrap();
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].rule_id, "INT-002");
+
+    let cancellation = parsed("examples/camt056_001_08_cancellation.xml");
+    assert!(profile.check(cancellation.data()).is_err());
+
+    let invalid = definition.replace(r#""max_length", "max": 10"#, r#""pattern", "pattern": "[""#);
+    assert!(ValidationProfile::from_json(&invalid).is_err());
+}
+
+#[test]
+fn test_registered_profile_in_validate_task() {
+    let definition = r#"{
+        "name": "internal",
+        "description": "Internal routing rules",
+        "version": "2",
+        "rules": [
+            {"id": "INT-003", "description": "Message id is upper case", "path": "FIToFICstmrCdtTrf.GrpHdr.MsgId", "check": {"type": "pattern", "pattern": "^[A-Z0-9]+$"}}
+        ]
+    }"#;
+    let mut profiles = ProfileRegistry::new();
+    profiles.register("internal", ValidationProfile::from_json(definition).unwrap());
+    assert!(profiles.get("sepa_sct").is_some());
+
+    let workflow = Workflow::from_value(json!({
+        "name": "incoming", "description": "", "version": 1, "tags": [], "status": "Active", "condition": true,
+        "tasks": [{"task_id": "check", "name": "Check", "description": "", "condition": true, "function": "Validate", "input": {"profile": "internal"}}]
+    })).unwrap();
+    let compiled = CompiledWorkflow::new(workflow, RuleEvaluator::shared()).expect("Registered profiles are resolved at run time");
+
+    let mut message = parsed("examples/pacs008_001_07_cct_outgoing.xml");
+    WorkflowExecutor::new().with_profiles(Arc::new(profiles)).run(&compiled, &mut message, &json!({})).unwrap();
+    assert_eq!(message.audit().iter().rev().find(|a| a.description() == "Profile validation passed").unwrap().changes()[0].reason(), "Validated against profile internal 2");
+
+    let mut message = parsed("examples/pacs008_001_07_cct_outgoing.xml");
+    let error = WorkflowExecutor::new().run(&compiled, &mut message, &json!({})).expect_err("Builtin profiles only");
+    assert_eq!(error.message, "Unknown profile internal");
+}
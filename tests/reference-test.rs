@@ -0,0 +1,107 @@
+use std::fs;
+use std::sync::Arc;
+use serde_json::json;
+use core_data::models::logic::*;
+use core_data::models::message::*;
+use core_data::models::operators::*;
+use core_data::models::payload::*;
+use core_data::models::reference::*;
+
+fn reference_data() -> Arc<ReferenceData> {
+    let reference_data = Arc::new(ReferenceData::new());
+    reference_data.insert(ReferenceDataset::load("bic_directory", "examples/reference/bic_directory.csv", "bic").unwrap());
+    reference_data.insert(ReferenceDataset::load("currencies", "examples/reference/currencies.json", "code").unwrap());
+    reference_data
+}
+
+#[test]
+fn test_load_datasets() {
+    let bics = ReferenceDataset::load("bic_directory", "examples/reference/bic_directory.csv", "bic")
+        .expect("Failed to load CSV dataset");
+    assert_eq!(bics.len(), 4);
+    assert_eq!(bics.get("COBADEFFXXX").unwrap()["name"], "Commerzbank, Frankfurt");
+    assert_eq!(bics.version().len(), 12);
+    assert!(bics.get("DEUTDEFFXXX").is_none());
+
+    let currencies = ReferenceDataset::load("currencies", "examples/reference/currencies.json", "code")
+        .expect("Failed to load JSON dataset");
+    assert_eq!(currencies.version(), "2024-06");
+    assert_eq!(currencies.get("JPY").unwrap()["minor_units"], 0);
+
+    assert!(ReferenceDataset::load("currencies", "examples/reference/currencies.json", "name_missing").is_err());
+
+    let path = std::env::temp_dir().join(format!("core-data-addresses-{}.csv", std::process::id()));
+    fs::write(&path, "bic,address\nAIBKIE2DXXX,\"10 Molesworth Street\nDublin 2\"\n\nBNPAFRPPXXX,\"16 \"\"Boulevard\"\" des Italiens\"\n").unwrap();
+    let addresses = ReferenceDataset::load("addresses", &path, "bic").expect("Failed to load quoted CSV");
+    assert_eq!(addresses.len(), 2);
+    assert_eq!(addresses.get("AIBKIE2DXXX").unwrap()["address"], "10 Molesworth Street\nDublin 2");
+    assert_eq!(addresses.get("BNPAFRPPXXX").unwrap()["address"], "16 \"Boulevard\" des Italiens");
+    fs::write(&path, "bic,address\nAIBKIE2DXXX\n").unwrap();
+    assert!(ReferenceDataset::load("addresses", &path, "bic").is_err());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_lookup_in_enrichment_is_audited() {
+    let evaluator = RuleEvaluator::with_reference_data(OperatorRegistry::standard(), reference_data())
+        .expect("Failed to register lookup");
+    assert_eq!(
+        evaluator.apply(&json!({"lookup": ["currencies", "USD"]}), &json!({})).unwrap(),
+        json!({"code": "USD", "name": "US Dollar", "minor_units": 2})
+    );
+    assert_eq!(evaluator.apply(&json!({"lookup": ["currencies", "XXX", "name"]}), &json!({})).unwrap(), json!(null));
+    assert!(evaluator.apply(&json!({"lookup": ["routing", "X"]}), &json!({})).is_err());
+
+    let xml_bytes = fs::read("examples/pacs008_001_07_cct_outgoing.xml").expect("Failed to read test XML file");
+    let payload = Payload::new_inline(Some(xml_bytes), PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+    let mut message = Message::new(payload, "banking".to_string(), "pacs.008.001.07".to_string(), "test_reference".to_string(), "ISOIncoming".to_string(), None);
+    message.parse(None, "test_reference".to_string(), "ISOIncoming".to_string()).unwrap();
+
+    message.enrich_with(
+        &evaluator,
+        vec![
+            EnrichmentConfig {
+                field: "data.routing.creditor_bank".to_string(),
+                rule: json!({"lookup": ["bic_directory", {"var": "input.bic"}, "name"]}),
+                description: Some("Creditor bank name".to_string()),
+                ..Default::default()
+            },
+            EnrichmentConfig {
+                field: "data.routing.minor_units".to_string(),
+                rule: json!({"lookup": [{"var": "input.dataset"}, "EUR", "minor_units"]}),
+                description: None,
+                ..Default::default()
+            },
+        ],
+        json!({"bic": "AIBKIE2DXXX", "dataset": "currencies"}),
+        None,
+        "test_reference".to_string(),
+        "Enrich".to_string()
+    ).expect("Failed to enrich message");
+
+    assert_eq!(message.data()["routing"]["creditor_bank"], "Allied Irish Banks");
+    let changes = message.audit().last().unwrap().changes();
+    assert!(changes[0].reason().starts_with("Creditor bank name using bic_directory "));
+    assert_eq!(changes[1].reason(), "Enriched field data.routing.minor_units using currencies 2024-06");
+}
+
+#[test]
+fn test_reload_dataset() {
+    let path = std::env::temp_dir().join(format!("core-data-routing-{}.json", std::process::id()));
+    fs::write(&path, r#"{"version": "1", "records": [{"code": "021000021", "bank": "JPMorgan Chase"}]}"#).unwrap();
+
+    let reference_data = Arc::new(ReferenceData::new());
+    reference_data.insert(ReferenceDataset::load("routing", &path, "code").unwrap());
+    let evaluator = RuleEvaluator::with_reference_data(OperatorRegistry::new(), Arc::clone(&reference_data)).unwrap();
+    let rule = json!({"lookup": ["routing", "026009593", "bank"]});
+    assert_eq!(evaluator.apply(&rule, &json!({})).unwrap(), json!(null));
+
+    fs::write(&path, r#"{"version": "2", "records": [{"code": "021000021", "bank": "JPMorgan Chase"}, {"code": "026009593", "bank": "Bank of America"}]}"#).unwrap();
+    reference_data.reload("routing").expect("Failed to reload dataset");
+    assert_eq!(evaluator.apply(&rule, &json!({})).unwrap(), json!("Bank of America"));
+    assert_eq!(reference_data.get("routing").unwrap().version(), "2");
+
+    fs::remove_file(&path).unwrap();
+    assert!(reference_data.reload("routing").is_err());
+    assert!(reference_data.reload("unknown").is_err());
+}
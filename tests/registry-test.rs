@@ -0,0 +1,80 @@
+use serde_json::{json, Value};
+use core_data::models::registry::*;
+use core_data::models::workflow::*;
+
+mod common;
+use common::parsed_pacs008;
+
+fn workflow(name: &str, version: u16, status: WorkflowStatus, condition: Value) -> Workflow {
+    Workflow {
+        name: name.to_string(),
+        description: String::new(),
+        version,
+        tags: vec![],
+        status,
+        on_failure: None,
+        tasks: vec![],
+        condition,
+    }
+}
+
+#[test]
+fn test_highest_active_version_selected() {
+    let pacs008 = json!({"==": [{"var": "origin"}, "pacs.008.001.07"]});
+    let mut registry = WorkflowRegistry::new();
+    registry.register(workflow("incoming", 1, WorkflowStatus::Active, pacs008.clone())).unwrap();
+    registry.register(workflow("incoming", 3, WorkflowStatus::Draft, pacs008.clone())).unwrap();
+    registry.register(workflow("incoming", 2, WorkflowStatus::Active, pacs008.clone())).unwrap();
+    registry.register(workflow("incoming", 4, WorkflowStatus::Deprecated, pacs008.clone())).unwrap();
+    registry.register(workflow("outgoing", 1, WorkflowStatus::Active, json!({"==": [{"var": "origin"}, "pain.001.001.09"]}))).unwrap();
+
+    let mut message = parsed_pacs008("test_registry");
+    let selected = registry.select(&mut message, None).expect("A workflow should match");
+    assert_eq!((selected.workflow().name.as_str(), selected.workflow().version), ("incoming", 2));
+    assert_eq!(message.progress().workflow_id, "incoming");
+    assert_eq!(message.progress().workflow_version, Some(2));
+
+    let audit = message.audit().last().unwrap();
+    assert_eq!(audit.description(), "Workflow selected");
+    assert_eq!(audit.changes()[0].reason(), "Selected workflow incoming version 2");
+    assert_eq!(audit.changes()[0].new_value(), Some(&json!({"workflow_id": "incoming", "workflow_version": 2})));
+}
+
+#[test]
+fn test_deprecated_workflow_selected_with_warning() {
+    let mut registry = WorkflowRegistry::new();
+    registry.register(workflow("legacy", 1, WorkflowStatus::Deprecated, json!(true))).unwrap();
+    registry.register(workflow("legacy", 2, WorkflowStatus::Deprecated, json!(true))).unwrap();
+    registry.register(workflow("replacement", 1, WorkflowStatus::Draft, json!(true))).unwrap();
+
+    let mut message = parsed_pacs008("test_registry");
+    let selected = registry.select(&mut message, Some("Routing".to_string())).unwrap();
+    assert_eq!(selected.workflow().version, 2);
+    assert_eq!(
+        message.audit().last().unwrap().changes()[0].reason(),
+        "Selected workflow legacy version 2, warning: workflow is deprecated"
+    );
+}
+
+#[test]
+fn test_selection_errors() {
+    let mut registry = WorkflowRegistry::from_dir("examples/workflows").expect("Failed to load workflows");
+    assert_eq!(registry.workflows().len(), 2);
+    assert!(registry.get("incoming_pacs008", 2).is_some());
+    let error = registry.register(Workflow::from_file("examples/workflows/incoming_pacs008.json").unwrap())
+        .expect_err("Duplicate version should fail");
+    assert_eq!(error.message, "Workflow incoming_pacs008 version 2 is already registered");
+    assert!(registry.register(workflow("invalid", 1, WorkflowStatus::Active, json!({"nope": 1}))).is_err());
+
+    registry.register(workflow("sanctions", 1, WorkflowStatus::Active, json!({"==": [{"var": "tenant"}, "banking"]}))).unwrap();
+    let mut message = parsed_pacs008("test_registry");
+    let audit_entries = message.audit().len();
+    let error = registry.select(&mut message, None).err().expect("Two workflows match");
+    assert!(error.message.contains("Ambiguous workflows"));
+    assert!(error.message.ends_with("incoming_pacs008 version 2, sanctions version 1"));
+    assert_eq!(message.audit().len(), audit_entries);
+
+    let mut registry = WorkflowRegistry::new();
+    registry.register(workflow("outgoing", 1, WorkflowStatus::Active, json!(false))).unwrap();
+    assert!(registry.select(&mut message, None).err().unwrap().message.starts_with("No workflow matches message"));
+}
@@ -0,0 +1,162 @@
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use serde_json::{json, Value};
+use core_data::models::errors::FunctionResponseError;
+use core_data::models::executor::*;
+use core_data::models::logic::*;
+use core_data::models::message::*;
+use core_data::models::payload::*;
+use core_data::models::workflow::*;
+
+/// Fails while `down` is set.
+#[derive(Default)]
+struct Gateway {
+    down: AtomicBool,
+    published: AtomicU32,
+}
+
+impl Publisher for Gateway {
+    fn publish(&self, _message: &Message, _input: &Value) -> Result<(), FunctionResponseError> {
+        if self.down.load(Ordering::SeqCst) {
+            return Err(FunctionResponseError::new("Publish".to_string(), 503, "Gateway down".to_string()));
+        }
+        self.published.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+fn append(task_id: &str, value: &str) -> Value {
+    json!({
+        "task_id": task_id, "name": task_id, "description": "", "condition": true, "function": "Enrich",
+        "input": [{"field": "data.metadata.trail", "rule": value, "description": null, "operation": {"type": "append"}}]
+    })
+}
+
+fn compile(version: u16, tasks: Vec<Value>) -> CompiledWorkflow {
+    let workflow = Workflow::from_value(json!({
+        "name": "outgoing", "description": "", "version": version, "tags": [], "status": "Active", "condition": true, "tasks": tasks
+    })).expect("Invalid workflow");
+    CompiledWorkflow::new(workflow, RuleEvaluator::shared()).expect("Workflow should compile")
+}
+
+fn v1() -> CompiledWorkflow {
+    let publish = json!({"task_id": "publish", "name": "publish", "description": "", "condition": true, "function": "Publish", "input": {"topic": "payments"}});
+    compile(1, vec![append("stamp", "stamp"), publish, append("route", "route")])
+}
+
+/// A message failed on the publish task of `workflow`, saved and loaded again.
+fn failed_on(workflow: &CompiledWorkflow, gateway: &Arc<Gateway>) -> Message {
+    let xml_bytes = fs::read("examples/pacs008_001_07_cct_outgoing.xml").expect("Failed to read test XML file");
+    let payload = Payload::new_inline(Some(xml_bytes), PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+    let mut message = Message::new(payload, "banking".to_string(), "pacs.008.001.07".to_string(), "test_resume".to_string(), "ISOIncoming".to_string(), None);
+    message.parse(None, "test_resume".to_string(), "ISOIncoming".to_string()).unwrap();
+
+    gateway.down.store(true, Ordering::SeqCst);
+    let executor = WorkflowExecutor::new().with_publisher(gateway.clone());
+    executor.run(workflow, &mut message, &json!({})).expect_err("Gateway is down");
+    gateway.down.store(false, Ordering::SeqCst);
+    serde_json::from_str(&serde_json::to_string(&message).unwrap()).expect("Message should deserialize")
+}
+
+fn failed_message(gateway: &Arc<Gateway>) -> Message {
+    failed_on(&v1(), gateway)
+}
+
+#[test]
+fn test_resume_runs_remaining_tasks_once() {
+    let gateway = Arc::new(Gateway::default());
+    let mut message = failed_message(&gateway);
+    assert_eq!(message.progress().status, MessageStatus::Failed);
+    assert_eq!(message.progress().workflow_version, Some(1));
+    let completed: Vec<&str> = message.progress().completed_tasks.iter().map(|c| c.task_id.as_str()).collect();
+    assert_eq!(completed, vec!["stamp"]);
+
+    let executor = WorkflowExecutor::new().with_publisher(gateway.clone());
+    let report = executor.resume(&v1(), &mut message, &json!({})).expect("Resume should succeed");
+    assert_eq!(report.resumed, vec!["stamp"]);
+    assert_eq!(report.executed, vec!["publish", "route"]);
+    assert_eq!(message.data()["metadata"]["trail"], json!(["stamp", "route"]));
+    assert_eq!(message.progress().status, MessageStatus::Completed);
+    assert_eq!(gateway.published.load(Ordering::SeqCst), 1);
+
+    let report = executor.resume(&v1(), &mut message, &json!({})).expect("Completed message resumes as is");
+    assert!(report.executed.is_empty());
+    assert_eq!(report.resumed, vec!["stamp", "publish", "route"]);
+    assert_eq!(gateway.published.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_resume_checks_workflow_version() {
+    let gateway = Arc::new(Gateway::default());
+    let executor = WorkflowExecutor::new().with_publisher(gateway.clone());
+    let publish = json!({"task_id": "publish", "name": "publish", "description": "Reworded", "condition": true, "function": "Publish", "input": {"topic": "payments"}});
+
+    let changed = compile(2, vec![append("stamp", "stamped"), publish.clone()]);
+    let error = executor.resume(&changed, &mut failed_message(&gateway), &json!({})).expect_err("Changed task");
+    assert!(error.message.ends_with("completed task stamp changed in workflow outgoing version 2"));
+
+    let removed = compile(2, vec![publish.clone()]);
+    let error = executor.resume(&removed, &mut failed_message(&gateway), &json!({})).expect_err("Removed task");
+    assert!(error.message.ends_with("completed task stamp was removed from workflow outgoing version 2"));
+
+    let extended = compile(2, vec![append("stamp", "stamp"), publish, append("audit", "audit")]);
+    let mut message = failed_message(&gateway);
+    let report = executor.resume(&extended, &mut message, &json!({})).expect("Compatible version");
+    assert_eq!(report.executed, vec!["publish", "audit"]);
+    assert_eq!(message.progress().workflow_version, Some(2));
+}
+
+#[test]
+fn test_resume_after_prev_task() {
+    let gateway = Arc::new(Gateway::default());
+    let mut saved = serde_json::to_value(failed_message(&gateway)).unwrap();
+    saved["progress"].as_object_mut().unwrap().remove("completed_tasks");
+    saved["progress"]["prev_task"] = json!("publish");
+    saved["progress"]["status"] = json!("Processing");
+    let mut message: Message = serde_json::from_value(saved.clone()).unwrap();
+
+    let executor = WorkflowExecutor::new().with_publisher(gateway.clone());
+    let report = executor.resume(&v1(), &mut message, &json!({})).expect("Resume should succeed");
+    assert_eq!(report.resumed, vec!["stamp", "publish"]);
+    assert_eq!(report.executed, vec!["route"]);
+    assert_eq!(gateway.published.load(Ordering::SeqCst), 0);
+
+    saved["progress"]["status"] = json!("Failed");
+    let report = executor.resume(&v1(), &mut serde_json::from_value(saved.clone()).unwrap(), &json!({})).unwrap();
+    assert_eq!(report.executed, vec!["publish", "route"]);
+
+    saved["progress"]["workflow_version"] = json!(3);
+    let error = executor.resume(&v1(), &mut serde_json::from_value(saved.clone()).unwrap(), &json!({})).expect_err("Unknown changes");
+    assert!(error.message.contains("changed from version 3 to 1 and no completed tasks are recorded"));
+    saved["progress"]["workflow_id"] = json!("incoming");
+    let error = executor.resume(&v1(), &mut serde_json::from_value(saved).unwrap(), &json!({})).expect_err("Other workflow");
+    assert!(error.message.ends_with("it is on workflow incoming, not outgoing"));
+}
+
+#[test]
+fn test_resume_after_compensation() {
+    let mut stamp = json!({
+        "task_id": "stamp", "name": "stamp", "description": "", "condition": true, "function": "Enrich",
+        "input": [{"field": "data.metadata.stamped", "rule": true, "description": null}]
+    });
+    stamp["compensation"] = json!({
+        "task_id": "unstamp", "name": "unstamp", "description": "", "condition": true, "function": "Enrich",
+        "input": [{"field": "data.metadata.stamped", "description": null, "operation": {"type": "remove"}}]
+    });
+    let publish = json!({"task_id": "publish", "name": "publish", "description": "", "condition": true, "function": "Publish", "input": {"topic": "payments"}, "depends_on": ["stamp", "route"]});
+    let workflow = compile(1, vec![stamp, append("route", "route"), publish]);
+
+    let gateway = Arc::new(Gateway::default());
+    let mut message = failed_on(&workflow, &gateway);
+    assert!(message.data()["metadata"].get("stamped").is_none());
+    let completed: Vec<&str> = message.progress().completed_tasks.iter().map(|c| c.task_id.as_str()).collect();
+    assert_eq!(completed, vec!["route"]);
+
+    let executor = WorkflowExecutor::new().with_publisher(gateway.clone());
+    let report = executor.resume(&workflow, &mut message, &json!({})).expect("Resume should succeed");
+    assert_eq!(report.resumed, vec!["route"]);
+    assert_eq!(report.executed, vec!["stamp", "publish"]);
+    assert_eq!(message.data()["metadata"]["stamped"], true);
+    assert_eq!(message.progress().status, MessageStatus::Completed);
+}
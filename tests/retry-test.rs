@@ -0,0 +1,120 @@
+use std::fs;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::{json, Value};
+use core_data::models::errors::*;
+use core_data::models::executor::*;
+use core_data::models::logic::*;
+use core_data::models::message::*;
+use core_data::models::payload::*;
+use core_data::models::task::*;
+use core_data::models::workflow::*;
+
+/// Fails with `code` until `failures` attempts have been made.
+struct FlakyPublisher {
+    code: u32,
+    failures: u32,
+    attempts: AtomicU32,
+}
+
+impl Publisher for FlakyPublisher {
+    fn publish(&self, _message: &Message, _input: &Value) -> Result<(), FunctionResponseError> {
+        match self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures {
+            true => Err(FunctionResponseError::new("Publish".to_string(), self.code, "Sink unavailable".to_string())),
+            false => Ok(()),
+        }
+    }
+}
+
+fn publish_workflow(retry: Value) -> CompiledWorkflow {
+    let definition = json!({
+        "name": "outgoing",
+        "description": "",
+        "version": 1,
+        "tags": [],
+        "status": "Active",
+        "condition": true,
+        "tasks": [{
+            "task_id": "publish",
+            "name": "Publish",
+            "description": "",
+            "condition": true,
+            "function": "Publish",
+            "input": {"topic": "payments"},
+            "retry": retry
+        }]
+    });
+    let workflow = Workflow::from_json(&definition.to_string()).expect("Invalid workflow");
+    CompiledWorkflow::new(workflow, RuleEvaluator::shared()).expect("Workflow should compile")
+}
+
+fn run(workflow: &CompiledWorkflow, code: u32, failures: u32) -> (Message, Result<ExecutionReport, FunctionResponseError>, u32) {
+    let xml_bytes = fs::read("examples/pacs008_001_07_cct_outgoing.xml").expect("Failed to read test XML file");
+    let payload = Payload::new_inline(Some(xml_bytes), PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+    let mut message = Message::new(payload, "banking".to_string(), "pacs.008.001.07".to_string(), "test_retry".to_string(), "ISOIncoming".to_string(), None);
+    let publisher = Arc::new(FlakyPublisher { code, failures, attempts: AtomicU32::new(0) });
+    let result = WorkflowExecutor::new().with_publisher(publisher.clone()).run(workflow, &mut message, &json!({}));
+    let attempts = publisher.attempts.load(Ordering::SeqCst);
+    (message, result, attempts)
+}
+
+#[test]
+fn test_transient_failures_retried() {
+    let workflow = publish_workflow(json!({"max_attempts": 3, "backoff": {"type": "exponential", "initial_ms": 1, "max_ms": 5}, "jitter": 0.5}));
+    let (message, result, attempts) = run(&workflow, 503, 2);
+    assert_eq!(result.expect("Third attempt should succeed").executed, vec!["publish"]);
+    assert_eq!(attempts, 3);
+    assert_eq!(message.progress().status, MessageStatus::Completed);
+
+    let audit: Vec<_> = message.audit().iter().skip(1).collect();
+    assert_eq!(audit.len(), 3);
+    assert_eq!(audit[0].description(), "Attempt 1 of 3 failed");
+    assert_eq!(audit[1].description(), "Attempt 2 of 3 failed");
+    assert!(audit[1].changes()[0].reason().starts_with("Publish: Sink unavailable, retrying in "));
+    assert_eq!(audit[1].changes()[0].new_value(), Some(&json!("Retrying")));
+    assert_eq!(audit[2].description(), "Message published");
+}
+
+#[test]
+fn test_retries_exhausted_or_not_retryable() {
+    let workflow = publish_workflow(json!({"max_attempts": 2, "backoff": {"type": "fixed", "delay_ms": 1}}));
+    let (message, result, attempts) = run(&workflow, 503, 5);
+    assert_eq!(result.expect_err("Retries should be exhausted").kind(), ErrorKind::Unavailable);
+    assert_eq!(attempts, 2);
+    assert_eq!(message.progress().status, MessageStatus::Failed);
+    assert_eq!(message.audit().len(), 2);
+
+    let (_, result, attempts) = run(&workflow, 400, 1);
+    assert_eq!(result.expect_err("Invalid input is not retried").kind(), ErrorKind::Invalid);
+    assert_eq!(attempts, 1);
+
+    let workflow = publish_workflow(json!({"max_attempts": 2, "backoff": {"type": "fixed", "delay_ms": 1}, "retry_on": ["invalid"]}));
+    assert!(run(&workflow, 400, 1).1.is_ok());
+}
+
+#[test]
+fn test_backoff_delays() {
+    let policy = RetryPolicy {
+        max_attempts: 10,
+        backoff: Backoff::Exponential { initial_ms: 100, max_ms: 1000 },
+        jitter: 0.0,
+        retry_on: vec![ErrorKind::Timeout],
+    };
+    let delays: Vec<Duration> = (1..=5).map(|retry| policy.delay(retry)).collect();
+    assert_eq!(delays, [100, 200, 400, 800, 1000].map(Duration::from_millis));
+    assert!(policy.retries(9, &FunctionResponseError::new("Publish".to_string(), 504, String::new())));
+    assert!(!policy.retries(10, &FunctionResponseError::new("Publish".to_string(), 504, String::new())));
+    assert!(!policy.retries(1, &FunctionResponseError::new("Publish".to_string(), 503, String::new())));
+
+    let policy = RetryPolicy { backoff: Backoff::Fixed { delay_ms: 100 }, jitter: 0.2, ..policy };
+    for _ in 0..20 {
+        let delay = policy.delay(3);
+        assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(120));
+    }
+
+    let mut workflow = Workflow::from_file("examples/workflows/outgoing_pain001.yaml").unwrap();
+    workflow.tasks[1].retry = Some(RetryPolicy { max_attempts: 0, jitter: 1.5, ..policy });
+    let locations: Vec<String> = workflow.validate().unwrap_err().into_iter().map(|e| e.location).collect();
+    assert_eq!(locations, vec!["tasks[1].retry.max_attempts", "tasks[1].retry.jitter"]);
+}
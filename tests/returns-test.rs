@@ -0,0 +1,65 @@
+use core_data::models::returns::*;
+
+mod common;
+use common::parsed_pacs008;
+
+fn config(amount: Option<f64>) -> Pacs004Config {
+    Pacs004Config {
+        instructing_agent: "AIBKIE2DXXX".to_string(),
+        instructed_agent: "IRCEIE2DXXX".to_string(),
+        settlement_method: "CLRG".to_string(),
+        clearing_system: Some("TTD".to_string()),
+        reason_code: "AC04".to_string(),
+        additional_info: Some("Account closed".to_string()),
+        amount,
+        end_to_end_id: None,
+    }
+}
+
+#[test]
+fn test_pacs004_full_return() {
+    let mut original = parsed_pacs008("test_returns");
+
+    let payment_return = original.to_pacs004(&config(None), None, "test_returns".to_string(), "Return".to_string())
+        .expect("Failed to generate return");
+
+    let document = &payment_return.data()["document"]["PmtRtr"];
+    assert_eq!(document["OrgnlGrpInf"]["OrgnlMsgId"], "VOLCUSTMSGID0001");
+    assert_eq!(document["TxInf"][0]["OrgnlEndToEndId"], "VOLCUSTETEID0001");
+    assert_eq!(document["TxInf"][0]["OrgnlTxId"], "VOLCUSTTXID00001");
+    assert_eq!(document["TxInf"][0]["OrgnlIntrBkSttlmAmt"]["$value"], 100.0);
+    assert_eq!(document["TxInf"][0]["RtrdIntrBkSttlmAmt"]["$value"], 100.0);
+    assert_eq!(document["TxInf"][0]["RtrRsnInf"][0]["Rsn"]["Cd"], "AC04");
+    assert_eq!(document["TxInf"][0]["RtrChain"]["Dbtr"]["Pty"]["Nm"], "ZZ Insurances");
+    assert_eq!(document["TxInf"][0]["RtrChain"]["Cdtr"]["Pty"]["Nm"], "Mr. Jones");
+
+    assert_eq!(payment_return.parent_id(), &Some(original.id().to_string()));
+    assert_eq!(payment_return.audit().len(), 2);
+    assert_eq!(original.audit().len(), 3);
+    assert_eq!(
+        original.audit()[2].changes()[0].new_value().unwrap(),
+        &serde_json::json!(payment_return.id().to_string())
+    );
+}
+
+#[test]
+fn test_pacs004_partial_return() {
+    let mut original = parsed_pacs008("test_returns");
+
+    let payment_return = original.to_pacs004(&config(Some(40.5)), None, "test_returns".to_string(), "Return".to_string())
+        .expect("Failed to generate return");
+
+    let document = &payment_return.data()["document"]["PmtRtr"];
+    assert_eq!(document["TxInf"][0]["RtrdIntrBkSttlmAmt"]["$value"], 40.5);
+    assert_eq!(document["GrpHdr"]["TtlRtrdIntrBkSttlmAmt"]["$value"], 40.5);
+    assert_eq!(document["TxInf"][0]["OrgnlIntrBkSttlmAmt"]["$value"], 100.0);
+}
+
+#[test]
+fn test_pacs004_rejects_excess_amount() {
+    let mut original = parsed_pacs008("test_returns");
+
+    let result = original.to_pacs004(&config(Some(150.0)), None, "test_returns".to_string(), "Return".to_string());
+    assert!(result.is_err());
+    assert_eq!(original.audit().len(), 2);
+}
@@ -0,0 +1,136 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde_json::{json, Value};
+use core_data::models::errors::FunctionResponseError;
+use core_data::models::executor::*;
+use core_data::models::logic::*;
+use core_data::models::message::*;
+use core_data::models::simulation::*;
+use core_data::models::workflow::*;
+
+mod common;
+use common::{parsed_pacs008, task};
+
+#[derive(Default)]
+struct RecordingPublisher {
+    published: Mutex<Vec<Value>>,
+}
+
+impl Publisher for RecordingPublisher {
+    fn publish(&self, _message: &Message, input: &Value) -> Result<(), FunctionResponseError> {
+        self.published.lock().unwrap().push(input.clone());
+        Ok(())
+    }
+}
+
+fn compile(condition: Value, tasks: Vec<Value>) -> CompiledWorkflow {
+    let workflow = Workflow::from_value(json!({
+        "name": "incoming", "description": "", "version": 2, "tags": [], "status": "Draft", "condition": condition, "tasks": tasks
+    })).expect("Invalid workflow");
+    CompiledWorkflow::new(workflow, RuleEvaluator::shared()).expect("Workflow should compile")
+}
+
+#[test]
+fn test_simulation_has_no_side_effects() {
+    let mut urgent = task("urgent", "Enrich", json!([{"field": "data.metadata.urgent", "rule": true, "description": null}]));
+    urgent["condition"] = json!({"==": [{"var": "data.metadata.channel"}, "RTGS"]});
+    let workflow = compile(json!({"==": [{"var": "origin"}, "pacs.008.001.07"]}), vec![
+        task("channel", "Enrich", json!([{"field": "data.metadata.channel", "rule": {"var": "input.channel"}, "description": null}])),
+        urgent,
+        task("publish", "Publish", json!({"topic": "payments"})),
+    ]);
+    let publisher = Arc::new(RecordingPublisher::default());
+    let executor = WorkflowExecutor::new().with_publisher(publisher.clone());
+    let message = parsed_pacs008("test_simulation");
+
+    let report = executor.simulate(&workflow, &message, &json!({"channel": "SWIFT"}));
+    assert!(report.matched);
+    assert_eq!((report.workflow.as_str(), report.version), ("incoming", 2));
+    assert_eq!(report.conditions, vec![
+        TaskCondition { task_id: "channel".to_string(), matched: true },
+        TaskCondition { task_id: "urgent".to_string(), matched: false },
+        TaskCondition { task_id: "publish".to_string(), matched: true },
+    ]);
+    assert_eq!(report.execution.executed, vec!["channel", "publish"]);
+    assert_eq!(report.changes, vec![DataChange { path: "metadata.channel".to_string(), old_value: None, new_value: Some(json!("SWIFT")) }]);
+    assert_eq!(report.publications, vec![SuppressedPublication { message_id: message.id(), input: json!({"topic": "payments"}) }]);
+    assert_eq!(report.audit.len(), 2);
+    assert_eq!(report.status, MessageStatus::Completed);
+    assert_eq!(report.error, None);
+
+    assert!(publisher.published.lock().unwrap().is_empty());
+    assert!(message.data().get("metadata").is_none());
+    assert_eq!(message.progress().status, MessageStatus::Recieved);
+}
+
+#[test]
+fn test_simulation_reports_failures() {
+    let mut failing = task("tags", "Enrich", json!([
+        {"field": "data.metadata.tags", "rule": "first", "description": null},
+        {"field": "data.metadata.tags", "rule": "second", "description": null, "operation": {"type": "append"}}
+    ]));
+    failing["on_failure"] = json!({"type": "reject", "queue": "rejections", "status_report": {
+        "instructing_agent": "AIBKIE2DXXX", "instructed_agent": "IRCEIE2DXXX", "reason_code": null
+    }});
+    let workflow = compile(json!({"==": [{"var": "origin"}, "pacs.009.001.08"]}), vec![
+        task("route", "Enrich", json!([{"field": "data.metadata.route", "rule": "SEPA", "description": null}])),
+        failing,
+        task("publish", "Publish", json!({"topic": "payments"})),
+    ]);
+
+    let report = WorkflowExecutor::new().simulate(&workflow, &parsed_pacs008("test_simulation"), &json!({}));
+    assert!(!report.matched);
+    assert_eq!(report.error.as_deref(), Some("Enrichment: Cannot append to data.metadata.tags: not an array"));
+    assert_eq!(report.status, MessageStatus::Failed);
+    assert_eq!(report.execution.failed, vec!["tags"]);
+    assert_eq!(report.conditions.len(), 2);
+    assert_eq!(report.changes[0].path, "metadata.route");
+    assert_eq!(report.changes[0].new_value, Some(json!("SEPA")));
+    assert_eq!(report.publications.len(), 1);
+    assert_eq!(report.publications[0].input["queue"], "rejections");
+}
+
+#[test]
+fn test_simulation_skips_retry_delays() {
+    let mut failing = task("tags", "Enrich", json!([
+        {"field": "data.metadata.tags", "rule": "first", "description": null},
+        {"field": "data.metadata.tags", "rule": "second", "description": null, "operation": {"type": "append"}}
+    ]));
+    failing["retry"] = json!({"max_attempts": 3, "backoff": {"type": "exponential", "initial_ms": 60000, "max_ms": 90000}, "retry_on": ["invalid"]});
+    let workflow = compile(json!(true), vec![failing]);
+
+    let start = Instant::now();
+    let report = WorkflowExecutor::new().simulate(&workflow, &parsed_pacs008("test_simulation"), &json!({}));
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert_eq!(report.execution.failed, vec!["tags"]);
+    assert_eq!(report.waits, vec![
+        PlannedWait { task_id: "tags".to_string(), attempt: 1, delay_ms: 60000 },
+        PlannedWait { task_id: "tags".to_string(), attempt: 2, delay_ms: 90000 },
+    ]);
+}
+
+#[test]
+fn test_enrichment_simulation() {
+    let message = parsed_pacs008("test_simulation");
+    let config = serde_json::from_value(json!([
+        {"field": "data.document.FIToFICstmrCdtTrf.GrpHdr.MsgId", "rule": {"cat": ["SIM-", {"var": "data.document.FIToFICstmrCdtTrf.GrpHdr.MsgId"}]}, "description": null},
+        {"field": "data.metadata.urgent", "rule": true, "description": null, "condition": false}
+    ])).unwrap();
+    let enrichment = CompiledEnrichment::new(RuleEvaluator::shared(), config).unwrap();
+
+    let simulation = message.simulate_enrichment(&enrichment, json!({}));
+    assert_eq!(simulation.error, None);
+    assert_eq!(simulation.changes, vec![DataChange {
+        path: "document.FIToFICstmrCdtTrf.GrpHdr.MsgId".to_string(),
+        old_value: Some(json!("VOLCUSTMSGID0001")),
+        new_value: Some(json!("SIM-VOLCUSTMSGID0001")),
+    }]);
+    assert_eq!(simulation.entries.len(), 2);
+    assert!(simulation.entries[1].reason().ends_with("skipped, condition not met"));
+    assert_eq!(message.data()["document"]["FIToFICstmrCdtTrf"]["GrpHdr"]["MsgId"], "VOLCUSTMSGID0001");
+
+    let config = serde_json::from_value(json!([{"field": "metadata.urgent", "rule": true, "description": null}])).unwrap();
+    let simulation = message.simulate_enrichment(&CompiledEnrichment::new(RuleEvaluator::shared(), config).unwrap(), json!({}));
+    assert!(simulation.error.unwrap().contains("Invalid field path"));
+    assert!(simulation.changes.is_empty());
+}
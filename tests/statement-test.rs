@@ -0,0 +1,98 @@
+use core_data::models::message::*;
+use core_data::models::statement::*;
+
+mod common;
+use common::parsed_pacs008;
+
+const CREDITOR_IBAN: &str = "IE29AIBK93115212345678";
+const DEBTOR_IBAN: &str = "IE64IRCE92050112345678";
+
+fn account(iban: &str) -> AccountConfig {
+    AccountConfig {
+        iban: iban.to_string(),
+        currency: None,
+        servicer: Some("AIBKIE2DXXX".to_string()),
+        bank_transaction_code: None,
+    }
+}
+
+fn statement_config(opening_balance: f64, closing_balance: f64) -> Camt053Config {
+    Camt053Config {
+        account: account(CREDITOR_IBAN),
+        opening_balance,
+        closing_balance,
+        date: Some("2020-06-20".to_string()),
+    }
+}
+
+#[test]
+fn test_camt054_credit_notification() {
+    let mut payment = parsed_pacs008("test_statement");
+
+    let notification = payment.to_camt054(&account(CREDITOR_IBAN), None, "test_statement".to_string(), "Notify".to_string())
+        .expect("Failed to generate notification");
+
+    let document = &notification.data()["document"]["BkToCstmrDbtCdtNtfctn"]["Ntfctn"][0];
+    assert_eq!(document["Acct"]["Id"]["IBAN"], CREDITOR_IBAN);
+    assert_eq!(document["Acct"]["Ccy"], "EUR");
+    assert_eq!(document["Ntry"][0]["CdtDbtInd"], "CRDT");
+    assert_eq!(document["Ntry"][0]["Amt"]["$value"], 100.0);
+    assert_eq!(document["Ntry"][0]["BookgDt"]["Dt"], "2020-06-20");
+    assert_eq!(document["Ntry"][0]["BkTxCd"]["Domn"]["Fmly"]["Cd"], "RCDT");
+    assert_eq!(document["Ntry"][0]["NtryDtls"][0]["TxDtls"][0]["Refs"]["EndToEndId"], "VOLCUSTETEID0001");
+    assert_eq!(document["Ntry"][0]["NtryDtls"][0]["TxDtls"][0]["RltdPties"]["Dbtr"]["Pty"]["Nm"], "Mr. Jones");
+
+    assert_eq!(notification.parent_id(), &Some(payment.id().to_string()));
+    assert!(notification.document().is_ok());
+    assert_eq!(payment.audit().last().unwrap().changes()[0].new_value(), Some(&serde_json::json!(notification.id().to_string())));
+}
+
+#[test]
+fn test_camt054_debit_notification_and_unknown_account() {
+    let mut payment = parsed_pacs008("test_statement");
+
+    let notification = payment.to_camt054(&account(DEBTOR_IBAN), None, "test_statement".to_string(), "Notify".to_string())
+        .expect("Failed to generate notification");
+    let entry = &notification.data()["document"]["BkToCstmrDbtCdtNtfctn"]["Ntfctn"][0]["Ntry"][0];
+    assert_eq!(entry["CdtDbtInd"], "DBIT");
+    assert_eq!(entry["BkTxCd"]["Domn"]["Fmly"]["Cd"], "ICDT");
+
+    let result = payment.to_camt054(&account("DE89370400440532013000"), None, "test_statement".to_string(), "Notify".to_string());
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_camt053_statement_of_completed_messages() {
+    let mut completed = parsed_pacs008("test_statement");
+    completed.set_status(MessageStatus::Completed);
+    let pending = parsed_pacs008("test_statement");
+    let mut messages = vec![completed, pending];
+
+    let statement = Message::to_camt053(&mut messages, &statement_config(250.0, 350.0), None, "test_statement".to_string(), "Statement".to_string())
+        .expect("Failed to generate statement");
+
+    let document = &statement.data()["document"]["BkToCstmrStmt"]["Stmt"][0];
+    assert_eq!(document["Bal"][0]["Tp"]["CdOrPrtry"]["Cd"], "OPBD");
+    assert_eq!(document["Bal"][0]["Amt"]["$value"], 250.0);
+    assert_eq!(document["Bal"][1]["Tp"]["CdOrPrtry"]["Cd"], "CLBD");
+    assert_eq!(document["Bal"][1]["Amt"]["$value"], 350.0);
+    assert_eq!(document["Ntry"].as_array().unwrap().len(), 1);
+    assert_eq!(document["TxsSummry"]["TtlCdtNtries"]["NbOfNtries"], "1");
+    assert_eq!(document["TxsSummry"]["TtlDbtNtries"]["Sum"], 0.0);
+
+    assert_eq!(statement.parent_id(), &None);
+    assert!(statement.document().is_ok());
+    assert_eq!(messages[0].audit().last().unwrap().changes()[0].field(), "statement");
+    assert_eq!(messages[1].audit().len(), 2);
+}
+
+#[test]
+fn test_camt053_balances_must_reconcile() {
+    let mut completed = parsed_pacs008("test_statement");
+    completed.set_status(MessageStatus::Completed);
+    let mut messages = vec![completed];
+
+    let result = Message::to_camt053(&mut messages, &statement_config(250.0, 300.0), None, "test_statement".to_string(), "Statement".to_string());
+    assert!(result.is_err());
+    assert_eq!(messages[0].audit().len(), 2);
+}
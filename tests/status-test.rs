@@ -0,0 +1,90 @@
+use core_data::models::message::*;
+use core_data::models::status::*;
+use core_data::models::errors::FunctionResponseError;
+use serde_json::json;
+
+mod common;
+use common::parsed_pacs008;
+
+fn config() -> Pacs002Config {
+    Pacs002Config {
+        instructing_agent: "AIBKIE2DXXX".to_string(),
+        instructed_agent: "IRCEIE2DXXX".to_string(),
+        reason_code: None,
+        rejected_transactions: Default::default(),
+        settled: false,
+    }
+}
+
+#[test]
+fn test_pacs002_accepted_settlement() {
+    let mut message = parsed_pacs008("test_status");
+    message.set_status(MessageStatus::Completed);
+
+    let report = message.to_pacs002(&config(), None, None, "test_status".to_string(), "StatusReport".to_string())
+        .expect("Failed to generate status report");
+
+    let status = &report.data()["document"]["FIToFIPmtStsRpt"];
+    assert_eq!(status["OrgnlGrpInfAndSts"][0]["OrgnlMsgId"], "VOLCUSTMSGID0001");
+    assert_eq!(status["OrgnlGrpInfAndSts"][0]["OrgnlMsgNmId"], "pacs.008.001.07");
+    assert_eq!(status["OrgnlGrpInfAndSts"][0]["GrpSts"], "ACCP");
+    assert_eq!(status["TxInfAndSts"][0]["OrgnlEndToEndId"], "VOLCUSTETEID0001");
+    assert_eq!(status["TxInfAndSts"][0]["TxSts"], "ACCP");
+    assert_eq!(report.parent_id(), &Some(message.id().to_string()));
+    assert_eq!(message.audit().last().unwrap().description(), "pacs.002 status report generated");
+
+    let mut config = config();
+    config.settled = true;
+    let report = message.to_pacs002(&config, None, None, "test_status".to_string(), "StatusReport".to_string())
+        .expect("Failed to generate status report");
+    let status = &report.data()["document"]["FIToFIPmtStsRpt"];
+    assert_eq!(status["OrgnlGrpInfAndSts"][0]["GrpSts"], "ACSC");
+    assert_eq!(status["TxInfAndSts"][0]["TxSts"], "ACSC");
+}
+
+#[test]
+fn test_pacs002_rejection_after_failure() {
+    let mut message = parsed_pacs008("test_status");
+    let failure = message.enrich(
+        vec![EnrichmentConfig {
+            field: "metadata.invalid".to_string(),
+            rule: json!({"var": ["input.value"]}),
+            description: None,
+            ..Default::default()
+        }],
+        json!({"value": 1}),
+        None,
+        "test_status".to_string(),
+        "Enrich".to_string(),
+    ).expect_err("Enrichment outside data should fail");
+    assert!(message.progress().status != MessageStatus::Completed);
+
+    let mut config = config();
+    config.reason_code = Some("AC01".to_string());
+    let report = message.to_pacs002(&config, Some(&failure), None, "test_status".to_string(), "StatusReport".to_string())
+        .expect("Failed to generate status report");
+
+    let status = &report.data()["document"]["FIToFIPmtStsRpt"];
+    assert_eq!(status["OrgnlGrpInfAndSts"][0]["GrpSts"], "RJCT");
+    assert_eq!(status["TxInfAndSts"][0]["TxSts"], "RJCT");
+    assert_eq!(status["TxInfAndSts"][0]["StsRsnInf"][0]["Rsn"]["Cd"], "AC01");
+    assert_eq!(status["TxInfAndSts"][0]["StsRsnInf"][0]["AddtlInf"][0], "Invalid field path");
+}
+
+#[test]
+fn test_pacs002_transaction_rejection() {
+    let mut message = parsed_pacs008("test_status");
+    let mut config = config();
+    config.rejected_transactions.insert("VOLCUSTETEID0001".to_string(), "AM04".to_string());
+
+    let report = message.to_pacs002(&config, None, None, "test_status".to_string(), "StatusReport".to_string())
+        .expect("Failed to generate status report");
+
+    let status = &report.data()["document"]["FIToFIPmtStsRpt"];
+    assert_eq!(status["OrgnlGrpInfAndSts"][0]["GrpSts"], "RJCT");
+    assert_eq!(status["OrgnlGrpInfAndSts"][0]["StsRsnInf"][0]["Rsn"]["Cd"], "AM04");
+    assert_eq!(status["TxInfAndSts"][0]["StsRsnInf"][0]["Rsn"]["Cd"], "AM04");
+
+    let error = FunctionResponseError::new("Publish".to_string(), 500, "Sink unavailable".to_string());
+    assert!(message.to_pacs002(&config, Some(&error), None, "test_status".to_string(), "StatusReport".to_string()).is_ok());
+}
@@ -0,0 +1,97 @@
+use std::fs;
+use core_data::models::message::*;
+use core_data::models::payload::*;
+
+fn bulk_message(xml_bytes: Vec<u8>) -> Message {
+    let payload = Payload::new_inline(
+        Some(xml_bytes),
+        PayloadFormat::Xml,
+        PayloadSchema::ISO20022,
+        Encoding::Utf8
+    );
+
+    Message::new(
+        payload,
+        "banking".to_string(),
+        "pain.001.001.09".to_string(),
+        "test_stream".to_string(),
+        "ISOIncoming".to_string(),
+        Some("bulk".to_string())
+    )
+}
+
+#[test]
+fn test_stream_bulk_transactions() {
+    let xml_bytes = fs::read("examples/pain001_001_09_bulk.xml")
+        .expect("Failed to read test XML file");
+    let mut message = bulk_message(xml_bytes);
+
+    let mut transactions = Vec::new();
+    let count = message.parse_stream(
+        Some("Streamed bulk file".to_string()),
+        "test_stream".to_string(),
+        "ISOIncoming".to_string(),
+        |transaction| {
+            transactions.push(transaction.expect("Transaction should validate"));
+            Ok(())
+        }
+    ).expect("Failed to stream message");
+
+    assert_eq!(count, 3);
+    assert_eq!(transactions.len(), 3);
+    assert!(message.data().is_null());
+    assert_eq!(message.audit().len(), 2);
+    assert_eq!(message.audit()[1].description(), "Streamed bulk file");
+
+    let end_to_end_ids: Vec<&str> = transactions.iter()
+        .map(|t| {
+            let initiation = &t["document"]["CstmrCdtTrfInitn"];
+            assert_eq!(initiation["GrpHdr"]["MsgId"], "ACMEMSGID20241115");
+            assert_eq!(initiation["PmtInf"].as_array().unwrap().len(), 1);
+            assert_eq!(initiation["PmtInf"][0]["CdtTrfTxInf"].as_array().unwrap().len(), 1);
+            initiation["PmtInf"][0]["CdtTrfTxInf"][0]["PmtId"]["EndToEndId"].as_str().unwrap()
+        })
+        .collect();
+    assert_eq!(end_to_end_ids, vec!["E2E-0001", "E2E-0002", "E2E-0003"]);
+    assert_eq!(transactions[2]["document"]["CstmrCdtTrfInitn"]["PmtInf"][0]["PmtInfId"], "PMTINF-0002");
+}
+
+#[test]
+fn test_stream_reports_invalid_transaction() {
+    let xml = fs::read_to_string("examples/pain001_001_09_bulk.xml")
+        .expect("Failed to read test XML file")
+        .replacen("<EndToEndId>E2E-0002<", "<EndToEndId>E2E-0002-EXCEEDING-THE-35-CHARACTER-LIMIT<", 1);
+    let mut message = bulk_message(xml.into_bytes());
+
+    let mut results = Vec::new();
+    let count = message.parse_stream(
+        None,
+        "test_stream".to_string(),
+        "ISOIncoming".to_string(),
+        |transaction| {
+            results.push(transaction.is_ok());
+            Ok(())
+        }
+    ).expect("Failed to stream message");
+
+    assert_eq!(count, 3);
+    assert_eq!(results, vec![true, false, true]);
+    assert!(message.audit()[1].changes()[0].reason().contains("1 failed validation"));
+}
+
+#[test]
+fn test_stream_single_transaction_message() {
+    let xml_bytes = fs::read("examples/pacs008_001_07_cct_outgoing.xml")
+        .expect("Failed to read test XML file");
+    let mut message = bulk_message(xml_bytes);
+
+    let mut streamed = Vec::new();
+    message.parse_stream(None, "test_stream".to_string(), "ISOIncoming".to_string(), |transaction| {
+        streamed.push(transaction?);
+        Ok(())
+    }).expect("Failed to stream message");
+
+    message.parse(None, "test_stream".to_string(), "ISOIncoming".to_string())
+        .expect("Failed to parse message");
+    assert_eq!(streamed, vec![message.data().clone()]);
+}
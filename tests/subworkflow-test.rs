@@ -0,0 +1,107 @@
+use std::sync::Arc;
+use serde_json::{json, Value};
+use core_data::models::executor::*;
+use core_data::models::library::*;
+use core_data::models::logic::*;
+use core_data::models::message::*;
+use core_data::models::registry::*;
+use core_data::models::workflow::*;
+
+mod common;
+use common::{parsed_pacs008, task};
+
+fn workflow(name: &str, tasks: Value) -> Workflow {
+    Workflow::from_value(json!({
+        "name": name, "description": "", "version": 1, "tags": [], "status": "Active", "condition": false, "tasks": tasks
+    })).expect("Invalid workflow")
+}
+
+#[test]
+fn test_library_references_resolve() {
+    let library = TaskLibrary::from_file("examples/library/tasks.yaml").expect("Failed to load task library");
+    assert_eq!(library.len(), 3);
+    assert_eq!(library.get("check_identifiers").unwrap().task_id, "check_identifiers");
+
+    let workflows = Workflow::from_dir_with("examples/library/workflows", &library).expect("Failed to load workflows");
+    let common = &workflows[0];
+    let ids: Vec<&str> = common.tasks.iter().map(|t| t.task_id.as_str()).collect();
+    assert_eq!(ids, vec!["validate_schema", "check_identifiers", "stamp_channel"]);
+    let route = &workflows[1].tasks[1];
+    assert_eq!((route.task_id.as_str(), route.name.as_str()), ("route", "Route"));
+    assert_eq!(route.input[0]["field"], "data.metadata.route");
+    assert!(workflows[1].validate().is_ok());
+
+    let error = Workflow::from_dir("examples/library/workflows").expect_err("References need a library");
+    assert!(error.message.contains("common_checks.yaml"));
+    let error = library.resolve(json!({"tasks": [task("fix", "Publish", json!({}))], "on_failure": {"type": "repair", "tasks": [{"use": "missing"}]}}))
+        .expect_err("Unknown library task");
+    assert_eq!(error.message, "on_failure.tasks[0].use: unknown library task missing");
+
+    let foreach = task("each", "Foreach", json!({"path": "data.items", "tasks": [{"use": "stamp_channel"}, {"use": "missing"}]}));
+    let error = library.resolve(json!({"tasks": [foreach.clone()]})).expect_err("Unknown library task");
+    assert_eq!(error.message, "tasks[0].input.tasks[1].use: unknown library task missing");
+    let mut foreach = foreach;
+    foreach["input"]["tasks"].as_array_mut().unwrap().pop();
+    let resolved = library.resolve(json!({"tasks": [foreach]})).expect("Element tasks should resolve");
+    assert_eq!(resolved["tasks"][0]["input"]["tasks"][0]["task_id"], "stamp_channel");
+}
+
+#[test]
+fn test_sub_workflow_runs_nested() {
+    let library = TaskLibrary::from_file("examples/library/tasks.yaml").unwrap();
+    let registry = Arc::new(WorkflowRegistry::from_dir_with("examples/library/workflows", &library).expect("Failed to load registry"));
+    let mut message = parsed_pacs008("test_subworkflow");
+    let workflow = registry.select(&mut message, None).expect("Workflow should be selected");
+    assert_eq!(workflow.workflow().name, "incoming_pacs008");
+
+    let report = WorkflowExecutor::new().with_registry(registry.clone())
+        .run(workflow, &mut message, &json!({"channel": "ACH"}))
+        .expect("Workflow should run");
+    assert_eq!(report.executed, vec!["checks", "route"]);
+    assert_eq!(message.data()["metadata"]["channel"], "SWIFT");
+    assert_eq!(message.data()["metadata"]["route"], "SEPA-SWIFT");
+    assert_eq!(message.progress().prev_task, "route");
+
+    let entries: Vec<(&str, &str)> = message.audit().iter().skip(3).map(|a| (a.workflow(), a.task())).collect();
+    assert_eq!(entries, vec![
+        ("incoming_pacs008", "checks"),
+        ("incoming_pacs008/common_checks", "validate_schema"),
+        ("incoming_pacs008/common_checks", "check_identifiers"),
+        ("incoming_pacs008/common_checks", "stamp_channel"),
+        ("incoming_pacs008", "checks"),
+        ("incoming_pacs008", "route"),
+    ]);
+    assert_eq!(message.audit()[3].description(), "Started sub-workflow common_checks version 1");
+    assert_eq!(message.audit()[3].changes()[0].new_value(), Some(&json!({"channel": "SWIFT"})));
+    assert_eq!(message.audit()[7].description(), "Completed sub-workflow common_checks version 1");
+}
+
+#[test]
+fn test_sub_workflow_failure_rolls_back() {
+    let mut registry = WorkflowRegistry::new();
+    registry.register(workflow("stamp_and_publish", json!([
+        task("stamp", "Enrich", json!([{"field": "data.metadata.stamped", "rule": true, "description": null}])),
+        task("publish", "Publish", json!({"topic": "payments"})),
+    ]))).unwrap();
+    registry.register(workflow("looping", json!([task("again", "SubWorkflow", json!({"workflow": "looping"}))]))).unwrap();
+    let registry = Arc::new(registry);
+    let executor = WorkflowExecutor::new().with_registry(registry.clone());
+
+    let caller = CompiledWorkflow::new(
+        workflow("caller", json!([task("call", "SubWorkflow", json!({"workflow": "stamp_and_publish", "version": 1}))])),
+        RuleEvaluator::shared()
+    ).unwrap();
+    let mut message = parsed_pacs008("test_subworkflow");
+    let error = executor.run(&caller, &mut message, &json!({})).expect_err("Sub-workflow should fail");
+    assert_eq!(error.message, "No publisher configured");
+    assert!(message.data().get("metadata").is_none());
+    assert_eq!(message.progress().status, MessageStatus::Failed);
+    let failed = message.audit().last().unwrap();
+    assert_eq!((failed.workflow(), failed.description()), ("caller", "Sub-workflow stamp_and_publish version 1 failed"));
+    assert_eq!(failed.changes()[0].reason(), "Publish: No publisher configured, changes rolled back");
+
+    let error = executor.run(registry.get("looping", 1).unwrap(), &mut parsed_pacs008("test_subworkflow"), &json!({})).expect_err("Recursion should fail");
+    assert_eq!(error.message, "Workflow looping version 1 is already running");
+    let error = WorkflowExecutor::new().run(&caller, &mut parsed_pacs008("test_subworkflow"), &json!({})).expect_err("Registry required");
+    assert_eq!(error.message, "No workflow registry configured");
+}
@@ -0,0 +1,138 @@
+use serde_json::{json, Value};
+use core_data::models::auditlog::AuditLog;
+use core_data::models::executor::*;
+use core_data::models::logic::*;
+use core_data::models::message::*;
+use core_data::models::trace::*;
+use core_data::models::workflow::*;
+
+mod common;
+use common::parsed_pacs008;
+
+fn enrichment(config: Value) -> CompiledEnrichment {
+    CompiledEnrichment::new(RuleEvaluator::shared(), serde_json::from_value(config).unwrap()).unwrap()
+}
+
+#[test]
+fn test_enrichment_rules_traced() {
+    let enrichment = enrichment(json!([
+        {
+            "field": "data.metadata.reference",
+            "rule": {"cat": [{"var": "input.channel"}, "/", {"var": "data.document.FIToFICstmrCdtTrf.GrpHdr.MsgId"}]},
+            "condition": {"==": [{"var": "input.channel"}, "SWIFT"]},
+            "description": null
+        },
+        {"field": "data.metadata.total", "rule": {"reduce": [{"var": "input.amounts"}, {"+": [{"var": "current"}, {"var": "accumulator"}]}, 0]}, "description": null}
+    ]));
+    let input = json!({"channel": "SWIFT", "amounts": [1, 2]});
+    let mut message = parsed_pacs008("test_trace");
+    message.enrich_traced(&enrichment, input.clone(), None, "test_trace".to_string(), "Enrich".to_string(), Some(&TraceConfig::default())).unwrap();
+
+    let trace = message.audit().last().unwrap().trace().expect("Enrichment should be traced");
+    let rule_ids: Vec<&str> = trace.rules.iter().map(|r| r.rule_id.as_str()).collect();
+    assert_eq!(rule_ids, vec!["input[0].condition", "input[0].rule", "input[1].rule"]);
+    assert_eq!(trace.rules[0].inputs, vec![TraceInput { path: "input.channel".to_string(), value: json!("SWIFT") }]);
+    assert_eq!(trace.rules[0].result, Some(json!(true)));
+    assert_eq!(trace.rules[1].inputs[1].value, "VOLCUSTMSGID0001");
+    assert_eq!(trace.rules[1].result, Some(json!("SWIFT/VOLCUSTMSGID0001")));
+    let paths: Vec<&str> = trace.rules[2].inputs.iter().map(|i| i.path.as_str()).collect();
+    assert_eq!(paths, vec!["input.amounts"]);
+    assert_eq!(trace.omitted, 0);
+
+    let saved: AuditLog = serde_json::from_str(&serde_json::to_string(message.audit().last().unwrap()).unwrap()).unwrap();
+    assert_eq!(saved.trace(), Some(trace));
+
+    message.enrich_compiled(&enrichment, input, None, "test_trace".to_string(), "Enrich".to_string()).unwrap();
+    assert!(message.audit().last().unwrap().trace().is_none());
+}
+
+#[test]
+fn test_executor_traces_selected_tenants() {
+    let workflow = Workflow::from_value(json!({
+        "name": "incoming", "description": "", "version": 1, "tags": [], "status": "Active", "condition": true,
+        "tasks": [{
+            "task_id": "urgent", "name": "Urgent", "description": "", "function": "Enrich",
+            "condition": {"==": [{"var": "data.document.FIToFICstmrCdtTrf.GrpHdr.SttlmInf.SttlmMtd"}, "INDA"]},
+            "input": [{"field": "data.metadata.urgent", "rule": true, "description": null}]
+        }]
+    })).unwrap();
+    let compiled = CompiledWorkflow::new(workflow, RuleEvaluator::shared()).unwrap();
+
+    let traced = WorkflowExecutor::new().with_trace(TraceConfig { tenants: vec!["banking".to_string()], ..Default::default() });
+    let mut message = parsed_pacs008("test_trace");
+    traced.run(&compiled, &mut message, &json!({})).unwrap();
+    let entry = message.audit().iter().find(|a| a.description().starts_with("Condition of task urgent")).expect("Condition should be audited");
+    let trace = entry.trace().unwrap();
+    assert_eq!(trace.rules[0].rule_id, "condition");
+    assert_eq!(trace.rules[0].inputs[0].path, "data.document.FIToFICstmrCdtTrf.GrpHdr.SttlmInf.SttlmMtd");
+    let met = trace.rules[0].result == Some(json!(true));
+    assert_eq!(entry.description(), if met { "Condition of task urgent met" } else { "Condition of task urgent not met" });
+    assert_eq!(message.data()["metadata"].get("urgent").is_some(), met);
+
+    let untraced = WorkflowExecutor::new().with_trace(TraceConfig { tenants: vec!["retail".to_string()], ..Default::default() });
+    let mut message = parsed_pacs008("test_trace");
+    untraced.run(&compiled, &mut message, &json!({})).unwrap();
+    assert!(message.audit().iter().all(|a| a.trace().is_none() && !a.description().starts_with("Condition of task")));
+}
+
+#[test]
+fn test_trace_size_cap() {
+    let rule = |path: &str| json!({"field": format!("data.metadata.{}", path), "rule": {"var": "data.document.FIToFICstmrCdtTrf"}, "description": null});
+    let enrichment = enrichment(json!([rule("first"), rule("second"), rule("third")]));
+    let mut message = parsed_pacs008("test_trace");
+    let config = TraceConfig { tenants: vec![], max_bytes: 400 };
+    message.enrich_traced(&enrichment, json!({}), None, "test_trace".to_string(), "Enrich".to_string(), Some(&config)).unwrap();
+
+    let trace = message.audit().last().unwrap().trace().unwrap();
+    assert!(!trace.rules.is_empty());
+    assert!(trace.omitted > 0);
+    assert_eq!(trace.rules.len() + trace.omitted, 3);
+    let value = trace.rules[0].inputs[0].value.as_str().unwrap();
+    assert!(value.starts_with('<') && value.ends_with(" bytes omitted>"));
+    assert!(serde_json::to_string(&trace.rules).unwrap().len() <= 400);
+    assert!(message.data()["metadata"]["third"].is_object());
+
+    // The cap covers every trace of the message, however many tasks evaluate conditions
+    let tasks: Vec<Value> = (0..5).map(|i| json!({
+        "task_id": format!("task{}", i), "name": "", "description": "", "function": "Enrich",
+        "condition": {"!!": {"var": "data.document.FIToFICstmrCdtTrf.GrpHdr"}},
+        "input": [{"field": format!("data.metadata.task{}", i), "rule": true, "description": null}]
+    })).collect();
+    let workflow = Workflow::from_value(json!({
+        "name": "incoming", "description": "", "version": 1, "tags": [], "status": "Active", "condition": true, "tasks": tasks
+    })).unwrap();
+    let compiled = CompiledWorkflow::new(workflow, RuleEvaluator::shared()).unwrap();
+    let mut message = parsed_pacs008("test_trace");
+    WorkflowExecutor::new().with_trace(config).run(&compiled, &mut message, &json!({})).unwrap();
+    let traces: Vec<&EvaluationTrace> = message.audit().iter().filter_map(|a| a.trace()).collect();
+    let rules: Vec<&RuleTrace> = traces.iter().flat_map(|t| &t.rules).collect();
+    assert!(serde_json::to_string(&rules).unwrap().len() <= 400);
+    assert_eq!(rules.len() + traces.iter().map(|t| t.omitted).sum::<usize>(), 10);
+}
+
+#[test]
+fn test_trace_cap_across_parallel_tasks() {
+    // Two tasks of one level run in parallel, each tracing three rules against the same budget
+    let task = |task_id: &str| {
+        let input: Vec<Value> = ["first", "second", "third"].iter().map(|field| json!({
+            "field": format!("data.metadata.{}_{}", task_id, field), "rule": {"var": "data.document.FIToFICstmrCdtTrf.GrpHdr"}, "description": null
+        })).collect();
+        json!({"task_id": task_id, "name": "", "description": "", "function": "Enrich", "condition": true, "depends_on": [], "input": input})
+    };
+    let workflow = Workflow::from_value(json!({
+        "name": "incoming", "description": "", "version": 1, "tags": [], "status": "Active", "condition": true,
+        "tasks": [task("left"), task("right")]
+    })).unwrap();
+    let compiled = CompiledWorkflow::new(workflow, RuleEvaluator::shared()).unwrap();
+    let mut message = parsed_pacs008("test_trace");
+    let config = TraceConfig { tenants: vec![], max_bytes: 600 };
+    WorkflowExecutor::new().with_trace(config).run(&compiled, &mut message, &json!({})).unwrap();
+
+    assert!(message.data()["metadata"]["left_third"].is_object() && message.data()["metadata"]["right_third"].is_object());
+    let traces: Vec<&EvaluationTrace> = message.audit().iter().filter_map(|a| a.trace()).collect();
+    let rules: Vec<&RuleTrace> = traces.iter().flat_map(|t| &t.rules).collect();
+    assert!(serde_json::to_string(&rules).unwrap().len() <= 600);
+    assert!(traces.iter().any(|t| t.omitted > 0));
+    // Both conditions and the three rules of each task
+    assert_eq!(rules.len() + traces.iter().map(|t| t.omitted).sum::<usize>(), 8);
+}
@@ -0,0 +1,152 @@
+use std::fs;
+use core_data::models::message::*;
+use core_data::models::payload::*;
+use core_data::models::transform::*;
+
+fn parsed_pain001() -> Message {
+    let xml_bytes = fs::read("examples/pain001_001_09_bulk.xml")
+        .expect("Failed to read test XML file");
+    parsed(xml_bytes)
+}
+
+/// The bulk pain.001 with its amounts and currencies replaced.
+fn parsed_pain001_in(currency: &str, amounts: [&str; 3]) -> Message {
+    let xml = fs::read_to_string("examples/pain001_001_09_bulk.xml")
+        .expect("Failed to read test XML file");
+    let xml = ["1500.00", "250.75", "98000.00"].iter().zip(amounts)
+        .fold(xml, |xml, (from, to)| xml.replace(&format!("Ccy=\"EUR\">{}", from), &format!("Ccy=\"{}\">{}", currency, to)));
+    parsed(xml.into_bytes())
+}
+
+fn parsed(xml_bytes: Vec<u8>) -> Message {
+    let payload = Payload::new_inline(
+        Some(xml_bytes),
+        PayloadFormat::Xml,
+        PayloadSchema::ISO20022,
+        Encoding::Utf8
+    );
+
+    let mut message = Message::new(
+        payload,
+        "banking".to_string(),
+        "pain.001.001.09".to_string(),
+        "test_transform".to_string(),
+        "ISOIncoming".to_string(),
+        Some("initiation".to_string())
+    );
+    message.parse(None, "test_transform".to_string(), "ISOIncoming".to_string())
+        .expect("Failed to parse message");
+    message
+}
+
+fn config(bulking: Bulking) -> Pacs008Config {
+    Pacs008Config {
+        instructing_agent: "COBADEFFXXX".to_string(),
+        instructed_agent: "DEUTDEFFXXX".to_string(),
+        settlement_method: "CLRG".to_string(),
+        clearing_system: Some("TGT".to_string()),
+        settlement_date: None,
+        charge_bearer: None,
+        bulking,
+    }
+}
+
+#[test]
+fn test_pain001_to_pacs008_per_instruction() {
+    let mut message = parsed_pain001();
+
+    let children = message.to_pacs008(
+        &config(Bulking::Instruction),
+        None,
+        "test_transform".to_string(),
+        "ToPacs008".to_string(),
+    ).expect("Failed to transform message");
+
+    assert_eq!(children.len(), 2);
+    assert_eq!(message.audit().len(), 3);
+    assert_eq!(message.audit()[2].changes().len(), 2);
+
+    let first = &children[0];
+    assert_eq!(first.parent_id(), &Some(message.id().to_string()));
+    let transfer = &first.data()["document"]["FIToFICstmrCdtTrf"];
+    assert_eq!(transfer["GrpHdr"]["NbOfTxs"], "2");
+    assert_eq!(transfer["GrpHdr"]["TtlIntrBkSttlmAmt"]["$value"], 1750.75);
+    assert_eq!(transfer["GrpHdr"]["IntrBkSttlmDt"], "2024-11-15");
+    assert_eq!(transfer["GrpHdr"]["SttlmInf"]["ClrSys"]["Cd"], "TGT");
+    assert_eq!(transfer["CdtTrfTxInf"][1]["PmtId"]["EndToEndId"], "E2E-0002");
+    assert_eq!(transfer["CdtTrfTxInf"][1]["Dbtr"]["Nm"], "Acme Corporation");
+    assert_eq!(transfer["CdtTrfTxInf"][1]["ChrgBr"], "SLEV");
+    assert_eq!(transfer["CdtTrfTxInf"][1]["IntrBkSttlmAmt"]["@Ccy"], "EUR");
+    assert!(first.document().is_ok());
+
+    let audit = &first.audit()[1];
+    assert!(audit.changes().iter().any(|c| {
+        c.field() == "data.document.FIToFICstmrCdtTrf.CdtTrfTxInf.1.Cdtr"
+            && c.reason() == "Mapped from CstmrCdtTrfInitn.PmtInf.0.CdtTrfTxInf.1.Cdtr"
+    }));
+    assert!(audit.changes().iter().any(|c| c.field() == "data.document.FIToFICstmrCdtTrf.GrpHdr.MsgId"));
+}
+
+#[test]
+fn test_pain001_to_pacs008_per_transaction() {
+    let mut message = parsed_pain001();
+
+    let children = message.to_pacs008(
+        &config(Bulking::Transaction),
+        None,
+        "test_transform".to_string(),
+        "ToPacs008".to_string(),
+    ).expect("Failed to transform message");
+
+    assert_eq!(children.len(), 3);
+    for child in &children {
+        assert_eq!(child.data()["document"]["FIToFICstmrCdtTrf"]["CdtTrfTxInf"].as_array().unwrap().len(), 1);
+    }
+    assert_eq!(children[2].data()["document"]["FIToFICstmrCdtTrf"]["GrpHdr"]["IntrBkSttlmDt"], "2024-11-16");
+}
+
+#[test]
+fn test_pacs008_transform_requires_pain001() {
+    let xml_bytes = fs::read("examples/pacs008_001_07_cct_outgoing.xml")
+        .expect("Failed to read test XML file");
+    let payload = Payload::new_inline(Some(xml_bytes), PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+    let mut message = Message::new(payload, "banking".to_string(), "pacs.008.001.07".to_string(), "test_transform".to_string(), "ISOIncoming".to_string(), None);
+    message.parse(None, "test_transform".to_string(), "ISOIncoming".to_string())
+        .expect("Failed to parse message");
+
+    let result = message.to_pacs008(&config(Bulking::Message), None, "test_transform".to_string(), "ToPacs008".to_string());
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_pacs008_totals_in_currency_minor_units() {
+    let mut message = parsed_pain001_in("KWD", ["0.105", "0.2", "1.001"]);
+    let children = message.to_pacs008(&config(Bulking::Message), None, "test_transform".to_string(), "ToPacs008".to_string())
+        .expect("Failed to transform message");
+    let header = &children[0].data()["document"]["FIToFICstmrCdtTrf"]["GrpHdr"];
+    assert_eq!(header["TtlIntrBkSttlmAmt"]["$value"], 1.306);
+    assert_eq!(header["TtlIntrBkSttlmAmt"]["@Ccy"], "KWD");
+
+    let mut message = parsed_pain001_in("JPY", ["1500", "250", "98000"]);
+    let children = message.to_pacs008(&config(Bulking::Message), None, "test_transform".to_string(), "ToPacs008".to_string())
+        .expect("Failed to transform message");
+    assert_eq!(children[0].data()["document"]["FIToFICstmrCdtTrf"]["GrpHdr"]["TtlIntrBkSttlmAmt"]["$value"], 99750);
+
+    let mut message = parsed_pain001_in("JPY", ["1500", "250.75", "98000"]);
+    let error = message.to_pacs008(&config(Bulking::Message), None, "test_transform".to_string(), "ToPacs008".to_string())
+        .expect_err("JPY has no minor units");
+    assert!(error.message.ends_with("has more decimals than JPY allows"));
+}
+
+#[test]
+fn test_pacs008_settlement_date_from_execution_date_time() {
+    let xml = fs::read_to_string("examples/pain001_001_09_bulk.xml")
+        .expect("Failed to read test XML file")
+        .replace("<Dt>2024-11-15</Dt>", "<DtTm>2024-11-15T09:30:00</DtTm>");
+    let mut message = parsed(xml.into_bytes());
+    let children = message.to_pacs008(&config(Bulking::Transaction), None, "test_transform".to_string(), "ToPacs008".to_string())
+        .expect("Failed to transform message");
+    let transfer = &children[0].data()["document"]["FIToFICstmrCdtTrf"];
+    assert_eq!(transfer["CdtTrfTxInf"][0]["IntrBkSttlmDt"], "2024-11-15");
+    assert!(children[0].audit()[1].changes().iter().any(|c| c.reason() == "Mapped from CstmrCdtTrfInitn.PmtInf.0.ReqdExctnDt.DtTm"));
+}
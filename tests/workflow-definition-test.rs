@@ -0,0 +1,86 @@
+use serde_json::json;
+use core_data::models::task::*;
+use core_data::models::workflow::*;
+
+#[test]
+fn test_load_workflow_definitions() {
+    let workflows = Workflow::from_dir("examples/workflows").expect("Failed to load workflows");
+    assert_eq!(workflows.len(), 2);
+    assert_eq!(workflows[0].name, "incoming_pacs008");
+    assert_eq!(workflows[1].name, "outgoing_pain001");
+    assert_eq!(workflows[1].status, WorkflowStatus::Draft);
+    assert_eq!(workflows[1].tasks[1].function, FunctionType::Publish);
+    assert_eq!(workflows[1].tasks[1].condition, json!({"!!": {"var": "data.metadata.charge_bearer"}}));
+    for workflow in &workflows {
+        assert_eq!(workflow.validate(), Ok(()));
+        assert_eq!(&Workflow::from_json(&workflow.to_json().unwrap()).unwrap(), workflow);
+        assert_eq!(&Workflow::from_yaml(&workflow.to_yaml().unwrap()).unwrap(), workflow);
+    }
+}
+
+#[test]
+fn test_definition_parse_errors_have_locations() {
+    let error = Workflow::from_json(r#"{"name": "w", "description": "", "version": 1, "tags": [], "status": "Live", "tasks": [], "condition": true}"#)
+        .expect_err("Unknown status should fail");
+    assert!(error.message.starts_with("Invalid JSON workflow: status: unknown variant `Live`"));
+
+    let error = Workflow::from_yaml("name: w\ndescription: ''\nversion: 1\ntags: []\nstatus: Active\ncondition: true\ntasks:\n  - task_id: a\n    name: a\n    description: ''\n    condition: true\n    function: Transform\n    input: {}\n")
+        .expect_err("Unknown function should fail");
+    assert!(error.message.starts_with("Invalid YAML workflow: tasks[0].function: unknown variant `Transform`"));
+
+    assert!(Workflow::from_file("examples/workflows/missing.json").is_err());
+    assert!(Workflow::from_file("examples/pacs008_001_07_cct_outgoing.xml").is_err());
+}
+
+#[test]
+fn test_validate_reports_every_error() {
+    let task = |task_id: &str, condition, function, input| Task {
+        task_id: task_id.to_string(),
+        name: task_id.to_string(),
+        description: String::new(),
+        condition,
+        function,
+        input,
+        depends_on: None,
+        retry: None,
+        on_failure: None,
+        compensation: None,
+    };
+    let workflow = Workflow {
+        name: "broken".to_string(),
+        description: String::new(),
+        version: 1,
+        tags: vec![],
+        status: WorkflowStatus::Draft,
+        on_failure: None,
+        condition: json!({"==": [1]}),
+        tasks: vec![
+            task("check", json!(true), FunctionType::Validate, json!({"profile": " "})),
+            task("check", json!({"nope": []}), FunctionType::Enrich, json!([
+                {"field": "data.a", "rule": {"var": "input.a"}, "description": null},
+                {"field": "data.b", "rule": {"upper": []}, "description": null, "condition": {"if": [{"!": []}]}}
+            ])),
+            task("", json!(true), FunctionType::Enrich, json!({"field": "data.a"})),
+            task("publish", json!(true), FunctionType::Publish, json!("topic")),
+            task("validate", json!(true), FunctionType::Validate, json!({"identifiers": true, "schema": true})),
+        ],
+    };
+
+    let errors = workflow.validate().expect_err("Definition errors expected");
+    let locations: Vec<&str> = errors.iter().map(|e| e.location.as_str()).collect();
+    assert_eq!(locations, vec![
+        "condition",
+        "tasks[0].input.profile",
+        "tasks[1].task_id",
+        "tasks[1].condition",
+        "tasks[1].input[1].condition",
+        "tasks[2].task_id",
+        "tasks[2].input",
+        "tasks[3].input",
+        "tasks[4].input",
+    ]);
+    assert_eq!(errors[1].message, "is empty");
+    assert_eq!(errors[2].message, "duplicate task id check");
+    assert_eq!(errors[4].message, "Operator ! called with 0 arguments");
+    assert!(errors[8].message.contains("unknown field `schema`"));
+}
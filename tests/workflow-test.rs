@@ -15,6 +15,10 @@ mod tests {
             condition: json!({"condition": "value"}),
             function: FunctionType::Validate,
             input: json!({"input": "value"}),
+            depends_on: None,
+            retry: None,
+            on_failure: None,
+            compensation: None,
         };
         let workflow = Workflow {
             name: String::from("Workflow 1"),
@@ -22,6 +26,7 @@ mod tests {
             version: 1,
             tags: vec![String::from("tag1"), String::from("tag2")],
             status: WorkflowStatus::Active,
+            on_failure: None,
             tasks: vec![task.clone()],
             condition: json!({"condition": "value"}),
         };
@@ -42,6 +47,7 @@ mod tests {
             version: 0,
             tags: vec![],
             status: WorkflowStatus::Draft,
+            on_failure: None,
             tasks: vec![],
             condition: json!({"condition": "value"}),
         };
@@ -59,6 +65,10 @@ mod tests {
             condition: json!({"condition": "value"}),
             function: FunctionType::Validate,
             input: json!({"input": "value"}),
+            depends_on: None,
+            retry: None,
+            on_failure: None,
+            compensation: None,
         };
         let task2 = Task {
             task_id: String::from("task_2"),
@@ -67,6 +77,10 @@ mod tests {
             condition: json!({"condition": "value"}),
             function: FunctionType::Enrich,
             input: json!({"input": "value"}),
+            depends_on: None,
+            retry: None,
+            on_failure: None,
+            compensation: None,
         };
         let workflow = Workflow {
             name: String::from("Workflow with Multiple Tasks"),
@@ -74,6 +88,7 @@ mod tests {
             version: 0,
             tags: vec![],
             status: WorkflowStatus::Draft,
+            on_failure: None,
             tasks: vec![task1.clone(), task2.clone()],
             condition: json!({"condition": "value"}),
         };